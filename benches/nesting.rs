@@ -0,0 +1,73 @@
+//! Time parsing and interpreting deeply nested hexprs, and large powers.
+//!
+//! Run with `cargo bench --bench nesting`.
+use std::time::Instant;
+
+use hexpr::{try_interpret, Hexpr, Operation, Signature};
+
+/// Every operation is `f : 1 -> 1`
+struct Endo;
+
+impl Signature for Endo {
+    type Arr = String;
+    type Obj = ();
+    type Error = std::convert::Infallible;
+
+    fn try_parse_op(&self, op: &Operation) -> Result<Self::Arr, Self::Error> {
+        Ok(op.as_str().to_string())
+    }
+
+    fn profile(&self, _op: &Self::Arr) -> (Vec<Option<Self::Obj>>, Vec<Option<Self::Obj>>) {
+        (vec![None], vec![None])
+    }
+}
+
+/// `depth` brackets around an identity, cycling through compositions, tensors, scopes and
+/// powers
+fn nested(depth: usize) -> String {
+    let open = ["(f ", "{", "(let [x] ", "("];
+    let close = [")", "}", ")", " ^ 1)"];
+    let mut source: String = (0..depth).map(|i| open[i % 4]).collect();
+    source.push_str("[x]");
+    source.extend((0..depth).rev().map(|i| close[i % 4]));
+    source
+}
+
+fn main() {
+    for depth in [1_000, 10_000, 100_000] {
+        let source = nested(depth);
+
+        let start = Instant::now();
+        let hexpr: Hexpr = source.parse().unwrap();
+        let parsed = start.elapsed();
+
+        let start = Instant::now();
+        try_interpret(&Endo, &hexpr).unwrap();
+        let interpreted = start.elapsed();
+        hexpr.drop_iteratively();
+
+        println!(
+            "depth {:>7}: parse {:>10.2?}  interpret {:>10.2?}",
+            depth, parsed, interpreted
+        );
+    }
+
+    // A power should take about as long as the tensor it stands for
+    for count in [10_000, 100_000] {
+        let power: Hexpr = format!("{{f * {}}}", count).parse().unwrap();
+        let flat: Hexpr = format!("{{{}}}", "f ".repeat(count)).parse().unwrap();
+
+        let start = Instant::now();
+        try_interpret(&Endo, &power).unwrap();
+        let powered = start.elapsed();
+
+        let start = Instant::now();
+        try_interpret(&Endo, &flat).unwrap();
+        let tensored = start.elapsed();
+
+        println!(
+            "count {:>7}: power {:>10.2?}  tensor {:>10.2?}",
+            count, powered, tensored
+        );
+    }
+}
@@ -1,18 +1,30 @@
-use hexpr::{parse_hexprs, Hexpr};
+use hexpr::diagnostic::RenderOptions;
+use hexpr::{parse_hexprs, Diagnostic, Hexpr, PrettyOptions};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Parse a single hexpr
     let expr: Hexpr = "({copy [y]} {[x0] ([x1 y . y x1] imp)} imp)".parse()?;
     println!("{:?}", expr);
 
+    // Print it back across lines
+    let options = PrettyOptions {
+        width: 30,
+        ..PrettyOptions::default()
+    };
+    println!("{}", expr.pretty(&options));
+
     // Parse many hexprs
     let expr: Vec<Hexpr> = parse_hexprs("(foo)(bar)")?;
     println!("{:?}", expr);
 
-    let err: Result<Hexpr, _> = "{x (a b {c ) y}".parse();
+    let source = "{x (a b {c ) y}";
+    let err: Result<Hexpr, _> = source.parse();
     match err {
         Ok(x) => println!("{}", x),
-        Err(e) => eprintln!("{}", e),
+        Err(e) => eprint!(
+            "{}",
+            Diagnostic::from(e).render(source, &RenderOptions::default())
+        ),
     }
     Ok(())
 }
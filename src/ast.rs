@@ -1,50 +1,1041 @@
 //! # H-Expression abstract syntax tree
 
 use crate::parser::{HExprParser, Rule};
+use crate::span::Span;
 use pest::Parser;
 
-#[derive(Debug, Clone, PartialEq)]
+/// An H-expression.
+///
+/// Every node carries the [`Span`] of source text it was parsed from.
+/// Spans are ignored when comparing expressions for equality.
+#[derive(Debug)]
 pub enum Hexpr {
     /// Sequential (categorical) composition of hexprs
-    Composition(Vec<Hexpr>),
+    Composition(Vec<Hexpr>, Span),
     /// Parallel (tensor) composition of hexprs
-    Tensor(Vec<Hexpr>),
+    Tensor(Vec<Hexpr>, Span),
     /// A Frobenius spider
     Frobenius {
         sources: Vec<Variable>,
         targets: Vec<Variable>,
+        /// Whether this was written as the shorthand `[x y]`, an identity with the same
+        /// `sources` and `targets`. Unlike `[x y . x y]`, it shares anonymous variables between
+        /// the two, so `[_]` is an identity while `[_ . _]` is two unconnected wires.
+        identity: bool,
+        span: Span,
     },
     /// A named operation
     Operation(Operation),
+    /// A constant, e.g. `3.14` or `"hello"`
+    Literal(Literal),
+    /// `(let [x y] body...)`: the composition of `body` in a scope where `x` and `y` are fresh
+    /// variables, shadowing any outer variables of the same name
+    Scope {
+        variables: Vec<Variable>,
+        body: Vec<Hexpr>,
+        span: Span,
+    },
+    /// `{neg * 8}` or `(f ^ 4)`: copies of a hexpr in parallel or in sequence.
+    /// The variables of each copy are local to it.
+    Power {
+        hexpr: Box<Hexpr>,
+        kind: PowerKind,
+        count: usize,
+        span: Span,
+    },
+    /// `(f g : ℝ ℝ -> ℝ)`: a hexpr together with the interface it is expected to have
+    Ascription {
+        hexpr: Box<Hexpr>,
+        sources: Ports,
+        targets: Ports,
+        span: Span,
+    },
+    /// Placeholder for source text that failed to parse, produced by
+    /// [`parse_hexprs_recovering`](crate::parser::parse_hexprs_recovering)
+    Error(Span),
+}
+
+impl Hexpr {
+    /// A composition with no source location
+    pub fn composition(hexprs: Vec<Hexpr>) -> Self {
+        Hexpr::Composition(hexprs, Span::default())
+    }
+
+    /// A tensor with no source location
+    pub fn tensor(hexprs: Vec<Hexpr>) -> Self {
+        Hexpr::Tensor(hexprs, Span::default())
+    }
+
+    /// A Frobenius spider with no source location
+    pub fn frobenius(sources: Vec<Variable>, targets: Vec<Variable>) -> Self {
+        Hexpr::Frobenius {
+            sources,
+            targets,
+            identity: false,
+            span: Span::default(),
+        }
+    }
+
+    /// The identity shorthand `[x y]`, with no source location
+    pub fn identity(variables: Vec<Variable>) -> Self {
+        Hexpr::Frobenius {
+            sources: variables.clone(),
+            targets: variables,
+            identity: true,
+            span: Span::default(),
+        }
+    }
+
+    /// A scope with no source location
+    pub fn scope(variables: Vec<Variable>, body: Vec<Hexpr>) -> Self {
+        Hexpr::Scope {
+            variables,
+            body,
+            span: Span::default(),
+        }
+    }
+
+    /// `count` copies of `hexpr`, with no source location
+    pub fn power(hexpr: Hexpr, kind: PowerKind, count: usize) -> Self {
+        Hexpr::Power {
+            hexpr: Box::new(hexpr),
+            kind,
+            count,
+            span: Span::default(),
+        }
+    }
+
+    /// `hexpr` ascribed the interface `sources -> targets`, with no source location
+    pub fn ascription(hexpr: Hexpr, sources: Ports, targets: Ports) -> Self {
+        Hexpr::Ascription {
+            hexpr: Box::new(hexpr),
+            sources,
+            targets,
+            span: Span::default(),
+        }
+    }
+
+    /// The source location of this expression
+    pub fn span(&self) -> Span {
+        match self {
+            Hexpr::Composition(_, span) | Hexpr::Tensor(_, span) => *span,
+            Hexpr::Frobenius { span, .. } | Hexpr::Scope { span, .. } => *span,
+            Hexpr::Power { span, .. } | Hexpr::Ascription { span, .. } => *span,
+            Hexpr::Operation(op) => op.span,
+            Hexpr::Literal(literal) => literal.span,
+            Hexpr::Error(span) => *span,
+        }
+    }
+
+    /// The hexprs directly inside this one, in order
+    pub fn children(&self) -> &[Hexpr] {
+        match self {
+            Hexpr::Composition(hexprs, _) | Hexpr::Tensor(hexprs, _) => hexprs,
+            Hexpr::Scope { body, .. } => body,
+            Hexpr::Power { hexpr, .. } | Hexpr::Ascription { hexpr, .. } => {
+                std::slice::from_ref(hexpr)
+            }
+            Hexpr::Frobenius { .. } | Hexpr::Operation(_) | Hexpr::Literal(_) | Hexpr::Error(_) => {
+                &[]
+            }
+        }
+    }
+
+    /// A copy of this hexpr with `children` in place of the hexprs directly inside it
+    pub(crate) fn with_children(&self, mut children: Vec<Hexpr>) -> Hexpr {
+        match self {
+            Hexpr::Composition(_, span) => Hexpr::Composition(children, *span),
+            Hexpr::Tensor(_, span) => Hexpr::Tensor(children, *span),
+            Hexpr::Scope {
+                variables, span, ..
+            } => Hexpr::Scope {
+                variables: variables.clone(),
+                body: children,
+                span: *span,
+            },
+            Hexpr::Power {
+                kind, count, span, ..
+            } => Hexpr::Power {
+                hexpr: Box::new(children.pop().unwrap()),
+                kind: *kind,
+                count: *count,
+                span: *span,
+            },
+            Hexpr::Ascription {
+                sources,
+                targets,
+                span,
+                ..
+            } => Hexpr::Ascription {
+                hexpr: Box::new(children.pop().unwrap()),
+                sources: sources.clone(),
+                targets: targets.clone(),
+                span: *span,
+            },
+            Hexpr::Frobenius {
+                sources,
+                targets,
+                identity,
+                span,
+            } => Hexpr::Frobenius {
+                sources: sources.clone(),
+                targets: targets.clone(),
+                identity: *identity,
+                span: *span,
+            },
+            Hexpr::Operation(op) => Hexpr::Operation(op.clone()),
+            Hexpr::Literal(literal) => Hexpr::Literal(literal.clone()),
+            Hexpr::Error(span) => Hexpr::Error(*span),
+        }
+    }
+
+    /// Rebuild this hexpr from the bottom up with an explicit stack rather than recursively.
+    ///
+    /// `f` is given each hexpr together with the rebuilt hexprs directly inside it, and returns
+    /// its replacement.
+    pub(crate) fn rebuild(&self, mut f: impl FnMut(&Hexpr, Vec<Hexpr>) -> Hexpr) -> Hexpr {
+        // Each hexpr is visited once on the way down, and again once its children are rebuilt
+        let mut stack = vec![(self, false)];
+        let mut rebuilt: Vec<Hexpr> = vec![];
+        while let Some((hexpr, visited)) = stack.pop() {
+            let children = hexpr.children();
+            if visited || children.is_empty() {
+                let children = rebuilt.split_off(rebuilt.len() - children.len());
+                rebuilt.push(f(hexpr, children));
+            } else {
+                stack.push((hexpr, true));
+                stack.extend(children.iter().rev().map(|child| (child, false)));
+            }
+        }
+        rebuilt.pop().unwrap()
+    }
+
+    /// Drop this hexpr with an explicit stack rather than recursively.
+    ///
+    /// Dropping a hexpr as usual recurses once per level of nesting, so deeply nested hexprs,
+    /// such as generated ones, should be dropped with this to avoid overflowing the call stack.
+    pub fn drop_iteratively(self) {
+        let mut stack = vec![self];
+        while let Some(mut hexpr) = stack.pop() {
+            take_children(&mut hexpr, &mut stack);
+        }
+    }
+}
+
+/// Move the hexprs directly inside `hexpr` onto `stack`
+fn take_children(hexpr: &mut Hexpr, stack: &mut Vec<Hexpr>) {
+    match hexpr {
+        Hexpr::Composition(hexprs, _) | Hexpr::Tensor(hexprs, _) => stack.append(hexprs),
+        Hexpr::Scope { body, .. } => stack.append(body),
+        Hexpr::Power { hexpr, .. } | Hexpr::Ascription { hexpr, .. } => {
+            let span = hexpr.span();
+            stack.push(std::mem::replace(hexpr, Hexpr::Error(span)));
+        }
+        Hexpr::Frobenius { .. } | Hexpr::Operation(_) | Hexpr::Literal(_) | Hexpr::Error(_) => (),
+    }
 }
 
-/// Operation names, must match [a-zA-Z0-9-_.*+-/|]+
-#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
-pub struct Operation(pub(crate) String);
+// Cloning and comparing don't recurse, so that deeply nested hexprs don't overflow the call stack
+impl Clone for Hexpr {
+    fn clone(&self) -> Self {
+        self.rebuild(|hexpr, children| hexpr.with_children(children))
+    }
+}
+
+impl PartialEq for Hexpr {
+    fn eq(&self, other: &Self) -> bool {
+        let mut stack = vec![(self, other)];
+        while let Some((a, b)) = stack.pop() {
+            if !a.eq_outside(b) || a.children().len() != b.children().len() {
+                return false;
+            }
+            stack.extend(a.children().iter().zip(b.children()));
+        }
+        true
+    }
+}
+
+impl Hexpr {
+    /// Whether two hexprs are equal, apart from the hexprs directly inside them
+    fn eq_outside(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Hexpr::Composition(..), Hexpr::Composition(..)) => true,
+            (Hexpr::Tensor(..), Hexpr::Tensor(..)) => true,
+            (
+                Hexpr::Frobenius {
+                    sources: s1,
+                    targets: t1,
+                    identity: i1,
+                    ..
+                },
+                Hexpr::Frobenius {
+                    sources: s2,
+                    targets: t2,
+                    identity: i2,
+                    ..
+                },
+            ) => i1 == i2 && same_wires(s1, s2) && same_wires(t1, t2),
+            (Hexpr::Operation(a), Hexpr::Operation(b)) => a == b,
+            (Hexpr::Literal(a), Hexpr::Literal(b)) => a == b,
+            (Hexpr::Scope { variables: v1, .. }, Hexpr::Scope { variables: v2, .. }) => v1 == v2,
+            (
+                Hexpr::Power {
+                    kind: k1,
+                    count: n1,
+                    ..
+                },
+                Hexpr::Power {
+                    kind: k2,
+                    count: n2,
+                    ..
+                },
+            ) => k1 == k2 && n1 == n2,
+            (
+                Hexpr::Ascription {
+                    sources: s1,
+                    targets: t1,
+                    ..
+                },
+                Hexpr::Ascription {
+                    sources: s2,
+                    targets: t2,
+                    ..
+                },
+            ) => s1 == s2 && t1 == t2,
+            (Hexpr::Error(_), Hexpr::Error(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Variables are equal by name, but occurrences in brackets must also have the same annotations
+fn same_wires(xs: &[Variable], ys: &[Variable]) -> bool {
+    xs.len() == ys.len()
+        && xs
+            .iter()
+            .zip(ys)
+            .all(|(x, y)| x == y && x.annotation == y.annotation)
+}
+
+/// How the copies in a [`Hexpr::Power`] are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PowerKind {
+    /// `{f * n}`
+    Tensor,
+    /// `(f ^ n)`
+    Composition,
+}
+
+/// One side of an interface [`Hexpr::Ascription`]: either the number of wires, e.g. `2`, or
+/// their types, e.g. `ℝ ℝ`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ports {
+    Arity(usize),
+    Objects(Vec<Object>),
+}
+
+impl Ports {
+    /// The number of wires
+    pub fn len(&self) -> usize {
+        match self {
+            Ports::Arity(n) => *n,
+            Ports::Objects(objects) => objects.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The type of each wire, if given
+    pub fn objects(&self) -> Option<&[Object]> {
+        match self {
+            Ports::Arity(_) => None,
+            Ports::Objects(objects) => Some(objects),
+        }
+    }
+}
+
+impl std::fmt::Display for Ports {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Ports::Arity(n) => write!(f, "{}", n),
+            Ports::Objects(objects) => {
+                for (i, obj) in objects.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", obj)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Operations: a name such as `add`, `σ` or `⊕` made of Unicode identifier characters,
+/// mathematical symbols and `-.*+/|>:=!?`, with optional parameters given
+/// positionally, `reshape<2,3>`, or by name, `conv2d{kernel=3 stride=2}`.
+///
+/// Any other name can be written in single quotes, like `'my op (v2)'` or `'it\'s'`, and is
+/// printed quoted so that it parses back to the same operation.
+///
+/// Compared, ordered and hashed by name and parameters.
+#[derive(Debug, Clone)]
+pub struct Operation {
+    pub(crate) name: String,
+    pub(crate) params: Vec<Param>,
+    pub(crate) span: Span,
+}
 
 impl Operation {
+    /// An operation with any name and no parameters, e.g. a node name from another system
+    pub fn new(name: impl Into<String>) -> Self {
+        Operation {
+            name: name.into(),
+            params: vec![],
+            span: Span::default(),
+        }
+    }
+
+    /// The name of the operation, without its parameters
+    pub fn as_str(&self) -> &str {
+        &self.name
+    }
+
+    /// All parameters, positional ones first
+    pub fn params(&self) -> &[Param] {
+        &self.params
+    }
+
+    /// The values of the parameters given in `<...>`
+    pub fn positional(&self) -> impl Iterator<Item = &Value> {
+        self.params
+            .iter()
+            .filter(|param| param.name.is_none())
+            .map(|param| &param.value)
+    }
+
+    /// The value of the parameter `name`, given in `{...}`
+    pub fn param(&self, name: &str) -> Option<&Value> {
+        self.params
+            .iter()
+            .find(|param| param.name.as_deref() == Some(name))
+            .map(|param| &param.value)
+    }
+
+    /// This operation with another parameter
+    pub fn with_param(mut self, param: Param) -> Self {
+        self.params.push(param);
+        self
+    }
+
+    /// Where this occurrence of the operation appears in the source
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// Parse the text of an `operation` rule, building any hexpr parameters with `hexpr`
+    pub(crate) fn from_pair(
+        pair: pest::iterators::Pair<Rule>,
+        hexpr: &mut dyn FnMut(pest::iterators::Pair<Rule>) -> Hexpr,
+    ) -> Self {
+        debug_assert_eq!(pair.as_rule(), Rule::operation);
+        let span = pair_span(&pair);
+        let mut inner = pair.into_inner();
+        let name = operation_name(inner.next().unwrap());
+        let params = inner
+            .flat_map(|params| params.into_inner())
+            .map(|param| match param.as_rule() {
+                Rule::value => Param {
+                    name: None,
+                    value: Value::from_pair(param, hexpr),
+                },
+                Rule::named_param => {
+                    let mut inner = param.into_inner();
+                    Param {
+                        name: Some(inner.next().unwrap().as_str().to_string()),
+                        value: Value::from_pair(inner.next().unwrap(), hexpr),
+                    }
+                }
+                x => panic!("unreachable: {:?}", x),
+            })
+            .collect();
+        Operation { name, params, span }
+    }
+}
+
+impl PartialEq for Operation {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.params == other.params
+    }
+}
+
+impl Eq for Operation {}
+
+impl std::hash::Hash for Operation {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        for param in &self.params {
+            param.name.hash(state);
+            param.value.hash_consistently(state);
+        }
+    }
+}
+
+impl PartialOrd for Operation {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Operation {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        let params = self.params.iter().zip(&other.params).map(|(a, b)| {
+            a.name
+                .cmp(&b.name)
+                .then_with(|| a.value.cmp_consistently(&b.value))
+        });
+        self.name.cmp(&other.name).then_with(|| {
+            params
+                .chain([self.params.len().cmp(&other.params.len())])
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+    }
+}
+
+/// A parameter of an [`Operation`], named if it was given in `{...}`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: Option<String>,
+    pub value: Value,
+}
+
+impl std::fmt::Display for Param {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{}={}", name, self.value),
+            None => write!(f, "{}", self.value),
+        }
+    }
+}
+
+/// The value of an operation parameter
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Integer(i128),
+    /// A finite float: numbers too large for an `f64`, like `1e999`, don't parse
+    Float(f64),
+    /// A quoted string, e.g. `"same"`
+    String(String),
+    /// An unquoted word, e.g. `f32`
+    Symbol(String),
+    /// A list in square brackets, e.g. `[1,2]`
+    List(Vec<Value>),
+    /// A hexpr in brackets, e.g. `(f g)`, passed to a template
+    Hexpr(Box<Hexpr>),
+}
+
+impl Value {
+    pub fn as_integer(&self) -> Option<i128> {
+        match self {
+            Value::Integer(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// The contents of a string or symbol
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) | Value::Symbol(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Value]> {
+        match self {
+            Value::List(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// Hash consistently with `==`, under which `-0.0` and `0.0` are equal
+    fn hash_consistently<H: std::hash::Hasher>(&self, state: &mut H) {
+        use std::hash::Hash;
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Value::Integer(n) => n.hash(state),
+            Value::Float(x) => (x + 0.0).to_bits().hash(state),
+            Value::String(s) | Value::Symbol(s) => s.hash(state),
+            // Equal hexprs print the same
+            Value::Hexpr(hexpr) => hexpr.to_string().hash(state),
+            Value::List(values) => {
+                values.len().hash(state);
+                for value in values {
+                    value.hash_consistently(state);
+                }
+            }
+        }
+    }
+
+    /// A total order consistent with `==`, for ordering [`Operation`]s
+    fn cmp_consistently(&self, other: &Value) -> std::cmp::Ordering {
+        let rank = |value: &Value| match value {
+            Value::Integer(_) => 0,
+            Value::Float(_) => 1,
+            Value::String(_) => 2,
+            Value::Symbol(_) => 3,
+            Value::List(_) => 4,
+            Value::Hexpr(_) => 5,
+        };
+        match (self, other) {
+            (Value::Integer(a), Value::Integer(b)) => a.cmp(b),
+            // Adding zero turns `-0.0` into `0.0`
+            (Value::Float(a), Value::Float(b)) => (a + 0.0).total_cmp(&(b + 0.0)),
+            (Value::String(a), Value::String(b)) | (Value::Symbol(a), Value::Symbol(b)) => a.cmp(b),
+            (Value::List(a), Value::List(b)) => a
+                .iter()
+                .zip(b)
+                .map(|(a, b)| a.cmp_consistently(b))
+                .chain([a.len().cmp(&b.len())])
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(std::cmp::Ordering::Equal),
+            (Value::Hexpr(a), Value::Hexpr(b)) => a.to_string().cmp(&b.to_string()),
+            _ => rank(self).cmp(&rank(other)),
+        }
+    }
+
+    fn from_pair(
+        pair: pest::iterators::Pair<Rule>,
+        hexpr: &mut dyn FnMut(pest::iterators::Pair<Rule>) -> Hexpr,
+    ) -> Self {
+        debug_assert_eq!(pair.as_rule(), Rule::value);
+        let inner = pair.into_inner().next().unwrap();
+        match inner.as_rule() {
+            Rule::list => Value::List(
+                inner
+                    .into_inner()
+                    .map(|value| Value::from_pair(value, hexpr))
+                    .collect(),
+            ),
+            Rule::param_hexpr => Value::Hexpr(Box::new(hexpr(inner))),
+            Rule::string => {
+                let text = inner.as_str();
+                Value::String(unescape(&text[1..text.len() - 1]))
+            }
+            Rule::param_number => match number_value(inner) {
+                LiteralValue::Integer(n) => Value::Integer(n),
+                LiteralValue::Float(x) => Value::Float(x),
+                LiteralValue::String(_) => unreachable!(),
+            },
+            Rule::symbol => Value::Symbol(inner.as_str().to_string()),
+            x => panic!("unreachable: {:?}", x),
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Integer(n) => write!(f, "{}", n),
+            // Debug formatting always includes a `.` or exponent, so reparses as a float
+            Value::Float(x) => write!(f, "{:?}", x),
+            Value::String(s) => write!(f, "\"{}\"", escape(s, '"')),
+            Value::Symbol(s) => write!(f, "{}", s),
+            Value::Hexpr(hexpr) => match **hexpr {
+                Hexpr::Composition(..)
+                | Hexpr::Tensor(..)
+                | Hexpr::Scope { .. }
+                | Hexpr::Power { .. }
+                | Hexpr::Ascription { .. } => write!(f, "{}", hexpr),
+                // Only bracketed hexprs parse as values
+                _ => write!(f, "({})", hexpr),
+            },
+            Value::List(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+/// A numeric or string constant, e.g. `3.14`, `-2`, `0x1f` or `"hello"`.
+///
+/// Compared and hashed by its source text, so `0x1f` and `31` are different literals.
+#[derive(Debug, Clone)]
+pub struct Literal {
+    pub(crate) text: String,
+    pub(crate) value: LiteralValue,
+    pub(crate) span: Span,
+}
+
+/// The value of a [`Literal`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum LiteralValue {
+    /// A decimal or hexadecimal integer
+    Integer(i128),
+    /// A number with a fractional part or exponent, or an integer too large for an `i128`.
+    /// Always finite: numbers too large for an `f64`, like `1e999`, don't parse.
+    Float(f64),
+    /// A string, with escapes like `\"` and `\n` resolved
+    String(String),
+}
+
+impl Literal {
+    /// The literal as written in the source, e.g. `"a\tb"` including quotes
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    pub fn value(&self) -> &LiteralValue {
+        &self.value
+    }
+
+    /// Where this occurrence of the literal appears in the source
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// An operation named by this literal's source text, e.g. `0` for the literal `0`
+    pub fn as_operation(&self) -> Operation {
+        Operation {
+            name: self.text.clone(),
+            params: vec![],
+            span: self.span,
+        }
+    }
+
+    /// Parse the text of a `literal` rule
+    pub(crate) fn from_pair(pair: pest::iterators::Pair<Rule>) -> Self {
+        debug_assert_eq!(pair.as_rule(), Rule::literal);
+        let text = pair.as_str().to_string();
+        let span = pair_span(&pair);
+        let inner = pair.into_inner().next().unwrap();
+        let value = match inner.as_rule() {
+            Rule::string => LiteralValue::String(unescape(&text[1..text.len() - 1])),
+            Rule::number => number_value(inner),
+            x => panic!("unreachable: {:?}", x),
+        };
+        Literal { text, value, span }
+    }
+}
+
+/// The value of a `number` or `param_number` rule
+fn number_value(pair: pest::iterators::Pair<Rule>) -> LiteralValue {
+    let text = pair.as_str();
+    let digits = pair.into_inner().next().unwrap();
+    let value = match digits.as_rule() {
+        Rule::integer => digits.as_str().parse().ok(),
+        Rule::hex_integer => i128::from_str_radix(&digits.as_str()[2..], 16).ok(),
+        _ => None,
+    };
+    match value {
+        Some(n) if text.starts_with('-') => LiteralValue::Integer(-n),
+        Some(n) => LiteralValue::Integer(n),
+        None => LiteralValue::Float(parse_float(text)),
+    }
+}
+
+/// The first number in `pair` too large to be a finite `f64`, like `1e999`, which has no
+/// syntax to print it back as
+pub(crate) fn infinite_number<'i>(
+    pair: &pest::iterators::Pair<'i, Rule>,
+) -> Option<pest::Span<'i>> {
+    std::iter::once(pair.clone())
+        .chain(pair.clone().into_inner().flatten())
+        .filter(|pair| matches!(pair.as_rule(), Rule::number | Rule::param_number))
+        .find(
+            |pair| matches!(number_value(pair.clone()), LiteralValue::Float(x) if x.is_infinite()),
+        )
+        .map(|pair| pair.as_span())
+}
+
+/// Fail if `pair` contains a number too large to be a finite `f64`
+fn check_finite(
+    pair: pest::iterators::Pair<'_, Rule>,
+) -> Result<pest::iterators::Pair<'_, Rule>, Box<pest::error::Error<Rule>>> {
+    match infinite_number(&pair) {
+        Some(span) => {
+            let message = "number is too large".to_string();
+            let variant = pest::error::ErrorVariant::CustomError { message };
+            Err(Box::new(pest::error::Error::new_from_span(variant, span)))
+        }
+        None => Ok(pair),
+    }
+}
+
+/// The name given by an `operation_name` rule, with any quotes and escapes resolved
+pub(crate) fn operation_name(pair: pest::iterators::Pair<Rule>) -> String {
+    debug_assert_eq!(pair.as_rule(), Rule::operation_name);
+    let inner = pair.into_inner().next().unwrap();
+    match inner.as_rule() {
+        Rule::quoted_name => {
+            let text = inner.as_str();
+            unescape(&text[1..text.len() - 1])
+        }
+        _ => inner.as_str().to_string(),
+    }
+}
+
+/// Whether `name` must be quoted to parse back as the same operation: names outside the plain
+/// operation syntax, names which would parse as literals, the keywords `let` and `def`, and
+/// `:` and `*`, which can start an ascription or a power
+fn needs_quotes(name: &str) -> bool {
+    parse_one(Rule::one_plain_name, name).is_err()
+        || parse_one(Rule::one_literal, name).is_ok()
+        || matches!(name, "let" | "def" | ":" | "*")
+}
+
+/// Escape a string for printing between `quote`s, the inverse of [`unescape`]
+fn escape(s: &str, quote: char) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            c if c == quote => {
+                result.push('\\');
+                result.push(c);
+            }
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\t' => result.push_str("\\t"),
+            '\r' => result.push_str("\\r"),
+            '\0' => result.push_str("\\0"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Resolve the escapes in the contents of a string literal
+fn unescape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some('0') => result.push('\0'),
+            // `\"`, `\\` and any other escaped character stand for themselves
+            Some(c) => result.push(c),
+            None => (),
+        }
+    }
+    result
+}
+
+/// Parse a float, or a hexadecimal integer too large for an `i128`
+fn parse_float(text: &str) -> f64 {
+    let (sign, digits) = match text.strip_prefix('-') {
+        Some(digits) => (-1.0, digits),
+        None => (1.0, text),
+    };
+    let value = match digits.strip_prefix("0x") {
+        Some(hex) => hex
+            .chars()
+            .fold(0.0, |n, c| n * 16.0 + c.to_digit(16).unwrap() as f64),
+        None => digits.parse().unwrap(),
+    };
+    sign * value
+}
+
+impl PartialEq for Literal {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text
+    }
+}
+
+impl Eq for Literal {}
+
+impl std::hash::Hash for Literal {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.text.hash(state)
+    }
+}
+
+impl std::str::FromStr for Literal {
+    type Err = Box<pest::error::Error<Rule>>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Literal::from_pair(check_finite(parse_one(
+            Rule::one_literal,
+            s,
+        )?)?))
+    }
+}
+
+impl std::fmt::Display for Literal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// Variable names in a Frobenius expression: Unicode identifiers like `x` or `α`, which may also
+/// contain `-`.
+///
+/// Compared and hashed by name only, so every occurrence of a name refers to the same variable.
+/// The exception is the anonymous variable `_`, each occurrence of which is a distinct wire.
+#[derive(Debug, Clone)]
+pub struct Variable {
+    pub(crate) name: String,
+    pub(crate) span: Span,
+    /// The wire type given at this occurrence, e.g. the `ℝ` in `[x:ℝ]`
+    pub(crate) annotation: Option<Object>,
+}
+
+impl Variable {
+    /// The anonymous variable `_`
+    pub fn anonymous() -> Self {
+        Variable {
+            name: "_".to_string(),
+            span: Span::default(),
+            annotation: None,
+        }
+    }
+
     pub fn as_str(&self) -> &str {
-        &self.0
+        &self.name
+    }
+
+    /// The type annotation of this occurrence, if any
+    pub fn annotation(&self) -> Option<&Object> {
+        self.annotation.as_ref()
+    }
+
+    /// This variable occurrence with a type annotation
+    pub fn with_annotation(self, annotation: Object) -> Self {
+        Variable {
+            annotation: Some(annotation),
+            ..self
+        }
+    }
+
+    /// Is this the anonymous variable `_`?
+    pub fn is_anonymous(&self) -> bool {
+        self.name == "_"
+    }
+
+    /// Where this occurrence of the variable appears in the source
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl PartialEq for Variable {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
     }
 }
 
-/// Variable names in a Frobenius expression. Must match `[a-zA-Z0-9-_]+`.
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct Variable(pub(crate) String);
+impl Eq for Variable {}
+
+impl std::hash::Hash for Variable {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.name.hash(state)
+    }
+}
 
 impl std::str::FromStr for Variable {
     type Err = Box<pest::error::Error<Rule>>;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let pairs = HExprParser::parse(Rule::variable, s)?;
-        let variable_pair = pairs.into_iter().next().unwrap();
-        Ok(Variable(variable_pair.as_str().to_string()))
+        let wire = parse_one(Rule::one_variable, s)?;
+        let mut inner = wire.into_inner();
+        let variable = inner.next().unwrap();
+        Ok(Variable {
+            name: variable.as_str().to_string(),
+            span: pair_span(&variable),
+            annotation: inner.next().map(|object| Object {
+                name: object.as_str().to_string(),
+                span: pair_span(&object),
+            }),
+        })
     }
 }
 
 impl std::fmt::Display for Variable {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+        write!(f, "{}", self.name)?;
+        if let Some(annotation) = &self.annotation {
+            write!(f, ":{}", annotation)?;
+        }
+        Ok(())
+    }
+}
+
+/// Object (wire type) names in annotations, e.g. `ℝ` in `[x:ℝ]`.
+/// May contain any characters except whitespace, brackets, `.`, `:`, `,`, `#` and the arrow `->`.
+///
+/// Compared, ordered and hashed by name only.
+#[derive(Debug, Clone)]
+pub struct Object {
+    pub(crate) name: String,
+    pub(crate) span: Span,
+}
+
+impl Object {
+    pub fn as_str(&self) -> &str {
+        &self.name
+    }
+
+    /// Where this occurrence of the object appears in the source
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl PartialEq for Object {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for Object {}
+
+impl std::hash::Hash for Object {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.name.hash(state)
+    }
+}
+
+impl PartialOrd for Object {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Object {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.name.cmp(&other.name)
+    }
+}
+
+impl std::str::FromStr for Object {
+    type Err = Box<pest::error::Error<Rule>>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let object_pair = parse_one(Rule::one_object, s)?;
+        Ok(Object {
+            name: object_pair.as_str().to_string(),
+            span: pair_span(&object_pair),
+        })
+    }
+}
+
+impl std::fmt::Display for Object {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
     }
 }
 
@@ -52,15 +1043,53 @@ impl std::str::FromStr for Operation {
     type Err = Box<pest::error::Error<Rule>>;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let pairs = HExprParser::parse(Rule::operation, s)?;
-        let operation_pair = pairs.into_iter().next().unwrap();
-        Ok(Operation(operation_pair.as_str().to_string()))
+        let pair = check_finite(parse_one(Rule::one_operation, s)?)?;
+        Ok(crate::parser::Builder::new(s).operation(pair))
     }
 }
 
 impl std::fmt::Display for Operation {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+        if needs_quotes(&self.name) {
+            write!(f, "'{}'", escape(&self.name, '\''))?;
+        } else {
+            write!(f, "{}", self.name)?;
+        }
+        let (named, positional): (Vec<&Param>, Vec<&Param>) =
+            self.params.iter().partition(|param| param.name.is_some());
+        for (i, param) in positional.iter().enumerate() {
+            write!(f, "{}{}", if i == 0 { "<" } else { "," }, param)?;
+        }
+        if !positional.is_empty() {
+            write!(f, ">")?;
+        }
+        for (i, param) in named.iter().enumerate() {
+            write!(f, "{}{}", if i == 0 { "{" } else { " " }, param)?;
+        }
+        if !named.is_empty() {
+            write!(f, "}}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse the whole of `s` with a `one_*` rule, returning the pair it wraps
+fn parse_one(
+    rule: Rule,
+    s: &str,
+) -> Result<pest::iterators::Pair<'_, Rule>, Box<pest::error::Error<Rule>>> {
+    let mut pairs = HExprParser::parse(rule, s)?;
+    Ok(pairs.next().unwrap().into_inner().next().unwrap())
+}
+
+fn pair_span(pair: &pest::iterators::Pair<Rule>) -> Span {
+    let span = pair.as_span();
+    let (line, column) = span.start_pos().line_col();
+    Span {
+        start: span.start(),
+        end: span.end(),
+        line,
+        column,
     }
 }
 
@@ -72,52 +1101,303 @@ impl std::str::FromStr for Hexpr {
     }
 }
 
+/// A step in printing a hexpr, so deeply nested hexprs print without recursion
+enum Print<'a> {
+    /// Print the hexpr up to its first child, and push the rest
+    Open(&'a Hexpr),
+    /// Print what follows the last child of the hexpr
+    Close(&'a Hexpr),
+    Space,
+}
+
+/// The brackets and hexprs written inside an ascription
+fn ascribed(hexpr: &Hexpr) -> (&'static str, &'static str, &[Hexpr]) {
+    match hexpr {
+        Hexpr::Composition(exprs, _) => ("(", ")", exprs.as_slice()),
+        Hexpr::Tensor(exprs, _) => ("{", "}", exprs.as_slice()),
+        other => ("(", ")", std::slice::from_ref(other)),
+    }
+}
+
 impl std::fmt::Display for Hexpr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Hexpr::Composition(exprs) => {
-                write!(f, "(")?;
-                for (i, expr) in exprs.iter().enumerate() {
-                    if i > 0 {
-                        write!(f, " ")?;
-                    }
-                    write!(f, "{}", expr)?;
+        let mut stack = vec![Print::Open(self)];
+        while let Some(print) = stack.pop() {
+            let hexpr = match print {
+                Print::Open(hexpr) => hexpr,
+                Print::Close(hexpr) => {
+                    close(hexpr, f)?;
+                    continue;
                 }
-                write!(f, ")")
-            }
-            Hexpr::Tensor(exprs) => {
-                write!(f, "{{")?;
-                for (i, expr) in exprs.iter().enumerate() {
-                    if i > 0 {
-                        write!(f, " ")?;
-                    }
-                    write!(f, "{}", expr)?;
+                Print::Space => {
+                    write!(f, " ")?;
+                    continue;
                 }
-                write!(f, "}}")
-            }
-            Hexpr::Frobenius { sources, targets } => {
-                // Special case for empty frobenius
-                if sources.is_empty() && targets.is_empty() {
-                    write!(f, "[]")
-                } else {
-                    write!(f, "[")?;
-                    for (i, var) in sources.iter().enumerate() {
+            };
+            stack.push(Print::Close(hexpr));
+            match hexpr {
+                Hexpr::Composition(exprs, _) | Hexpr::Tensor(exprs, _) => {
+                    let open = match hexpr {
+                        Hexpr::Composition(..) => "(",
+                        _ => "{",
+                    };
+                    write!(f, "{}", open)?;
+                    for (i, expr) in exprs.iter().enumerate().rev() {
+                        stack.push(Print::Open(expr));
                         if i > 0 {
-                            write!(f, " ")?;
+                            stack.push(Print::Space);
                         }
-                        write!(f, "{}", var)?;
                     }
-                    write!(f, " . ")?;
-                    for (i, var) in targets.iter().enumerate() {
+                }
+                Hexpr::Frobenius {
+                    sources,
+                    targets,
+                    identity,
+                    ..
+                } => {
+                    // Special case for empty frobenius
+                    if sources.is_empty() && targets.is_empty() {
+                        write!(f, "[]")?;
+                    } else if *identity {
+                        write!(f, "[")?;
+                        for (i, var) in sources.iter().enumerate() {
+                            if i > 0 {
+                                write!(f, " ")?;
+                            }
+                            write!(f, "{}", var)?;
+                        }
+                        write!(f, "]")?;
+                    } else {
+                        write!(f, "[")?;
+                        for (i, var) in sources.iter().enumerate() {
+                            if i > 0 {
+                                write!(f, " ")?;
+                            }
+                            write!(f, "{}", var)?;
+                        }
+                        write!(f, " . ")?;
+                        for (i, var) in targets.iter().enumerate() {
+                            if i > 0 {
+                                write!(f, " ")?;
+                            }
+                            write!(f, "{}", var)?;
+                        }
+                        write!(f, "]")?;
+                    }
+                }
+                Hexpr::Operation(name) => write!(f, "{}", name)?,
+                Hexpr::Literal(literal) => write!(f, "{}", literal)?,
+                Hexpr::Scope {
+                    variables, body, ..
+                } => {
+                    write!(f, "(let [")?;
+                    for (i, var) in variables.iter().enumerate() {
                         if i > 0 {
                             write!(f, " ")?;
                         }
                         write!(f, "{}", var)?;
                     }
-                    write!(f, "]")
+                    write!(f, "]")?;
+                    for expr in body.iter().rev() {
+                        stack.push(Print::Open(expr));
+                        stack.push(Print::Space);
+                    }
+                }
+                Hexpr::Power { hexpr, kind, .. } => {
+                    match kind {
+                        PowerKind::Tensor => write!(f, "{{")?,
+                        PowerKind::Composition => write!(f, "(")?,
+                    }
+                    stack.push(Print::Open(hexpr));
                 }
+                Hexpr::Ascription { hexpr, .. } => {
+                    // The ascription goes inside the brackets of a composition or tensor
+                    let (open, _, exprs) = ascribed(hexpr);
+                    write!(f, "{}", open)?;
+                    for expr in exprs.iter().rev() {
+                        stack.push(Print::Space);
+                        stack.push(Print::Open(expr));
+                    }
+                }
+                Hexpr::Error(_) => write!(f, "<error>")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Print what follows the hexprs inside `hexpr`
+fn close(hexpr: &Hexpr, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match hexpr {
+        Hexpr::Composition(..) | Hexpr::Scope { .. } => write!(f, ")"),
+        Hexpr::Tensor(..) => write!(f, "}}"),
+        Hexpr::Power { kind, count, .. } => match kind {
+            PowerKind::Tensor => write!(f, " * {}}}", count),
+            PowerKind::Composition => write!(f, " ^ {})", count),
+        },
+        Hexpr::Ascription {
+            hexpr,
+            sources,
+            targets,
+            ..
+        } => {
+            write!(f, ":")?;
+            if sources.objects() != Some(&[]) {
+                write!(f, " {}", sources)?;
+            }
+            write!(f, " ->")?;
+            if targets.objects() != Some(&[]) {
+                write!(f, " {}", targets)?;
             }
-            Hexpr::Operation(name) => write!(f, "{}", name),
+            write!(f, "{}", ascribed(hexpr).1)
+        }
+        Hexpr::Frobenius { .. } | Hexpr::Operation(_) | Hexpr::Literal(_) | Hexpr::Error(_) => {
+            Ok(())
+        }
+    }
+}
+
+/// A named hexpr, `(def name body)`.
+///
+/// Later items in a [`Program`] can use `name` as an operation.
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub name: Operation,
+    pub body: Hexpr,
+    pub span: Span,
+}
+
+impl PartialEq for Definition {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.body == other.body
+    }
+}
+
+impl std::fmt::Display for Definition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(def {} {})", self.name, self.body)
+    }
+}
+
+/// A definition parameterised by integers and operations, `(def (name params...) body)`.
+///
+/// Using it as `name<args...>` expands to a copy of `body` with each parameter replaced by its
+/// argument; see [`template::expand`](crate::template::expand).
+/// A template may have several definitions, with integer patterns for base cases:
+/// `(def (tree 1) ...)` and `(def (tree n) ... tree<n-1> ...)`.
+#[derive(Debug, Clone)]
+pub struct Template {
+    pub name: Operation,
+    pub params: Vec<Pattern>,
+    pub body: Hexpr,
+    pub span: Span,
+}
+
+impl PartialEq for Template {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.params == other.params && self.body == other.body
+    }
+}
+
+impl std::fmt::Display for Template {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(def ({}", self.name)?;
+        for param in &self.params {
+            write!(f, " {}", param)?;
+        }
+        write!(f, ") {})", self.body)
+    }
+}
+
+/// A template parameter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pattern {
+    /// Bound to any argument
+    Name(String),
+    /// Only matches an equal integer argument
+    Integer(i128),
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pattern::Name(name) => write!(f, "{}", name),
+            Pattern::Integer(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+/// A top-level item of a [`Program`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Item {
+    Definition(Definition),
+    Template(Template),
+    Hexpr(Hexpr),
+}
+
+impl std::fmt::Display for Item {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Item::Definition(definition) => write!(f, "{}", definition),
+            Item::Template(template) => write!(f, "{}", template),
+            Item::Hexpr(hexpr) => write!(f, "{}", hexpr),
+        }
+    }
+}
+
+/// A sequence of definitions and hexprs
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Program {
+    pub items: Vec<Item>,
+}
+
+impl Program {
+    /// Drop the hexprs of this program [iteratively](Hexpr::drop_iteratively)
+    pub fn drop_iteratively(self) {
+        for item in self.items {
+            match item {
+                Item::Definition(definition) => definition.body.drop_iteratively(),
+                Item::Template(template) => template.body.drop_iteratively(),
+                Item::Hexpr(hexpr) => hexpr.drop_iteratively(),
+            }
+        }
+    }
+
+    pub fn definitions(&self) -> impl Iterator<Item = &Definition> {
+        self.items.iter().filter_map(|item| match item {
+            Item::Definition(definition) => Some(definition),
+            _ => None,
+        })
+    }
+
+    pub fn templates(&self) -> impl Iterator<Item = &Template> {
+        self.items.iter().filter_map(|item| match item {
+            Item::Template(template) => Some(template),
+            _ => None,
+        })
+    }
+
+    pub fn hexprs(&self) -> impl Iterator<Item = &Hexpr> {
+        self.items.iter().filter_map(|item| match item {
+            Item::Hexpr(hexpr) => Some(hexpr),
+            _ => None,
+        })
+    }
+}
+
+impl std::str::FromStr for Program {
+    type Err = crate::parser::ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        crate::parser::HExprParser::parse_program(s)
+    }
+}
+
+impl std::fmt::Display for Program {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for item in &self.items {
+            writeln!(f, "{}", item)?;
         }
+        Ok(())
     }
 }
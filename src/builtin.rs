@@ -0,0 +1,123 @@
+//! Wiring combinators understood by the interpreter without any [`Signature`].
+//!
+//! Each builtin is shorthand for a Frobenius bracket, and is interpreted with the same sharing
+//! of nodes:
+//!
+//! | builtin        | equivalent bracket |
+//! |----------------|--------------------|
+//! | `swap`         | `[x y . y x]`      |
+//! | `id<2>`        | `[x y . x y]`      |
+//! | `perm<2 0 1>`  | `[x y z . z x y]`  |
+//! | `copy<3>`      | `[x . x x x]`      |
+//! | `drop<2>`      | `[x y . ]`         |
+//!
+//! Apart from `swap`, builtins must be given their parameters, so that a signature can still
+//! have operations named `id`, `copy` or `drop`. A definition of the same name takes precedence.
+//!
+//! [`Signature`]: crate::interpret::Signature
+use crate::ast::{Operation, Value};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Builtin {
+    Swap,
+    Id(usize),
+    /// Target `i` is source `perm[i]`
+    Perm(Vec<usize>),
+    Copy(usize),
+    Drop(usize),
+}
+
+impl Builtin {
+    /// Recognise a builtin. Returns `None` if `op` is not a builtin, and an error message if it
+    /// is one with invalid parameters.
+    pub fn parse(op: &Operation) -> Option<Result<Builtin, String>> {
+        let args: Vec<&Value> = op.positional().collect();
+        let has_params = !op.params().is_empty();
+        let builtin = match op.as_str() {
+            "swap" if has_params => Err("`swap` takes no parameters".to_string()),
+            "swap" => Ok(Builtin::Swap),
+            _ if !has_params => return None,
+            "id" => count(op, &args).map(Builtin::Id),
+            "copy" => count(op, &args).map(Builtin::Copy),
+            "drop" => count(op, &args).map(Builtin::Drop),
+            "perm" => permutation(op, &args).map(Builtin::Perm),
+            _ => return None,
+        };
+        Some(builtin)
+    }
+
+    /// The number of sources plus targets, without building the wiring
+    pub fn width(&self) -> usize {
+        match self {
+            Builtin::Swap => 4,
+            Builtin::Id(n) => n.saturating_mul(2),
+            Builtin::Perm(perm) => perm.len() * 2,
+            Builtin::Copy(n) => n.saturating_add(1),
+            Builtin::Drop(n) => *n,
+        }
+    }
+
+    /// The wiring of this builtin: how many nodes it has, and which of them are its sources
+    /// and targets
+    pub fn wiring(&self) -> (usize, Vec<usize>, Vec<usize>) {
+        match self {
+            Builtin::Swap => (2, vec![0, 1], vec![1, 0]),
+            Builtin::Id(n) => (*n, (0..*n).collect(), (0..*n).collect()),
+            Builtin::Perm(perm) => (perm.len(), (0..perm.len()).collect(), perm.clone()),
+            Builtin::Copy(n) => (1, vec![0], vec![0; *n]),
+            Builtin::Drop(n) => (*n, (0..*n).collect(), vec![]),
+        }
+    }
+}
+
+impl std::fmt::Display for Builtin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Builtin::Swap => write!(f, "swap"),
+            Builtin::Id(n) => write!(f, "id<{}>", n),
+            Builtin::Perm(perm) => {
+                write!(f, "perm<")?;
+                for (i, p) in perm.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", p)?;
+                }
+                write!(f, ">")
+            }
+            Builtin::Copy(n) => write!(f, "copy<{}>", n),
+            Builtin::Drop(n) => write!(f, "drop<{}>", n),
+        }
+    }
+}
+
+fn natural(value: &Value) -> Option<usize> {
+    value.as_integer().and_then(|n| usize::try_from(n).ok())
+}
+
+/// The single wire count parameter of `id`, `copy` and `drop`
+fn count(op: &Operation, args: &[&Value]) -> Result<usize, String> {
+    match (args, op.params().len()) {
+        ([n], 1) => natural(n).ok_or_else(|| format!("expected a number of wires, got `{}`", n)),
+        _ => Err(format!("expected `{}<n>`", op.as_str())),
+    }
+}
+
+/// The parameters of `perm`, which must list each of `0..n` exactly once
+fn permutation(op: &Operation, args: &[&Value]) -> Result<Vec<usize>, String> {
+    if args.len() != op.params().len() {
+        return Err("`perm` takes no named parameters".to_string());
+    }
+    let perm: Vec<usize> = args
+        .iter()
+        .map(|&arg| natural(arg).filter(|&i| i < args.len()))
+        .collect::<Option<_>>()
+        .ok_or_else(|| format!("expected a permutation of 0 to {}", args.len() - 1))?;
+    let mut seen = vec![false; perm.len()];
+    for &i in &perm {
+        if std::mem::replace(&mut seen[i], true) {
+            return Err(format!("`{}` appears twice", i));
+        }
+    }
+    Ok(perm)
+}
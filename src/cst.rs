@@ -0,0 +1,268 @@
+//! A lossless concrete syntax tree, for tools which edit source files.
+//!
+//! The [`Hexpr`] AST drops comments and whitespace. A [`SyntaxNode`] instead keeps every byte
+//! of its source: printing it gives back exactly the text it was parsed from. Tools can replace
+//! parts of the tree, e.g. with [`SyntaxNode::rewrite`], and print the result, leaving the rest
+//! of the file untouched.
+//!
+//! Each node corresponds to a rule of the grammar, and its children are the nodes of nested
+//! rules interleaved with [tokens](SyntaxToken): brackets and other punctuation, whitespace and
+//! comments. The AST is derived by parsing the printed tree, so it reflects any edits.
+//!
+//! Unlike [`parse_program`](crate::parse_program), the syntax tree is built by the recursive
+//! rules of the grammar, so it suits hand-written source rather than very deeply nested hexprs.
+use pest::iterators::Pair;
+use pest::Parser;
+
+use crate::ast::{Hexpr, Program};
+use crate::parser::{HExprParser, ParseError, Rule};
+
+/// A node of the tree, for one occurrence of a grammar rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxNode {
+    rule: Rule,
+    children: Vec<SyntaxElement>,
+}
+
+/// A child of a [`SyntaxNode`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+/// A piece of source text with no further structure
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxToken {
+    kind: TokenKind,
+    text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    /// Spaces, tabs and newlines
+    Whitespace,
+    /// A comment from `#` to the end of the line, without the newline
+    Comment,
+    /// Anything else: a name, number or string, or punctuation like `(` or `->`
+    Text,
+}
+
+/// Parse a program, keeping all of its text.
+///
+/// The result is a [`Rule::program`] node, which prints as exactly `input`.
+pub fn parse_program(input: &str) -> Result<SyntaxNode, ParseError> {
+    let program = HExprParser::parse(Rule::program, input)?.next().unwrap();
+    Ok(SyntaxNode::from_pair(program))
+}
+
+/// Parse a single hexpr, e.g. to insert into another tree.
+///
+/// The result is a [`Rule::hexpr`] node: whitespace and comments around it are not included.
+pub fn parse_hexpr(input: &str) -> Result<SyntaxNode, ParseError> {
+    let one_hexpr = HExprParser::parse(Rule::one_hexpr, input)?.next().unwrap();
+    let hexpr = one_hexpr.into_inner().next().unwrap();
+    Ok(SyntaxNode::from_pair(hexpr))
+}
+
+impl SyntaxNode {
+    pub fn new(rule: Rule, children: Vec<SyntaxElement>) -> Self {
+        SyntaxNode { rule, children }
+    }
+
+    /// The grammar rule this node is an occurrence of
+    pub fn rule(&self) -> Rule {
+        self.rule
+    }
+
+    pub fn children(&self) -> &[SyntaxElement] {
+        &self.children
+    }
+
+    pub fn children_mut(&mut self) -> &mut Vec<SyntaxElement> {
+        &mut self.children
+    }
+
+    /// The child nodes, skipping tokens
+    pub fn nodes(&self) -> impl Iterator<Item = &SyntaxNode> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+
+    /// This node and all nodes below it, in source order
+    pub fn descendants(&self) -> Vec<&SyntaxNode> {
+        let mut result = vec![self];
+        for node in self.nodes() {
+            result.extend(node.descendants());
+        }
+        result
+    }
+
+    /// All tokens below this node, in source order
+    pub fn tokens(&self) -> Vec<&SyntaxToken> {
+        let mut result = vec![];
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => result.extend(node.tokens()),
+                SyntaxElement::Token(token) => result.push(token),
+            }
+        }
+        result
+    }
+
+    /// Replace nodes for which `f` returns a new node, visiting this node and then its
+    /// descendants in source order. The replacements themselves are not visited.
+    pub fn rewrite(&mut self, f: &mut impl FnMut(&SyntaxNode) -> Option<SyntaxNode>) {
+        if let Some(node) = f(self) {
+            *self = node;
+            return;
+        }
+        for child in &mut self.children {
+            if let SyntaxElement::Node(node) = child {
+                node.rewrite(f);
+            }
+        }
+    }
+
+    /// The source text of this node
+    pub fn text(&self) -> String {
+        self.to_string()
+    }
+
+    /// Parse this node's text as a program
+    pub fn to_program(&self) -> Result<Program, ParseError> {
+        HExprParser::parse_program(&self.text())
+    }
+
+    /// Parse this node's text as a single hexpr
+    pub fn to_hexpr(&self) -> Result<Hexpr, ParseError> {
+        HExprParser::parse_hexpr(&self.text())
+    }
+
+    fn from_pair(pair: Pair<Rule>) -> Self {
+        let rule = pair.as_rule();
+        let text = pair.as_str();
+        let start = pair.as_span().start();
+        if is_atomic(rule) {
+            return SyntaxNode::new(
+                rule,
+                vec![SyntaxElement::Token(SyntaxToken::new(
+                    TokenKind::Text,
+                    text,
+                ))],
+            );
+        }
+
+        // Text between nested rules is punctuation, whitespace and comments
+        let mut children = vec![];
+        let mut offset = 0;
+        for inner in pair.into_inner() {
+            if inner.as_rule() == Rule::EOI {
+                continue;
+            }
+            let span = inner.as_span();
+            children.extend(tokenize(&text[offset..span.start() - start]));
+            offset = span.end() - start;
+            children.push(SyntaxElement::Node(SyntaxNode::from_pair(inner)));
+        }
+        children.extend(tokenize(&text[offset..]));
+        SyntaxNode::new(rule, children)
+    }
+}
+
+impl std::fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => write!(f, "{}", node)?,
+                SyntaxElement::Token(token) => write!(f, "{}", token)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SyntaxToken {
+    pub fn new(kind: TokenKind, text: impl Into<String>) -> Self {
+        SyntaxToken {
+            kind,
+            text: text.into(),
+        }
+    }
+
+    pub fn kind(&self) -> TokenKind {
+        self.kind
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Whether this token is whitespace or a comment
+    pub fn is_trivia(&self) -> bool {
+        self.kind != TokenKind::Text
+    }
+}
+
+impl std::fmt::Display for SyntaxToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// Rules whose text is a single token. Other rules containing no nested rules, like an empty
+/// binder `[ ]`, may still contain punctuation and whitespace.
+fn is_atomic(rule: Rule) -> bool {
+    matches!(
+        rule,
+        Rule::def_keyword
+            | Rule::let_keyword
+            | Rule::pattern
+            | Rule::times
+            | Rule::count
+            | Rule::ascription_colon
+            | Rule::arity
+            | Rule::object
+            | Rule::string
+            | Rule::hex_integer
+            | Rule::float
+            | Rule::exponent
+            | Rule::integer
+            | Rule::plain_name
+            | Rule::quoted_name
+            | Rule::param_name
+            | Rule::symbol
+            | Rule::variable
+            | Rule::unexpected
+            | Rule::unexpected_in_group
+    )
+}
+
+/// Split text outside any nested rule into tokens. Each bracket is its own token, and other
+/// punctuation runs up to the next bracket, whitespace or comment.
+fn tokenize(text: &str) -> Vec<SyntaxElement> {
+    let is_whitespace = |c: char| matches!(c, ' ' | '\t' | '\n' | '\r');
+    let is_bracket = |c: char| "()[]{}".contains(c);
+    let mut tokens = vec![];
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let (kind, len) = if is_whitespace(c) {
+            let len = rest.find(|c| !is_whitespace(c)).unwrap_or(rest.len());
+            (TokenKind::Whitespace, len)
+        } else if c == '#' {
+            (TokenKind::Comment, rest.find('\n').unwrap_or(rest.len()))
+        } else if is_bracket(c) {
+            (TokenKind::Text, c.len_utf8())
+        } else {
+            let len = rest
+                .find(|c| is_whitespace(c) || c == '#' || is_bracket(c))
+                .unwrap_or(rest.len());
+            (TokenKind::Text, len)
+        };
+        tokens.push(SyntaxElement::Token(SyntaxToken::new(kind, &rest[..len])));
+        rest = &rest[len..];
+    }
+    tokens
+}
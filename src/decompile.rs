@@ -0,0 +1,361 @@
+//! Convert open hypergraphs back to hexprs, so graphs built by other tools can be written in the
+//! textual format.
+//!
+//! [`Hexpr::from_open_hypergraph`] writes an open hypergraph as a hexpr which interprets to an
+//! isomorphic one, in either of two [`Style`]s. In the imperative style, every wire is named, and
+//! each operation is bound to the wires of its ports:
+//!
+//! ```text
+//! ([x0 x1 . ] {([ . x0 x1] add [x2 . ]) ([ . x0 x2] mul [x3 . ])} [ . x3])
+//! ```
+//!
+//! In the layered style, operations are composed in layers, with identities for wires which pass
+//! through a layer and spiders between layers which copy, join, discard or reorder wires. The
+//! variables of each spider are local to it:
+//!
+//! ```text
+//! ((let [x0 x1] [x0 x1 . x0 x0 x1]) {(let [x0] [x0 . x0]) add} mul)
+//! ```
+use open_hypergraphs::lax::{NodeId, OpenHypergraph};
+
+use crate::ast::{Hexpr, Object, Operation, Variable};
+use crate::layer::layers;
+use crate::span::Span;
+
+/// A way to write arrows and objects in a hexpr: the inverse of a
+/// [`Signature`](crate::interpret::Signature).
+pub trait Notation {
+    type Arr;
+    type Obj;
+
+    /// The operation to write an arrow as
+    fn operation(&self, arr: &Self::Arr) -> Operation;
+
+    /// The annotation for a wire of type `obj`. By default, wires aren't annotated.
+    fn object(&self, _obj: &Self::Obj) -> Option<Object> {
+        None
+    }
+}
+
+/// How to write a decompiled open hypergraph
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Style {
+    /// Name every wire, and bind the ports of each operation to them: `([ . x0] neg [x1 . ])`
+    #[default]
+    Imperative,
+    /// Compose layers of operations in parallel, joined by spiders with local variables
+    Layered,
+}
+
+impl Hexpr {
+    /// Write an open hypergraph as a hexpr, naming arrows and objects with `notation`.
+    ///
+    /// Interpreting the hexpr with a signature giving each operation the profile of its arrow
+    /// produces an isomorphic open hypergraph, except that nodes identified by the quotient are
+    /// one wire. Operations are only given types by the signature, so only wires which aren't
+    /// connected to any operation are annotated. An operation named like a builtin, such as
+    /// `swap`, is interpreted as the builtin.
+    pub fn from_open_hypergraph<N: Notation>(
+        graph: &OpenHypergraph<Option<N::Obj>, N::Arr>,
+        notation: &N,
+        style: Style,
+    ) -> Hexpr
+    where
+        N::Obj: Clone,
+        N::Arr: Clone,
+    {
+        let wires = Wires::new(graph, notation);
+        match style {
+            Style::Imperative => wires.imperative(),
+            Style::Layered => wires.layered(),
+        }
+    }
+}
+
+/// An open hypergraph with its nodes numbered by wire, in order of first appearance
+struct Wires {
+    sources: Vec<usize>,
+    targets: Vec<usize>,
+    /// The operation, inputs and outputs of each edge
+    edges: Vec<(Operation, Vec<usize>, Vec<usize>)>,
+    /// The number of wires connected to an edge or the interface; the rest are isolated
+    connected: usize,
+    /// The annotation of each wire, if it needs one
+    annotations: Vec<Option<Object>>,
+}
+
+impl Wires {
+    fn new<N: Notation>(graph: &OpenHypergraph<Option<N::Obj>, N::Arr>, notation: &N) -> Self
+    where
+        N::Obj: Clone,
+        N::Arr: Clone,
+    {
+        let hypergraph = &graph.hypergraph;
+        let coequalizer = hypergraph.coequalizer();
+        let class = &coequalizer.table.0;
+        let mut wire: Vec<Option<usize>> = vec![None; coequalizer.target];
+        let mut count = 0;
+        let mut number = |nodes: &[NodeId]| -> Vec<usize> {
+            nodes
+                .iter()
+                .map(|node| {
+                    *wire[class[node.0]].get_or_insert_with(|| {
+                        count += 1;
+                        count - 1
+                    })
+                })
+                .collect()
+        };
+
+        let sources = number(&graph.sources);
+        let edges: Vec<(Operation, Vec<usize>, Vec<usize>)> = hypergraph
+            .edges
+            .iter()
+            .zip(&hypergraph.adjacency)
+            .map(|(arr, edge)| {
+                let inputs = number(&edge.sources);
+                let outputs = number(&edge.targets);
+                (notation.operation(arr), inputs, outputs)
+            })
+            .collect();
+        let targets = number(&graph.targets);
+        let connected = count;
+        for w in wire.iter_mut().filter(|w| w.is_none()) {
+            *w = Some(count);
+            count += 1;
+        }
+
+        // Wires connected to an operation get their type from the signature
+        let mut operated = vec![false; count];
+        for (_, inputs, outputs) in &edges {
+            for &w in inputs.iter().chain(outputs) {
+                operated[w] = true;
+            }
+        }
+        let mut annotations = vec![None; count];
+        for (node, label) in hypergraph.nodes.iter().enumerate() {
+            let w = wire[class[node]].unwrap();
+            if let (false, None, Some(obj)) = (operated[w], &annotations[w], label) {
+                annotations[w] = notation.object(obj);
+            }
+        }
+
+        Wires {
+            sources,
+            targets,
+            edges,
+            connected,
+            annotations,
+        }
+    }
+
+    /// `([x0 . ] {([ . x0] f [x1 . ]) ...} [ . x1])`
+    fn imperative(&self) -> Hexpr {
+        let mut annotated = vec![false; self.annotations.len()];
+        let mut var = |w: usize| self.occurrence(format!("x{}", w), w, &mut annotated);
+
+        let mut items = vec![];
+        if !self.sources.is_empty() {
+            let sources = self.sources.iter().map(|&w| var(w)).collect();
+            items.push(Hexpr::frobenius(sources, vec![]));
+        }
+        let mut operations: Vec<Hexpr> = vec![];
+        for (op, inputs, outputs) in &self.edges {
+            let mut parts = vec![];
+            if !inputs.is_empty() {
+                parts.push(Hexpr::frobenius(
+                    vec![],
+                    inputs.iter().map(|&w| var(w)).collect(),
+                ));
+            }
+            parts.push(Hexpr::Operation(op.clone()));
+            if !outputs.is_empty() {
+                parts.push(Hexpr::frobenius(
+                    outputs.iter().map(|&w| var(w)).collect(),
+                    vec![],
+                ));
+            }
+            operations.push(composition(parts));
+        }
+        for w in self.connected..self.annotations.len() {
+            operations.push(Hexpr::composition(vec![
+                Hexpr::frobenius(vec![], vec![var(w)]),
+                Hexpr::frobenius(vec![var(w)], vec![]),
+            ]));
+        }
+        if !operations.is_empty() {
+            items.push(tensor(operations));
+        }
+        if !self.targets.is_empty() {
+            let targets = self.targets.iter().map(|&w| var(w)).collect();
+            items.push(Hexpr::frobenius(vec![], targets));
+        }
+        composition(items)
+    }
+
+    /// `(spider {identity f g} spider h spider)`
+    fn layered(&self) -> Hexpr {
+        let wires = self.annotations.len();
+        let mut producers: Vec<Vec<usize>> = vec![vec![]; wires];
+        for (e, (_, _, outputs)) in self.edges.iter().enumerate() {
+            for &w in outputs {
+                producers[w].push(e);
+            }
+        }
+        let layer = layers(self.edges.iter().map(|(_, inputs, _)| {
+            let mut dependencies: Vec<usize> = inputs
+                .iter()
+                .flat_map(|&w| &producers[w])
+                .copied()
+                .collect();
+            dependencies.sort_unstable();
+            dependencies.dedup();
+            dependencies
+        }));
+        let depth = layer.iter().copied().max().unwrap_or(0);
+
+        // The first and last spiders each wire appears in, where spider `k` comes after layer
+        // `k`, or after the sources for `k = 0`. A wire used by a layer before the one producing
+        // it, in a cycle, appears from the spider before its use until the one after it is
+        // produced, where the two are joined.
+        let mut first = vec![usize::MAX; wires];
+        let mut last = vec![0; wires];
+        let mut spider = |w: usize, k: usize| {
+            first[w] = first[w].min(k);
+            last[w] = last[w].max(k);
+        };
+        for &w in &self.sources {
+            spider(w, 0);
+        }
+        for (e, (_, inputs, outputs)) in self.edges.iter().enumerate() {
+            for &w in inputs {
+                spider(w, layer[e] - 1);
+            }
+            for &w in outputs {
+                spider(w, layer[e]);
+            }
+        }
+        for &w in &self.targets {
+            spider(w, depth);
+        }
+
+        // The wires into and out of each layer, and the layers themselves
+        let mut annotated = vec![false; wires];
+        let mut into = vec![];
+        let mut out_of = vec![self.sources.clone()];
+        let mut layered = vec![];
+        for k in 1..=depth {
+            let through: Vec<usize> = (0..self.connected)
+                .filter(|&w| first[w] < k && k <= last[w])
+                .collect();
+            let (mut inputs, mut outputs) = (through.clone(), through.clone());
+            let mut parts = vec![];
+            if !through.is_empty() {
+                parts.push(self.spider(&through, &through, &mut annotated));
+            }
+            for (e, (op, op_inputs, op_outputs)) in self.edges.iter().enumerate() {
+                if layer[e] == k {
+                    inputs.extend(op_inputs);
+                    outputs.extend(op_outputs);
+                    parts.push(Hexpr::Operation(op.clone()));
+                }
+            }
+            into.push(inputs);
+            out_of.push(outputs);
+            layered.push(tensor(parts));
+        }
+        into.push(self.targets.clone());
+
+        // Spiders are left out where they would be an identity
+        let mut items = vec![];
+        let mut layered = layered.into_iter();
+        for (k, (from, to)) in out_of.iter().zip(&into).enumerate() {
+            let distinct = from.iter().enumerate().all(|(i, w)| !from[..i].contains(w));
+            let unannotated = from
+                .iter()
+                .any(|&w| !annotated[w] && self.annotations[w].is_some());
+            if from != to || !distinct || unannotated || depth == 0 {
+                items.push(self.spider(from, to, &mut annotated));
+            }
+            if k < depth {
+                items.push(layered.next().unwrap());
+            }
+        }
+        let hexpr = composition(items);
+
+        if self.connected == wires {
+            return hexpr;
+        }
+        let isolated: Vec<usize> = (self.connected..wires).collect();
+        tensor(vec![hexpr, self.spider(&isolated, &[], &mut annotated)])
+    }
+
+    /// A spider from the wires `from` to `to`, with its own variables.
+    /// Isolated wires are created and discarded again.
+    fn spider(&self, from: &[usize], to: &[usize], annotated: &mut [bool]) -> Hexpr {
+        let mut wires: Vec<usize> = vec![];
+        let mut var = |w: usize| {
+            let i = match wires.iter().position(|&v| v == w) {
+                Some(i) => i,
+                None => {
+                    wires.push(w);
+                    wires.len() - 1
+                }
+            };
+            self.occurrence(format!("x{}", i), w, annotated)
+        };
+        let isolated = from.iter().any(|&w| w >= self.connected);
+        let mut body = vec![];
+        if isolated {
+            body.push(Hexpr::frobenius(
+                vec![],
+                from.iter().map(|&w| var(w)).collect(),
+            ));
+        }
+        let sources = from.iter().map(|&w| var(w)).collect();
+        let targets = to.iter().map(|&w| var(w)).collect();
+        body.push(Hexpr::frobenius(sources, targets));
+        if wires.is_empty() {
+            return composition(body);
+        }
+        let variables = (0..wires.len())
+            .map(|i| variable(format!("x{}", i), None))
+            .collect();
+        Hexpr::scope(variables, body)
+    }
+
+    /// An occurrence of a wire, annotated if it needs an annotation and this is its first
+    fn occurrence(&self, name: String, wire: usize, annotated: &mut [bool]) -> Variable {
+        let annotation = match std::mem::replace(&mut annotated[wire], true) {
+            false => self.annotations[wire].clone(),
+            true => None,
+        };
+        variable(name, annotation)
+    }
+}
+
+fn variable(name: String, annotation: Option<Object>) -> Variable {
+    Variable {
+        name,
+        span: Span::default(),
+        annotation,
+    }
+}
+
+/// The composition of `hexprs`, without brackets around just one
+fn composition(mut hexprs: Vec<Hexpr>) -> Hexpr {
+    match hexprs.len() {
+        0 => Hexpr::frobenius(vec![], vec![]),
+        1 => hexprs.pop().unwrap(),
+        _ => Hexpr::composition(hexprs),
+    }
+}
+
+/// The tensor of `hexprs`, without brackets around just one
+fn tensor(mut hexprs: Vec<Hexpr>) -> Hexpr {
+    match hexprs.len() {
+        0 => Hexpr::frobenius(vec![], vec![]),
+        1 => hexprs.pop().unwrap(),
+        _ => Hexpr::tensor(hexprs),
+    }
+}
@@ -0,0 +1,379 @@
+//! Compiler-style diagnostics for errors from every stage: parsing, interpretation and
+//! unification.
+//!
+//! Errors convert into a [`Diagnostic`] with `From`, which can then be rendered against the
+//! source text:
+//!
+//! ```text
+//! error: failed to compose
+//!  --> input:1:2
+//!   |
+//! 1 | (neg add)
+//!   |  ^^^ these outputs have arity 1
+//!   |      --- these inputs have arity 2
+//!   |
+//!   = note: each expression in a composition must have as many outputs as the next has inputs
+//! ```
+use std::fmt::{Display, Write};
+
+use crate::interpret;
+use crate::parser::ParseError;
+use crate::span::{LineIndex, Span};
+use crate::template::ExpandError;
+use crate::unify::UnifyError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A message attached to a range of source text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    /// Primary labels mark the cause of a diagnostic, secondary labels give context.
+    pub primary: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+/// How to render a [`Diagnostic`]
+#[derive(Debug, Clone, Default)]
+pub struct RenderOptions {
+    /// Name of the source shown in the location line, defaults to `input`
+    pub file_name: Option<String>,
+    /// Use ANSI terminal colors
+    pub color: bool,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            message: message.into(),
+            labels: vec![],
+            notes: vec![],
+        }
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::error(message)
+        }
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: true,
+        });
+        self
+    }
+
+    pub fn with_secondary_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: false,
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// The span of the first primary label, if any
+    pub fn span(&self) -> Option<Span> {
+        self.labels.iter().find(|l| l.primary).map(|l| l.span)
+    }
+
+    /// Render this diagnostic against the source it refers to
+    pub fn render(&self, source: &str, options: &RenderOptions) -> String {
+        let style = Style {
+            color: options.color,
+        };
+        let lines = LineIndex::new(source);
+        let source_lines: Vec<&str> = source.split('\n').collect();
+        let mut out = String::new();
+
+        let severity_color = match self.severity {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        };
+        let _ = writeln!(
+            out,
+            "{}{}",
+            style.paint(&self.severity.to_string(), severity_color),
+            style.paint(&format!(": {}", self.message), BOLD)
+        );
+
+        // Labels sorted by position, each with the (1-based) line and column range it underlines
+        let mut labels: Vec<(usize, usize, usize, &Label)> = self
+            .labels
+            .iter()
+            .map(|label| {
+                let start = label.span.start.min(source.len());
+                let end = label.span.end.clamp(start, source.len());
+                let (line, column) = lines.line_col(start);
+                let line_text = source_lines[line - 1].trim_end_matches('\r');
+                let (end_line, end_column) = lines.line_col(end);
+                // Multi-line spans are underlined to the end of their first line
+                let end_column = if end_line == line {
+                    end_column
+                } else {
+                    line_text.chars().count() + 1
+                };
+                let width = end_column.saturating_sub(column).max(1);
+                (line, column, width, label)
+            })
+            .collect();
+        labels.sort_by_key(|&(line, column, _, label)| (line, column, !label.primary));
+
+        let gutter = labels
+            .last()
+            .map(|&(line, ..)| line.to_string().len())
+            .unwrap_or(0)
+            .max(1);
+        let pad = " ".repeat(gutter);
+        let bar = style.paint("|", BLUE);
+
+        let primary = labels
+            .iter()
+            .find(|(.., label)| label.primary)
+            .or(labels.first());
+        if let Some(&(line, column, ..)) = primary {
+            let name = options.file_name.as_deref().unwrap_or("input");
+            let _ = writeln!(
+                out,
+                "{}{} {}:{}:{}",
+                pad,
+                style.paint("-->", BLUE),
+                name,
+                line,
+                column
+            );
+        }
+
+        if !labels.is_empty() {
+            let _ = writeln!(out, "{} {}", pad, bar);
+            let mut previous_line: Option<usize> = None;
+            for &(line, column, width, label) in &labels {
+                if previous_line != Some(line) {
+                    if previous_line.is_some_and(|p| line > p + 1) {
+                        let _ = writeln!(out, "{}", style.paint("...", BLUE));
+                    }
+                    let text = source_lines[line - 1]
+                        .trim_end_matches('\r')
+                        .replace('\t', " ");
+                    let number = format!("{:>gutter$}", line);
+                    let _ = writeln!(out, "{} {} {}", style.paint(&number, BLUE), bar, text);
+                    previous_line = Some(line);
+                }
+                let (marker, color) = if label.primary {
+                    ('^', severity_color)
+                } else {
+                    ('-', BLUE)
+                };
+                let mut underline = marker.to_string().repeat(width);
+                if !label.message.is_empty() {
+                    underline.push(' ');
+                    underline.push_str(&label.message);
+                }
+                let _ = writeln!(
+                    out,
+                    "{} {} {}{}",
+                    pad,
+                    bar,
+                    " ".repeat(column - 1),
+                    style.paint(&underline, color)
+                );
+            }
+        }
+
+        if !self.notes.is_empty() {
+            if !labels.is_empty() {
+                let _ = writeln!(out, "{} {}", pad, bar);
+            }
+            for note in &self.notes {
+                let _ = writeln!(out, "{} {} note: {}", pad, style.paint("=", BLUE), note);
+            }
+        }
+        out
+    }
+}
+
+impl Display for Diagnostic {
+    /// A single-line summary, without source text
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.severity, self.message)?;
+        if let Some(span) = self.span() {
+            write!(f, " at {}", span)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<ParseError> for Diagnostic {
+    fn from(err: ParseError) -> Self {
+        let title = match err {
+            ParseError::Syntax(_) => "syntax error",
+            ParseError::Limit(..) => "input exceeds a limit",
+        };
+        Diagnostic::error(title).with_label(err.span(), err.message())
+    }
+}
+
+impl<E: Display> From<interpret::Error<E>> for Diagnostic {
+    fn from(err: interpret::Error<E>) -> Self {
+        match err {
+            interpret::Error::Composition {
+                left,
+                left_arity,
+                right,
+                right_arity,
+            } => Diagnostic::error("failed to compose")
+                .with_label(left, format!("these outputs have arity {}", left_arity))
+                .with_secondary_label(right, format!("these inputs have arity {}", right_arity))
+                .with_note(
+                    "each expression in a composition must have as many outputs as the next has inputs",
+                ),
+            interpret::Error::Signature(op, e) => {
+                Diagnostic::error(format!("couldn't parse operation `{}`", op))
+                    .with_label(op.span(), e.to_string())
+            }
+            interpret::Error::Literal(literal, e) => {
+                Diagnostic::error(format!("couldn't parse literal `{}`", literal))
+                    .with_label(literal.span(), e.to_string())
+            }
+            interpret::Error::Builtin(op, message) => {
+                Diagnostic::error(format!("invalid use of builtin `{}`", op.as_str()))
+                    .with_label(op.span(), message)
+            }
+            interpret::Error::Object(obj) => {
+                Diagnostic::error(format!("unknown object `{}`", obj))
+                    .with_label(obj.span(), "not an object of the signature")
+            }
+            interpret::Error::Redefinition { name, previous } => {
+                Diagnostic::error(format!("`{}` is defined more than once", name))
+                    .with_label(name.span(), "redefined here")
+                    .with_secondary_label(previous, "first defined here")
+            }
+            interpret::Error::Ascription {
+                span,
+                boundary,
+                expected,
+                actual,
+            } => Diagnostic::error("interface doesn't match its ascription")
+                .with_label(span, format!("this has {} {}", actual, boundary))
+                .with_note(format!("the ascription expects {} {}", expected, boundary)),
+            interpret::Error::Expand(e) => e.into(),
+            interpret::Error::Limit { exceeded, span } => {
+                Diagnostic::error("interpretation exceeds a limit")
+                    .with_label(span, format!("{} after interpreting this", exceeded))
+            }
+            interpret::Error::Syntax(span) => {
+                Diagnostic::error("can't interpret an expression containing syntax errors")
+                    .with_label(span, "syntax error here")
+            }
+        }
+    }
+}
+
+impl From<ExpandError> for Diagnostic {
+    fn from(err: ExpandError) -> Self {
+        match err {
+            ExpandError::NoMatchingDefinition(op) => Diagnostic::error(format!(
+                "no definition of template `{}` matches `{}`",
+                op.as_str(),
+                op
+            ))
+            .with_label(op.span(), "used here"),
+            ExpandError::RecursionLimit { op, limit } => {
+                Diagnostic::error(format!("recursion limit reached while expanding `{}`", op))
+                    .with_label(op.span(), format!("nested {} templates deep", limit))
+                    .with_note("is a definition for the base case missing?")
+            }
+            ExpandError::NotAHexpr { span, value } => {
+                Diagnostic::error(format!("`{}` can't be used as a hexpr", value))
+                    .with_label(span, "template parameter used as an operation")
+            }
+            ExpandError::Limit { exceeded, span } => {
+                Diagnostic::error("template expansion exceeds a limit")
+                    .with_label(span, format!("{} after expanding this", exceeded))
+            }
+        }
+    }
+}
+
+impl From<UnifyError> for Diagnostic {
+    fn from(err: UnifyError) -> Self {
+        match err {
+            UnifyError::NotAllLabelsKnown { spans, .. } => {
+                let diagnostic = Diagnostic::error("couldn't infer the type of a wire")
+                    .with_note("connect the wire to an operation to fix its type");
+                match spans.split_first() {
+                    Some((first, rest)) => rest.iter().fold(
+                        diagnostic.with_label(*first, "this wire has no known type"),
+                        |d, &span| d.with_secondary_label(span, "same wire"),
+                    ),
+                    None => diagnostic,
+                }
+            }
+            UnifyError::Mismatch {
+                spans, other_spans, ..
+            } => {
+                let mut diagnostic = Diagnostic::error("mismatched wire types");
+                if let Some(&span) = spans.first() {
+                    diagnostic = diagnostic.with_label(span, "this wire's type conflicts...");
+                }
+                if let Some(&span) = other_spans.first() {
+                    diagnostic =
+                        diagnostic.with_secondary_label(span, "...with the type given here");
+                }
+                diagnostic.with_note("connected wires must all have the same type")
+            }
+            UnifyError::Quotient => Diagnostic::error("failed to quotient the open hypergraph"),
+        }
+    }
+}
+
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+struct Style {
+    color: bool,
+}
+
+impl Style {
+    fn paint(&self, text: &str, color: &str) -> String {
+        if self.color {
+            format!("{}{}{}", color, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! Drawing interpreted open hypergraphs with [Graphviz](https://graphviz.org).
+//!
+//! Each hyperedge is a record box with a row of input ports, its operation, and a row of output
+//! ports, in order. Nodes are points labelled with their variable names and type, and the
+//! `sources` and `targets` of the open hypergraph are drawn as rails of numbered ports at either
+//! end, so the order of the interface is visible:
+//!
+//! ```text
+//! $ hexpr emit --format dot '(add [x . x x])' | dot -Tsvg > add.svg
+//! ```
+use std::fmt::{Display, Write};
+
+use open_hypergraphs::lax::NodeId;
+
+use crate::ast::Variable;
+use crate::interpret::OpenHypergraphWithNames;
+
+/// Which way a DOT drawing flows, from sources to targets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Direction {
+    #[default]
+    LeftToRight,
+    TopToBottom,
+}
+
+/// How to draw an open hypergraph as DOT
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DotOptions {
+    pub direction: Direction,
+    /// Draw nodes identified by the quotient as one node, as unification would.
+    /// Otherwise each node is drawn separately, with dashed lines between identified nodes.
+    pub quotient: bool,
+}
+
+impl<O: Display + Clone, A: Display + Clone> OpenHypergraphWithNames<Option<O>, A> {
+    /// Draw this open hypergraph, as produced by the interpreter, in the DOT language.
+    /// Nodes of unknown type are labelled only by their names.
+    pub fn to_dot(&self, options: &DotOptions) -> String {
+        self.to_dot_with(options, |obj| obj.as_ref().map(ToString::to_string))
+    }
+}
+
+impl<O: Clone, A: Display + Clone> OpenHypergraphWithNames<O, A> {
+    /// Draw this open hypergraph in the DOT language, labelling each node by `object`.
+    ///
+    /// Unified open hypergraphs can be drawn with `|obj| Some(obj.to_string())`.
+    pub fn to_dot_with(
+        &self,
+        options: &DotOptions,
+        object: impl Fn(&O) -> Option<String>,
+    ) -> String {
+        let graph = &self.graph;
+        let hypergraph = &graph.hypergraph;
+
+        // The point each node is drawn as
+        let (point, points): (Vec<usize>, usize) = match options.quotient {
+            true => {
+                let coequalizer = hypergraph.coequalizer();
+                (coequalizer.table.0, coequalizer.target)
+            }
+            false => (
+                (0..hypergraph.nodes.len()).collect(),
+                hypergraph.nodes.len(),
+            ),
+        };
+        let mut names: Vec<Vec<&Variable>> = vec![vec![]; points];
+        let mut objects: Vec<Vec<String>> = vec![vec![]; points];
+        for (i, node) in hypergraph.nodes.iter().enumerate() {
+            for variable in self.names.get(&NodeId(i)).into_iter().flatten() {
+                if !names[point[i]].contains(&variable) {
+                    names[point[i]].push(variable);
+                }
+            }
+            if let Some(obj) = object(node) {
+                if !objects[point[i]].contains(&obj) {
+                    objects[point[i]].push(obj);
+                }
+            }
+        }
+
+        let (rankdir, input, output) = match options.direction {
+            Direction::LeftToRight => ("LR", "w", "e"),
+            Direction::TopToBottom => ("TB", "n", "s"),
+        };
+        let mut out = String::new();
+        let _ = writeln!(out, "digraph {{");
+        let _ = writeln!(out, "  rankdir={};", rankdir);
+        let _ = writeln!(out, "  node [shape=record];");
+
+        // Interface rails
+        for (rail, port, rank, nodes) in [
+            ("sources", "s", "source", &graph.sources),
+            ("targets", "t", "sink", &graph.targets),
+        ] {
+            if nodes.is_empty() {
+                continue;
+            }
+            let ports: Vec<String> = (0..nodes.len())
+                .map(|i| format!("<{}{}> {}", port, i, i))
+                .collect();
+            let _ = writeln!(
+                out,
+                "  {{ rank={}; {} [label=\"{}\", style=dashed]; }}",
+                rank,
+                rail,
+                ports.join("|")
+            );
+        }
+
+        // Nodes
+        for p in 0..points {
+            let names: Vec<String> = names[p].iter().map(ToString::to_string).collect();
+            let label = match (names.is_empty(), objects[p].is_empty()) {
+                (_, true) => names.join(", "),
+                (true, false) => objects[p].join(" ≠ "),
+                (false, false) => format!("{} : {}", names.join(", "), objects[p].join(" ≠ ")),
+            };
+            let _ = writeln!(
+                out,
+                "  n{} [shape=point, xlabel=\"{}\"];",
+                p,
+                escape(&label)
+            );
+        }
+
+        // Hyperedges, as records of input ports, the operation and output ports
+        for (e, (op, edge)) in hypergraph
+            .edges
+            .iter()
+            .zip(&hypergraph.adjacency)
+            .enumerate()
+        {
+            let ports = |port: &str, count: usize| -> String {
+                let ports: Vec<String> = (0..count).map(|i| format!("<{}{}>", port, i)).collect();
+                format!("{{{}}}", ports.join("|"))
+            };
+            let mut fields = vec![];
+            if !edge.sources.is_empty() {
+                fields.push(ports("i", edge.sources.len()));
+            }
+            fields.push(escape_record(&op.to_string()));
+            if !edge.targets.is_empty() {
+                fields.push(ports("o", edge.targets.len()));
+            }
+            let _ = writeln!(out, "  e{} [label=\"{{{}}}\"];", e, fields.join("|"));
+            for (i, node) in edge.sources.iter().enumerate() {
+                let _ = writeln!(out, "  n{} -> e{}:i{}:{};", point[node.0], e, i, input);
+            }
+            for (i, node) in edge.targets.iter().enumerate() {
+                let _ = writeln!(out, "  e{}:o{}:{} -> n{};", e, i, output, point[node.0]);
+            }
+        }
+
+        // Boundary wires
+        for (i, node) in graph.sources.iter().enumerate() {
+            let _ = writeln!(out, "  sources:s{}:{} -> n{};", i, output, point[node.0]);
+        }
+        for (i, node) in graph.targets.iter().enumerate() {
+            let _ = writeln!(out, "  n{} -> targets:t{}:{};", point[node.0], i, input);
+        }
+
+        // Nodes identified by the quotient, when drawn separately
+        if !options.quotient {
+            let (left, right) = &hypergraph.quotient;
+            for (l, r) in left.iter().zip(right) {
+                let _ = writeln!(out, "  n{} -> n{} [style=dashed, dir=none];", l.0, r.0);
+            }
+        }
+        let _ = writeln!(out, "}}");
+        out
+    }
+}
+
+/// Escape a DOT string
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Escape a DOT string used as a record label, where braces, bars and angle brackets are special
+fn escape_record(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if matches!(c, '\\' | '"' | '{' | '}' | '|' | '<' | '>' | ' ') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
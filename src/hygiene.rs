@@ -0,0 +1,341 @@
+//! Renaming variables, so that hexprs built from fragments don't accidentally share wires.
+//!
+//! Hexprs from different sources may use the same variable names for unrelated wires.
+//! Nesting them naively in a [`Hexpr::Composition`] joins those wires together;
+//! [`Hexpr::splice`] renames fragments apart first.
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::{Hexpr, Variable};
+
+impl Hexpr {
+    /// Apply `f` to every variable occurrence, including those declared by scopes.
+    pub fn map_variables(&self, f: &mut impl FnMut(&Variable) -> Variable) -> Hexpr {
+        self.rebuild(|hexpr, children| match hexpr {
+            Hexpr::Frobenius {
+                sources,
+                targets,
+                identity,
+                span,
+            } => Hexpr::Frobenius {
+                sources: sources.iter().map(&mut *f).collect(),
+                targets: targets.iter().map(&mut *f).collect(),
+                identity: *identity,
+                span: *span,
+            },
+            Hexpr::Scope {
+                variables, span, ..
+            } => Hexpr::Scope {
+                variables: variables.iter().map(&mut *f).collect(),
+                body: children,
+                span: *span,
+            },
+            _ => hexpr.with_children(children),
+        })
+    }
+
+    /// Every named variable occurring in this hexpr. Excludes the anonymous variable `_`.
+    pub fn variables(&self) -> HashSet<Variable> {
+        let mut variables = HashSet::new();
+        // Iterative, since this is used when interpreting arbitrarily deep powers
+        let mut stack = vec![self];
+        while let Some(hexpr) = stack.pop() {
+            let occurrences: &[Variable] = match hexpr {
+                Hexpr::Frobenius {
+                    sources, targets, ..
+                } => {
+                    variables.extend(sources.iter().filter(|var| !var.is_anonymous()).cloned());
+                    targets
+                }
+                Hexpr::Scope {
+                    variables: declared,
+                    ..
+                } => declared,
+                _ => &[],
+            };
+            variables.extend(
+                occurrences
+                    .iter()
+                    .filter(|var| !var.is_anonymous())
+                    .cloned(),
+            );
+            stack.extend(hexpr.children());
+        }
+        variables
+    }
+
+    /// This hexpr in a scope declaring each of its variables, so that it shares no wires with
+    /// its surroundings. Hexprs without variables are returned unchanged.
+    pub fn localize(&self) -> Hexpr {
+        self.clone().localized()
+    }
+
+    /// [`Hexpr::localize`] without copying
+    pub(crate) fn localized(self) -> Hexpr {
+        let mut variables: Vec<Variable> = self
+            .variables()
+            .into_iter()
+            .map(|var| Variable {
+                annotation: None,
+                ..var
+            })
+            .collect();
+        if variables.is_empty() {
+            return self;
+        }
+        variables.sort_by(|a, b| a.name.cmp(&b.name));
+        let span = self.span();
+        Hexpr::Scope {
+            variables,
+            body: vec![self],
+            span,
+        }
+    }
+
+    /// Rename every variable not in `keep` to a name occurring neither in this hexpr nor in
+    /// `avoid`.
+    ///
+    /// Renaming is consistent, so the result is [alpha-equivalent](Hexpr::alpha_eq) to `self`.
+    pub fn freshen(&self, keep: &HashSet<Variable>, avoid: &HashSet<Variable>) -> Hexpr {
+        let mut used: HashSet<Variable> = self.variables();
+        used.extend(avoid.iter().cloned());
+        let mut renaming: HashMap<Variable, Variable> = HashMap::new();
+        self.map_variables(&mut |var| {
+            if var.is_anonymous() || keep.contains(var) {
+                return var.clone();
+            }
+            let name = renaming
+                .entry(var.clone())
+                .or_insert_with(|| fresh_name(var, &mut used))
+                .name
+                .clone();
+            Variable {
+                name,
+                ..var.clone()
+            }
+        })
+    }
+
+    /// Rename the variables of each fragment apart from those of the others, so that combining
+    /// them in a composition or tensor only shares the wires named in `shared`.
+    pub fn splice(fragments: &[Hexpr], shared: &HashSet<Variable>) -> Vec<Hexpr> {
+        let mut seen: HashSet<Variable> = shared.clone();
+        let mut result = Vec::with_capacity(fragments.len());
+        for fragment in fragments {
+            // Only rename the variables which clash with an earlier fragment
+            let keep = fragment
+                .variables()
+                .into_iter()
+                .filter(|var| shared.contains(var) || !seen.contains(var))
+                .collect();
+            let renamed = fragment.freshen(&keep, &seen);
+            seen.extend(renamed.variables());
+            result.push(renamed);
+        }
+        result
+    }
+
+    /// Equality up to consistent renaming of variables.
+    ///
+    /// Two hexprs are alpha-equivalent when they have the same structure and operations, and
+    /// there is a one-to-one correspondence between their wires: each variable binding of one
+    /// corresponds to a binding of the other.
+    /// Anonymous variables only correspond to anonymous variables.
+    pub fn alpha_eq(&self, other: &Hexpr) -> bool {
+        AlphaEq::default().eq(self, other)
+    }
+}
+
+/// A name based on `var` which is not in `used`, which it is then added to.
+fn fresh_name(var: &Variable, used: &mut HashSet<Variable>) -> Variable {
+    let base = var
+        .name
+        .trim_end_matches(|c: char| c.is_ascii_digit() || c == '_');
+    let base = if base.is_empty() { "v" } else { base };
+    let candidate = (1..)
+        .map(|i| Variable {
+            name: format!("{}_{}", base, i),
+            span: var.span,
+            annotation: None,
+        })
+        .find(|candidate| !used.contains(candidate))
+        .unwrap();
+    used.insert(candidate.clone());
+    candidate
+}
+
+/// Binding of a variable occurrence: either a scope declaration or the top level
+type Binding = usize;
+
+/// The variable bindings visible at a point in a hexpr
+#[derive(Default)]
+struct Bindings {
+    /// Innermost scope last
+    scopes: Vec<HashMap<String, Binding>>,
+    globals: HashMap<String, Binding>,
+}
+
+impl Bindings {
+    /// The binding of a variable, creating a top-level binding if it is not in scope
+    fn resolve(&mut self, var: &Variable, next: &mut Binding) -> Binding {
+        let scoped = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&var.name).copied());
+        scoped.unwrap_or_else(|| {
+            *self.globals.entry(var.name.clone()).or_insert_with(|| {
+                *next += 1;
+                *next
+            })
+        })
+    }
+}
+
+/// State for comparing two hexprs up to renaming
+#[derive(Default)]
+struct AlphaEq {
+    left: Bindings,
+    right: Bindings,
+    /// The correspondence between bindings, in both directions
+    left_to_right: HashMap<Binding, Binding>,
+    right_to_left: HashMap<Binding, Binding>,
+    next_binding: Binding,
+}
+
+/// A step in comparing two hexprs up to renaming
+enum Compare<'a> {
+    Hexprs(&'a Hexpr, &'a Hexpr),
+    /// Leave the innermost scope of both hexprs
+    PopScopes,
+}
+
+impl AlphaEq {
+    /// Compare with an explicit stack rather than recursively, so that deeply nested hexprs
+    /// don't overflow the call stack
+    fn eq(&mut self, a: &Hexpr, b: &Hexpr) -> bool {
+        let mut stack = vec![Compare::Hexprs(a, b)];
+        while let Some(compare) = stack.pop() {
+            let (a, b) = match compare {
+                Compare::Hexprs(a, b) => (a, b),
+                Compare::PopScopes => {
+                    self.left.scopes.pop();
+                    self.right.scopes.pop();
+                    continue;
+                }
+            };
+            if !self.eq_outside(a, b, &mut stack) || a.children().len() != b.children().len() {
+                return false;
+            }
+            // Children are compared before any scope pushed for them is popped
+            let children = a.children().iter().zip(b.children()).rev();
+            stack.extend(children.map(|(x, y)| Compare::Hexprs(x, y)));
+        }
+        true
+    }
+
+    /// Compare two hexprs apart from the hexprs directly inside them, entering any scope they
+    /// declare and pushing the step which leaves it
+    fn eq_outside<'a>(&mut self, a: &'a Hexpr, b: &'a Hexpr, stack: &mut Vec<Compare<'a>>) -> bool {
+        match (a, b) {
+            (Hexpr::Composition(..), Hexpr::Composition(..))
+            | (Hexpr::Tensor(..), Hexpr::Tensor(..)) => true,
+            (
+                Hexpr::Frobenius {
+                    sources: s1,
+                    targets: t1,
+                    identity: i1,
+                    ..
+                },
+                Hexpr::Frobenius {
+                    sources: s2,
+                    targets: t2,
+                    identity: i2,
+                    ..
+                },
+            ) => i1 == i2 && self.eq_variables(s1, s2) && self.eq_variables(t1, t2),
+            (Hexpr::Operation(x), Hexpr::Operation(y)) => x == y,
+            (Hexpr::Literal(x), Hexpr::Literal(y)) => x == y,
+            (Hexpr::Error(_), Hexpr::Error(_)) => true,
+            (Hexpr::Scope { variables: v1, .. }, Hexpr::Scope { variables: v2, .. }) => {
+                if v1.len() != v2.len() {
+                    return false;
+                }
+                self.enter(v1, v2, stack);
+                self.eq_variables(v1, v2)
+            }
+            (
+                Hexpr::Power {
+                    hexpr: h1,
+                    kind: k1,
+                    count: n1,
+                    ..
+                },
+                Hexpr::Power {
+                    hexpr: h2,
+                    kind: k2,
+                    count: n2,
+                    ..
+                },
+            ) => {
+                // The variables of a power are local to it
+                let v1: Vec<Variable> = h1.variables().into_iter().collect();
+                let v2: Vec<Variable> = h2.variables().into_iter().collect();
+                if k1 != k2 || n1 != n2 || v1.len() != v2.len() {
+                    return false;
+                }
+                self.enter(&v1, &v2, stack);
+                true
+            }
+            (
+                Hexpr::Ascription {
+                    sources: s1,
+                    targets: t1,
+                    ..
+                },
+                Hexpr::Ascription {
+                    sources: s2,
+                    targets: t2,
+                    ..
+                },
+            ) => s1 == s2 && t1 == t2,
+            _ => false,
+        }
+    }
+
+    /// Enter a scope declaring `v1` on the left and `v2` on the right, until `stack` leaves it
+    fn enter(&mut self, v1: &[Variable], v2: &[Variable], stack: &mut Vec<Compare>) {
+        let left = self.declare(v1);
+        let right = self.declare(v2);
+        self.left.scopes.push(left);
+        self.right.scopes.push(right);
+        stack.push(Compare::PopScopes);
+    }
+
+    fn declare(&mut self, variables: &[Variable]) -> HashMap<String, Binding> {
+        variables
+            .iter()
+            .map(|var| {
+                self.next_binding += 1;
+                (var.name.clone(), self.next_binding)
+            })
+            .collect()
+    }
+
+    fn eq_variables(&mut self, xs: &[Variable], ys: &[Variable]) -> bool {
+        xs.len() == ys.len() && xs.iter().zip(ys).all(|(x, y)| self.eq_variable(x, y))
+    }
+
+    fn eq_variable(&mut self, x: &Variable, y: &Variable) -> bool {
+        if x.annotation != y.annotation {
+            return false;
+        }
+        if x.is_anonymous() || y.is_anonymous() {
+            return x.is_anonymous() && y.is_anonymous();
+        }
+        let left = self.left.resolve(x, &mut self.next_binding);
+        let right = self.right.resolve(y, &mut self.next_binding);
+        let forward = *self.left_to_right.entry(left).or_insert(right);
+        let backward = *self.right_to_left.entry(right).or_insert(left);
+        forward == right && backward == left
+    }
+}
@@ -3,35 +3,147 @@ use std::collections::HashMap;
 
 use open_hypergraphs::lax::{Interface, NodeId, OpenHypergraph};
 
-use crate::ast::{Hexpr, Operation, Variable};
+use crate::ast::{Hexpr, Item, Literal, Object, Operation, Ports, PowerKind, Program, Variable};
+use crate::builtin::Builtin;
+use crate::limit::{Limit, LimitExceeded};
+use crate::span::Span;
+use crate::template::{self, ExpandError};
 use thiserror::Error;
 
 #[derive(Debug)]
 pub struct OpenHypergraphWithNames<O, A> {
     pub graph: OpenHypergraph<O, A>,
     pub names: HashMap<NodeId, Vec<Variable>>,
+    /// Source locations which gave rise to each node: operation ports and variable occurrences.
+    pub spans: HashMap<NodeId, Vec<Span>>,
 }
 
 /// A `Signature` is:
 ///  - A way to parse operations
 ///   - A profile: a source/target type for each arrow
+///  - Optionally, a way to parse objects in wire type annotations like `[x:ℝ]`
+///  - Optionally, a way to parse literal constants like `3.14` to arrows
 pub trait Signature {
     type Arr;
     type Obj;
     type Error;
 
     fn try_parse_op(&self, op: &Operation) -> Result<Self::Arr, Self::Error>;
+    #[allow(clippy::type_complexity)]
     fn profile(&self, op: &Self::Arr) -> (Vec<Option<Self::Obj>>, Vec<Option<Self::Obj>>);
+
+    /// Parse a wire type annotation. By default, no annotations are allowed.
+    fn try_parse_obj(&self, _obj: &Object) -> Option<Self::Obj> {
+        None
+    }
+
+    /// Parse a literal constant, typically to an arrow with no inputs and one output.
+    /// By default, literals are parsed as the operation with the same text, so a signature can
+    /// still give meaning to a few constants like `0` and `1` in `try_parse_op`.
+    fn try_parse_literal(&self, literal: &Literal) -> Result<Self::Arr, Self::Error> {
+        self.try_parse_op(&literal.as_operation())
+    }
 }
 
 #[derive(Debug, Error)]
 pub enum Error<E> {
-    #[error("Failed to compose {0} ; {1}")]
-    Composition(Hexpr, Hexpr),
-    #[error("Couldn't parse op {0}: {1}")]
+    #[error("{left}: Failed to compose {left_arity} outputs with {right_arity} inputs at {right}")]
+    Composition {
+        /// The expression whose targets are being composed
+        left: Span,
+        left_arity: usize,
+        /// The expression whose sources are being composed
+        right: Span,
+        right_arity: usize,
+    },
+    #[error("{span}: Couldn't parse op {0}: {1}", span = .0.span())]
     Signature(Operation, E),
+    #[error("{span}: Couldn't parse literal {0}: {1}", span = .0.span())]
+    Literal(Literal, E),
+    #[error("{span}: Invalid builtin {0}: {1}", span = .0.span())]
+    Builtin(Operation, String),
+    #[error("{span}: Unknown object {0}", span = .0.span())]
+    Object(Object),
+    #[error("{0}: Can't interpret a syntax error")]
+    Syntax(Span),
+    #[error("{span}: {name} is already defined at {previous}", span = .name.span())]
+    Redefinition { name: Operation, previous: Span },
+    #[error(transparent)]
+    Expand(#[from] ExpandError),
+    #[error("{span}: Expected {expected} {boundary} but found {actual}")]
+    Ascription {
+        /// The ascribed expression
+        span: Span,
+        boundary: Boundary,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("{span}: {exceeded}")]
+    Limit {
+        exceeded: LimitExceeded,
+        /// The expression being interpreted when the limit was exceeded
+        span: Span,
+    },
+}
+
+/// Limits on the open hypergraphs built by [`try_interpret_with_options`] and
+/// [`try_interpret_program_with_options`], for interpreting untrusted hexprs. Nothing is limited
+/// by default.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterpretOptions {
+    /// The maximum number of operations, literals and Frobenius brackets to interpret, counting
+    /// each copy in a power and each use of a definition
+    pub max_operations: usize,
+    /// The maximum number of nodes in the result
+    pub max_nodes: usize,
+    /// The maximum number of edges in the result
+    pub max_edges: usize,
+}
+
+impl Default for InterpretOptions {
+    fn default() -> Self {
+        InterpretOptions {
+            max_operations: usize::MAX,
+            max_nodes: usize::MAX,
+            max_edges: usize::MAX,
+        }
+    }
+}
+
+/// One side of an expression's interface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Boundary {
+    Sources,
+    Targets,
+}
+
+impl std::fmt::Display for Boundary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Boundary::Sources => write!(f, "inputs"),
+            Boundary::Targets => write!(f, "outputs"),
+        }
+    }
 }
 
+impl<E> Error<E> {
+    /// The source range responsible for this error
+    pub fn span(&self) -> Span {
+        match self {
+            Error::Composition { left, right, .. } => left.join(*right),
+            Error::Signature(op, _) => op.span(),
+            Error::Literal(literal, _) => literal.span(),
+            Error::Builtin(op, _) => op.span(),
+            Error::Object(obj) => obj.span(),
+            Error::Syntax(span) => *span,
+            Error::Redefinition { name, .. } => name.span(),
+            Error::Expand(e) => e.span(),
+            Error::Ascription { span, .. } | Error::Limit { span, .. } => *span,
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
 pub fn try_interpret<S: Signature>(
     signature: &S,
     hexpr: &Hexpr,
@@ -39,110 +151,598 @@ pub fn try_interpret<S: Signature>(
     Ok(try_interpret_with_names(signature, hexpr)?.graph)
 }
 
+#[allow(clippy::type_complexity)]
 pub fn try_interpret_with_names<S: Signature>(
     signature: &S,
     hexpr: &Hexpr,
 ) -> Result<OpenHypergraphWithNames<Option<S::Obj>, S::Arr>, Error<S::Error>> {
-    let mut state = OpenHypergraph::empty();
-    let mut env = HashMap::new();
-    let (sources, targets) = try_interpret_stack(signature, &mut state, &mut env, hexpr)?;
+    try_interpret_with_options(signature, hexpr, &InterpretOptions::default())
+}
+
+/// Interpret a hexpr, failing with [`Error::Limit`] if it exceeds any of the `options`
+#[allow(clippy::type_complexity)]
+pub fn try_interpret_with_options<S: Signature>(
+    signature: &S,
+    hexpr: &Hexpr,
+    options: &InterpretOptions,
+) -> Result<OpenHypergraphWithNames<Option<S::Obj>, S::Arr>, Error<S::Error>> {
+    interpret_with_definitions(signature, &(), options, hexpr)
+}
+
+/// Interpret each hexpr of a program.
+///
+/// Templates are [expanded](crate::template::expand) first.
+/// Operations named by an earlier definition are replaced by a copy of the definition's open
+/// hypergraph, then [builtins](crate::builtin) are recognised; all other operations are parsed by
+/// the signature.
+/// Variables are local to each definition and top-level hexpr.
+#[allow(clippy::type_complexity)]
+pub fn try_interpret_program<S: Signature>(
+    signature: &S,
+    program: &Program,
+) -> Result<Vec<OpenHypergraphWithNames<Option<S::Obj>, S::Arr>>, Error<S::Error>>
+where
+    S::Obj: Clone,
+    S::Arr: Clone,
+{
+    try_interpret_program_with_options(signature, program, &InterpretOptions::default())
+}
+
+/// Interpret each hexpr of a program like [`try_interpret_program`], failing with
+/// [`Error::Limit`] if any definition or hexpr exceeds the `options`
+#[allow(clippy::type_complexity)]
+pub fn try_interpret_program_with_options<S: Signature>(
+    signature: &S,
+    program: &Program,
+    options: &InterpretOptions,
+) -> Result<Vec<OpenHypergraphWithNames<Option<S::Obj>, S::Arr>>, Error<S::Error>>
+where
+    S::Obj: Clone,
+    S::Arr: Clone,
+{
+    // Expanding copies every hexpr, so skip it when there's nothing to expand
+    if !program
+        .items
+        .iter()
+        .any(|item| matches!(item, Item::Template(_)))
+    {
+        return interpret_items(signature, program, options);
+    }
+    let expanded =
+        template::expand_with_options(program, template::DEFAULT_RECURSION_LIMIT, options)?;
+    let results = interpret_items(signature, &expanded, options);
+    // The copies may be deeply nested
+    expanded.drop_iteratively();
+    results
+}
+
+/// Interpret the definitions and hexprs of a program with no templates
+#[allow(clippy::type_complexity)]
+fn interpret_items<S: Signature>(
+    signature: &S,
+    program: &Program,
+    options: &InterpretOptions,
+) -> Result<Vec<OpenHypergraphWithNames<Option<S::Obj>, S::Arr>>, Error<S::Error>>
+where
+    S::Obj: Clone,
+    S::Arr: Clone,
+{
+    let mut definitions = HashMap::new();
+    let mut results = vec![];
+    for item in &program.items {
+        match item {
+            Item::Definition(definition) => {
+                if let Some((previous, _)) = definitions.get_key_value(&definition.name) {
+                    return Err(Error::Redefinition {
+                        name: definition.name.clone(),
+                        previous: Operation::span(previous),
+                    });
+                }
+                let graph =
+                    interpret_with_definitions(signature, &definitions, options, &definition.body)?;
+                definitions.insert(definition.name.clone(), graph);
+            }
+            Item::Hexpr(hexpr) => results.push(interpret_with_definitions(
+                signature,
+                &definitions,
+                options,
+                hexpr,
+            )?),
+            // Removed by expansion
+            Item::Template(_) => (),
+        }
+    }
+    Ok(results)
+}
+
+type Definitions<S> = HashMap<
+    Operation,
+    OpenHypergraphWithNames<Option<<S as Signature>::Obj>, <S as Signature>::Arr>,
+>;
+
+/// Earlier definitions for an [`Interpreter`] to inline. Inlining copies their labels, so only
+/// programs need `Clone` objects and arrows; a single hexpr has no definitions, `()`.
+trait Inline<S: Signature> {
+    /// Append a copy of the definition named `op`, if there is one, returning its interface
+    fn inline(&self, interpreter: &mut Interpreter<'_, S>, op: &Operation) -> Option<Interface>;
+}
+
+impl<S: Signature> Inline<S> for () {
+    fn inline(&self, _: &mut Interpreter<'_, S>, _: &Operation) -> Option<Interface> {
+        None
+    }
+}
+
+impl<S: Signature> Inline<S> for Definitions<S>
+where
+    S::Obj: Clone,
+    S::Arr: Clone,
+{
+    fn inline(&self, interpreter: &mut Interpreter<'_, S>, op: &Operation) -> Option<Interface> {
+        let definition = self.get(op)?;
+        let state = &mut interpreter.state;
+        let offset = state.hypergraph.nodes.len();
+        let shift = |nodes: &[NodeId]| -> Vec<NodeId> {
+            nodes.iter().map(|node| NodeId(node.0 + offset)).collect()
+        };
+
+        let graph = &definition.graph;
+        for (i, label) in graph.hypergraph.nodes.iter().enumerate() {
+            let node = state.new_node(label.clone());
+            let spans = interpreter.spans.entry(node).or_default();
+            spans.extend(definition.spans.get(&NodeId(i)).into_iter().flatten());
+            spans.push(op.span());
+        }
+        for (label, edge) in graph
+            .hypergraph
+            .edges
+            .iter()
+            .zip(&graph.hypergraph.adjacency)
+        {
+            let interface = (shift(&edge.sources), shift(&edge.targets));
+            state.new_edge(label.clone(), interface);
+        }
+        let (vs, ws) = &graph.hypergraph.quotient;
+        for (v, w) in shift(vs).into_iter().zip(shift(ws)) {
+            state.unify(v, w);
+        }
+        Some((shift(&graph.sources), shift(&graph.targets)))
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn interpret_with_definitions<S: Signature>(
+    signature: &S,
+    definitions: &dyn Inline<S>,
+    options: &InterpretOptions,
+    hexpr: &Hexpr,
+) -> Result<OpenHypergraphWithNames<Option<S::Obj>, S::Arr>, Error<S::Error>> {
+    let mut interpreter = Interpreter {
+        signature,
+        definitions,
+        options,
+        operations: 0,
+        state: OpenHypergraph::empty(),
+        scopes: vec![Scope::closed()],
+        names: HashMap::new(),
+        spans: HashMap::new(),
+    };
+    let (sources, targets) = interpreter.interpret(hexpr)?;
+    interpreter.pop_scope();
+    let mut state = interpreter.state;
     state.sources = sources;
     state.targets = targets;
     Ok(OpenHypergraphWithNames {
         graph: state,
-        names: names_by_node(env),
+        names: interpreter.names,
+        spans: interpreter.spans,
     })
 }
 
-fn names_by_node(env: HashMap<Variable, NodeId>) -> HashMap<NodeId, Vec<Variable>> {
-    env.into_iter()
-        .fold(HashMap::new(), |mut names, (variable, node)| {
-            names.entry(node).or_default().push(variable);
-            names
-        })
+/// A step of interpreting a hexpr.
+///
+/// Hexprs are interpreted with an explicit stack of tasks rather than recursively, so that deeply
+/// nested hexprs don't overflow the call stack. Each task which completes a hexpr pushes its
+/// interface onto a stack of interfaces.
+enum Task<'h> {
+    Interpret(&'h Hexpr),
+    /// Replace the top `n` interfaces with their tensor product
+    Tensor(usize),
+    /// Replace the top two interfaces with their composition, reporting a mismatch at the spans
+    /// of the hexprs they came from
+    Compose {
+        left: Span,
+        right: Span,
+    },
+    PushScope(Scope),
+    PopScope,
+    /// Check the top interface against an ascription
+    Ascribe {
+        sources: &'h Ports,
+        targets: &'h Ports,
+        span: Span,
+    },
+    /// Interpret the copies of a power after the first `done`, combining each with the last
+    Repeat {
+        hexpr: &'h Hexpr,
+        kind: PowerKind,
+        done: usize,
+        count: usize,
+    },
 }
 
-fn try_interpret_stack<S: Signature>(
-    signature: &S,
-    state: &mut OpenHypergraph<Option<S::Obj>, S::Arr>,
-    env: &mut HashMap<Variable, NodeId>,
-    hexpr: &Hexpr,
-) -> Result<Interface, Error<S::Error>> {
-    match hexpr {
-        Hexpr::Composition(hexprs) => {
-            let mut iter = hexprs.iter();
-            let mut hexpr = match iter.next() {
-                Some(hexpr) => hexpr,
-                None => return Ok((vec![], vec![])),
-            };
-
-            let (sources, mut current_targets) = try_interpret_stack(signature, state, env, hexpr)?;
-            for next_hexpr in iter {
-                let (next_sources, next_targets) =
-                    try_interpret_stack(signature, state, env, next_hexpr)?;
-
-                // Check if targets of current match sources of next
-                if current_targets.len() != next_sources.len() {
-                    return Err(Error::Composition(hexpr.clone(), next_hexpr.clone()));
-                }
+/// Push the tasks for a composition of `hexprs`
+fn compose<'h>(tasks: &mut Vec<Task<'h>>, hexprs: &'h [Hexpr]) {
+    let Some(first) = hexprs.first() else {
+        // The empty composition has the empty interface
+        tasks.push(Task::Tensor(0));
+        return;
+    };
+    // Tasks run last to first
+    for pair in hexprs.windows(2).rev() {
+        tasks.push(Task::Compose {
+            left: pair[0].span(),
+            right: pair[1].span(),
+        });
+        tasks.push(Task::Interpret(&pair[1]));
+    }
+    tasks.push(Task::Interpret(first));
+}
+
+/// Push the tasks for a tensor product of `hexprs`
+fn tensor<'h>(tasks: &mut Vec<Task<'h>>, hexprs: &'h [Hexpr]) {
+    tasks.push(Task::Tensor(hexprs.len()));
+    tasks.extend(hexprs.iter().rev().map(Task::Interpret));
+}
 
-                // Unify targets of current with sources of next
-                for (&target, &source) in current_targets.iter().zip(&next_sources) {
-                    state.unify(target, source);
+/// The variables bound by a scope
+struct Scope {
+    /// Variables declared by the scope, which are unbound until their first occurrence
+    bindings: HashMap<Variable, Option<NodeId>>,
+    /// Whether the scope also binds every variable not declared by an inner scope, as the
+    /// outermost scope and the copies in a power do
+    closed: bool,
+}
+
+impl Scope {
+    fn new(variables: &[Variable]) -> Self {
+        Scope {
+            bindings: variables.iter().map(|var| (var.clone(), None)).collect(),
+            closed: false,
+        }
+    }
+
+    fn closed() -> Self {
+        Scope {
+            bindings: HashMap::new(),
+            closed: true,
+        }
+    }
+}
+
+/// State accumulated while interpreting a hexpr
+struct Interpreter<'a, S: Signature> {
+    signature: &'a S,
+    definitions: &'a dyn Inline<S>,
+    options: &'a InterpretOptions,
+    /// Operations, literals and Frobenius brackets interpreted so far
+    operations: usize,
+    state: OpenHypergraph<Option<S::Obj>, S::Arr>,
+    /// Variable bindings, innermost scope last
+    scopes: Vec<Scope>,
+    /// Names of the variables in scopes which have ended
+    names: HashMap<NodeId, Vec<Variable>>,
+    spans: HashMap<NodeId, Vec<Span>>,
+}
+
+impl<S: Signature> Interpreter<'_, S> {
+    fn interpret(&mut self, hexpr: &Hexpr) -> Result<Interface, Error<S::Error>> {
+        let mut tasks = vec![Task::Interpret(hexpr)];
+        // The interfaces of the hexprs interpreted so far
+        let mut interfaces: Vec<Interface> = vec![];
+        while let Some(task) = tasks.pop() {
+            match task {
+                Task::Interpret(hexpr) => match hexpr {
+                    Hexpr::Composition(hexprs, _) => compose(&mut tasks, hexprs),
+                    Hexpr::Tensor(hexprs, _) => tensor(&mut tasks, hexprs),
+                    Hexpr::Scope {
+                        variables, body, ..
+                    } => {
+                        tasks.push(Task::PopScope);
+                        compose(&mut tasks, body);
+                        tasks.push(Task::PushScope(Scope::new(variables)));
+                    }
+                    Hexpr::Power {
+                        hexpr, kind, count, ..
+                    } => tasks.push(Task::Repeat {
+                        hexpr,
+                        kind: *kind,
+                        done: 0,
+                        count: *count,
+                    }),
+                    Hexpr::Ascription {
+                        hexpr,
+                        sources,
+                        targets,
+                        span,
+                    } => {
+                        tasks.push(Task::Ascribe {
+                            sources,
+                            targets,
+                            span: *span,
+                        });
+                        tasks.push(Task::Interpret(hexpr));
+                    }
+                    _ => {
+                        interfaces.push(self.leaf(hexpr)?);
+                        self.check_limits(hexpr.span())?;
+                    }
+                },
+                Task::Tensor(0) => interfaces.push((vec![], vec![])),
+                Task::Tensor(n) => {
+                    // Append to the first interface in place, so each copy in a power only costs
+                    // its own size
+                    let rest = interfaces.split_off(interfaces.len() - (n - 1));
+                    let (all_sources, all_targets) = interfaces.last_mut().unwrap();
+                    for (sources, targets) in rest {
+                        all_sources.extend(sources);
+                        all_targets.extend(targets);
+                    }
                 }
+                Task::Compose { left, right } => {
+                    let (next_sources, next_targets) = interfaces.pop().unwrap();
+                    let (sources, current_targets) = interfaces.pop().unwrap();
 
-                // Update current interface
-                current_targets = next_targets;
-                hexpr = next_hexpr;
-            }
+                    // Check if targets of current match sources of next
+                    if current_targets.len() != next_sources.len() {
+                        return Err(Error::Composition {
+                            left,
+                            left_arity: current_targets.len(),
+                            right,
+                            right_arity: next_sources.len(),
+                        });
+                    }
 
-            Ok((sources, current_targets))
+                    // Unify targets of current with sources of next
+                    for (&target, &source) in current_targets.iter().zip(&next_sources) {
+                        self.state.unify(target, source);
+                    }
+                    interfaces.push((sources, next_targets));
+                }
+                Task::PushScope(scope) => self.scopes.push(scope),
+                Task::PopScope => self.pop_scope(),
+                Task::Ascribe {
+                    sources,
+                    targets,
+                    span,
+                } => {
+                    let (source_nodes, target_nodes) = interfaces.last().unwrap();
+                    self.check_ports(span, Boundary::Sources, sources, source_nodes)?;
+                    self.check_ports(span, Boundary::Targets, targets, target_nodes)?;
+                }
+                Task::Repeat {
+                    hexpr,
+                    kind,
+                    done,
+                    count,
+                } => {
+                    if done == count {
+                        if count == 0 {
+                            interfaces.push((vec![], vec![]));
+                        }
+                        continue;
+                    }
+                    tasks.push(Task::Repeat {
+                        hexpr,
+                        kind,
+                        done: done + 1,
+                        count,
+                    });
+                    if done > 0 {
+                        tasks.push(match kind {
+                            PowerKind::Tensor => Task::Tensor(2),
+                            PowerKind::Composition => Task::Compose {
+                                left: hexpr.span(),
+                                right: hexpr.span(),
+                            },
+                        });
+                    }
+                    // Each copy has its own variables
+                    tasks.push(Task::PopScope);
+                    tasks.push(Task::Interpret(hexpr));
+                    tasks.push(Task::PushScope(Scope::closed()));
+                }
+            }
         }
-        Hexpr::Tensor(hexprs) => {
-            let mut all_sources = vec![];
-            let mut all_targets = vec![];
+        Ok(interfaces.pop().unwrap())
+    }
 
-            for hexpr in hexprs {
-                let (sources, targets) = try_interpret_stack(signature, state, env, hexpr)?;
-                all_sources.extend(sources);
-                all_targets.extend(targets);
+    /// Interpret a hexpr with no nested hexprs
+    fn leaf(&mut self, hexpr: &Hexpr) -> Result<Interface, Error<S::Error>> {
+        match hexpr {
+            Hexpr::Operation(op) => {
+                if let Some(interface) = self.definitions.inline(self, op) {
+                    return Ok(interface);
+                }
+                if let Some(builtin) = Builtin::parse(op) {
+                    let builtin = builtin.map_err(|message| Error::Builtin(op.clone(), message))?;
+                    // Builtins like `id<n>` can be arbitrarily large, so check before building
+                    let nodes = self.state.hypergraph.nodes.len();
+                    let max = self.options.max_nodes;
+                    LimitExceeded::check(Limit::Nodes, nodes.saturating_add(builtin.width()), max)
+                        .map_err(|exceeded| Error::Limit {
+                            exceeded,
+                            span: op.span(),
+                        })?;
+                    return Ok(self.builtin(&builtin, op.span()));
+                }
+                let arr: S::Arr = self
+                    .signature
+                    .try_parse_op(op)
+                    .map_err(|e| Error::Signature(op.clone(), e))?;
+                Ok(self.operation(arr, op.span()))
+            }
+            Hexpr::Literal(literal) => {
+                let arr = self
+                    .signature
+                    .try_parse_literal(literal)
+                    .map_err(|e| Error::Literal(literal.clone(), e))?;
+                Ok(self.operation(arr, literal.span()))
+            }
+            Hexpr::Frobenius {
+                sources,
+                targets,
+                identity,
+                ..
+            } => {
+                let source_nodes = self.process_frobenius_variables(sources)?;
+                // The shorthand `[x y]` is an identity, even on anonymous wires. Its targets are
+                // the same occurrences as its sources, so their annotations are already checked.
+                let target_nodes = if *identity {
+                    for (&node, var) in source_nodes.iter().zip(targets) {
+                        self.spans.entry(node).or_default().push(var.span());
+                    }
+                    source_nodes.clone()
+                } else {
+                    self.process_frobenius_variables(targets)?
+                };
+                Ok((source_nodes, target_nodes))
             }
+            Hexpr::Error(span) => Err(Error::Syntax(*span)),
+            x => panic!("unreachable: {:?}", x),
+        }
+    }
+
+    /// Count an interpreted leaf, and check the size of the graph so far
+    fn check_limits(&mut self, span: Span) -> Result<(), Error<S::Error>> {
+        self.operations += 1;
+        let graph = &self.state.hypergraph;
+        let options = self.options;
+        LimitExceeded::check(Limit::Operations, self.operations, options.max_operations)
+            .and(LimitExceeded::check(
+                Limit::Nodes,
+                graph.nodes.len(),
+                options.max_nodes,
+            ))
+            .and(LimitExceeded::check(
+                Limit::Edges,
+                graph.edges.len(),
+                options.max_edges,
+            ))
+            .map_err(|exceeded| Error::Limit { exceeded, span })
+    }
 
-            Ok((all_sources, all_targets))
+    /// Check one side of an ascribed interface: the number of wires, then their types
+    fn check_ports(
+        &mut self,
+        span: Span,
+        boundary: Boundary,
+        ports: &Ports,
+        nodes: &[NodeId],
+    ) -> Result<(), Error<S::Error>> {
+        if ports.len() != nodes.len() {
+            return Err(Error::Ascription {
+                span,
+                boundary,
+                expected: ports.len(),
+                actual: nodes.len(),
+            });
         }
-        Hexpr::Operation(op) => {
-            let arr: S::Arr = signature
-                .try_parse_op(op)
-                .map_err(|e| Error::Signature(op.clone(), e))?;
-            let (s, t) = signature.profile(&arr);
-            let (_, interface) = state.new_operation(arr, s, t);
-            Ok(interface)
+        for (&node, obj) in nodes.iter().zip(ports.objects().unwrap_or_default()) {
+            let label = self.parse_obj(obj)?;
+            self.unify_label(node, label, obj);
         }
-        Hexpr::Frobenius { sources, targets } => {
-            let source_nodes = process_frobenius_variables(sources, env, state);
-            let target_nodes = process_frobenius_variables(targets, env, state);
-            Ok((source_nodes, target_nodes))
+        Ok(())
+    }
+
+    /// Add the nodes of a builtin to the graph, returning its interface
+    fn builtin(&mut self, builtin: &Builtin, span: Span) -> Interface {
+        let (count, sources, targets) = builtin.wiring();
+        let nodes: Vec<NodeId> = (0..count).map(|_| self.state.new_node(None)).collect();
+        for &node in &nodes {
+            self.spans.entry(node).or_default().push(span);
         }
+        let select = |indices: Vec<usize>| indices.into_iter().map(|i| nodes[i]).collect();
+        (select(sources), select(targets))
     }
-}
 
-fn process_frobenius_variables<O, A>(
-    variables: &[Variable],
-    env: &mut HashMap<Variable, NodeId>,
-    state: &mut OpenHypergraph<Option<O>, A>,
-) -> Vec<NodeId> {
-    variables
-        .iter()
-        .map(|var| {
-            if let Some(&existing_node) = env.get(var) {
-                // Variable already exists - reuse its node (creates unification)
-                existing_node
-            } else {
-                // First occurrence of this variable - create new node with None type
-                let new_node = state.new_node(None);
-                env.insert(var.clone(), new_node);
-                new_node
+    /// Add an operation to the graph, returning its interface
+    fn operation(&mut self, arr: S::Arr, span: Span) -> Interface {
+        let (s, t) = self.signature.profile(&arr);
+        let (_, interface) = self.state.new_operation(arr, s, t);
+        for &node in interface.0.iter().chain(&interface.1) {
+            self.spans.entry(node).or_default().push(span);
+        }
+        interface
+    }
+
+    /// End the innermost scope, recording the names of its variables
+    fn pop_scope(&mut self) {
+        let scope = self.scopes.pop().unwrap();
+        for (variable, node) in scope.bindings {
+            if let Some(node) = node {
+                self.names.entry(node).or_default().push(variable);
             }
-        })
-        .collect()
+        }
+    }
+
+    fn process_frobenius_variables(
+        &mut self,
+        variables: &[Variable],
+    ) -> Result<Vec<NodeId>, Error<S::Error>> {
+        variables
+            .iter()
+            .map(|var| {
+                let node = if var.is_anonymous() {
+                    // Anonymous variables are never shared
+                    self.state.new_node(None)
+                } else {
+                    // The innermost scope declaring the variable, or else the innermost closed
+                    // scope
+                    let scope = self
+                        .scopes
+                        .iter_mut()
+                        .rev()
+                        .find(|scope| scope.closed || scope.bindings.contains_key(var))
+                        .unwrap();
+                    let binding = scope.bindings.entry(var.clone()).or_default();
+                    match binding {
+                        // Variable already exists - reuse its node (creates unification)
+                        Some(existing_node) => *existing_node,
+                        // First occurrence of this variable - create new node with None type
+                        None => *binding.insert(self.state.new_node(None)),
+                    }
+                };
+                self.spans.entry(node).or_default().push(var.span());
+                if let Some(obj) = var.annotation() {
+                    self.annotate(node, obj)?;
+                }
+                Ok(node)
+            })
+            .collect()
+    }
+
+    /// Give a node the type in an annotation
+    fn annotate(&mut self, node: NodeId, obj: &Object) -> Result<(), Error<S::Error>> {
+        let label = self.parse_obj(obj)?;
+        match &mut self.state.hypergraph.nodes[node.0] {
+            unlabelled @ None => *unlabelled = Some(label),
+            Some(_) => self.unify_label(node, label, obj),
+        }
+        Ok(())
+    }
+
+    fn parse_obj(&self, obj: &Object) -> Result<S::Obj, Error<S::Error>> {
+        self.signature
+            .try_parse_obj(obj)
+            .ok_or_else(|| Error::Object(obj.clone()))
+    }
+
+    /// Unify a node with a new node of the type `obj`, so a mismatch is reported by unification
+    /// at `obj`.
+    fn unify_label(&mut self, node: NodeId, label: S::Obj, obj: &Object) {
+        let annotated = self.state.new_node(Some(label));
+        self.state.unify(node, annotated);
+        self.spans.entry(annotated).or_default().push(obj.span());
+    }
 }
@@ -0,0 +1,197 @@
+//! Signatures defined in JSON, so that hexprs can be interpreted without writing Rust.
+//!
+//! A signature file maps each operation name to the objects of its inputs and outputs:
+//!
+//! ```json
+//! {
+//!   "add": { "inputs": ["ℝ", "ℝ"], "outputs": ["ℝ"] },
+//!   "nat/zero": { "inputs": [], "outputs": ["ℕ"] }
+//! }
+//! ```
+//!
+//! Literals are looked up by their text, so `"1": { "inputs": [], "outputs": ["ℝ"] }` makes
+//! `1` a constant. The objects of a [`JsonSignature`] are those used by its operations.
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::ast::{Object, Operation};
+use crate::decompile::Notation;
+use crate::interpret::Signature;
+
+/// A signature read from JSON, like the `signature.json` in this repository.
+///
+/// Arrows are the [`Operation`]s themselves, and objects are their names.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JsonSignature {
+    operations: HashMap<String, (Vec<String>, Vec<String>)>,
+    objects: HashSet<String>,
+}
+
+/// Why a signature couldn't be loaded
+#[derive(Debug, Error)]
+pub enum JsonSignatureError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// The JSON is well-formed but isn't an object of operations
+    #[error("expected an object mapping operation names to types")]
+    NotAnObject,
+    /// The entry for an operation is malformed
+    #[error("operation `{op}`: {message}")]
+    Invalid { op: String, message: String },
+}
+
+/// Why an operation isn't in a [`JsonSignature`]
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum JsonOpError {
+    #[error("not in the signature")]
+    Unknown,
+    #[error("operations in a JSON signature take no parameters")]
+    Params,
+}
+
+impl JsonSignature {
+    /// Read a signature from a JSON file
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, JsonSignatureError> {
+        std::fs::read_to_string(path)?.parse()
+    }
+
+    /// Read a signature from a JSON object of operations
+    pub fn from_value(value: &Value) -> Result<Self, JsonSignatureError> {
+        let Value::Object(entries) = value else {
+            return Err(JsonSignatureError::NotAnObject);
+        };
+        let mut signature = JsonSignature::default();
+        for (name, entry) in entries {
+            let Value::Object(fields) = entry else {
+                return Err(invalid(
+                    name,
+                    "expected `inputs` and `outputs` lists".to_string(),
+                ));
+            };
+            if let Some(field) = fields
+                .keys()
+                .find(|&key| key != "inputs" && key != "outputs")
+            {
+                return Err(invalid(name, format!("unexpected field `{}`", field)));
+            }
+            let inputs = objects(name, fields.get("inputs"), "inputs")?;
+            let outputs = objects(name, fields.get("outputs"), "outputs")?;
+            signature
+                .objects
+                .extend(inputs.iter().chain(&outputs).cloned());
+            signature.operations.insert(name.clone(), (inputs, outputs));
+        }
+        Ok(signature)
+    }
+
+    /// The names of the operations, in no particular order
+    pub fn operations(&self) -> impl Iterator<Item = &str> {
+        self.operations.keys().map(String::as_str)
+    }
+
+    /// The input and output objects of an operation
+    pub fn operation(&self, name: &str) -> Option<(&[String], &[String])> {
+        let (inputs, outputs) = self.operations.get(name)?;
+        Some((inputs, outputs))
+    }
+}
+
+impl std::str::FromStr for JsonSignature {
+    type Err = JsonSignatureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        JsonSignature::from_value(&serde_json::from_str(s)?)
+    }
+}
+
+impl TryFrom<Value> for JsonSignature {
+    type Error = JsonSignatureError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        JsonSignature::from_value(&value)
+    }
+}
+
+impl Signature for JsonSignature {
+    type Arr = Operation;
+    type Obj = String;
+    type Error = JsonOpError;
+
+    fn try_parse_op(&self, op: &Operation) -> Result<Self::Arr, Self::Error> {
+        if !self.operations.contains_key(op.as_str()) {
+            return Err(JsonOpError::Unknown);
+        }
+        if !op.params().is_empty() {
+            return Err(JsonOpError::Params);
+        }
+        Ok(op.clone())
+    }
+
+    fn profile(&self, op: &Self::Arr) -> (Vec<Option<Self::Obj>>, Vec<Option<Self::Obj>>) {
+        let (inputs, outputs) = &self.operations[op.as_str()];
+        let known = |objects: &[String]| objects.iter().cloned().map(Some).collect();
+        (known(inputs), known(outputs))
+    }
+
+    fn try_parse_obj(&self, obj: &Object) -> Option<Self::Obj> {
+        self.objects
+            .contains(obj.as_str())
+            .then(|| obj.as_str().to_string())
+    }
+}
+
+impl Notation for JsonSignature {
+    type Arr = Operation;
+    type Obj = String;
+
+    fn operation(&self, arr: &Self::Arr) -> Operation {
+        arr.clone()
+    }
+
+    /// Objects of this signature, which were checked to be valid names when it was loaded.
+    /// Any other object is left unannotated, as [`Signature::try_parse_obj`] would reject it.
+    fn object(&self, obj: &Self::Obj) -> Option<Object> {
+        self.objects.contains(obj).then(|| {
+            obj.parse()
+                .expect("signature objects are checked when loaded")
+        })
+    }
+}
+
+fn invalid(op: &str, message: String) -> JsonSignatureError {
+    JsonSignatureError::Invalid {
+        op: op.to_string(),
+        message,
+    }
+}
+
+/// The `inputs` or `outputs` of an operation: a list of object names
+fn objects(op: &str, list: Option<&Value>, field: &str) -> Result<Vec<String>, JsonSignatureError> {
+    let Some(list) = list else {
+        return Err(invalid(op, format!("missing `{}`", field)));
+    };
+    let Value::Array(items) = list else {
+        let message = format!("`{}` must be a list of object names", field);
+        return Err(invalid(op, message));
+    };
+    items
+        .iter()
+        .map(|item| match item.as_str() {
+            Some(name) if is_object(name) => Ok(name.to_string()),
+            _ => {
+                let message = format!("`{}` contains {}, which is not an object name", field, item);
+                Err(invalid(op, message))
+            }
+        })
+        .collect()
+}
+
+/// Whether `name` can be written as an object in annotations and ascriptions
+fn is_object(name: &str) -> bool {
+    name.parse::<Object>().is_ok_and(|obj| obj.as_str() == name)
+}
@@ -0,0 +1,50 @@
+//! Arranging the edges of a hypergraph in layers, for drawing and decompiling it.
+use std::collections::VecDeque;
+
+/// The layer of each edge, given the edges each depends on: one more than the deepest of them.
+///
+/// Edges are visited in topological order; a cycle is broken at its first edge, which is then
+/// placed after only the dependencies placed so far.
+pub fn layers(dependencies: impl Iterator<Item = Vec<usize>>) -> Vec<usize> {
+    let dependencies: Vec<Vec<usize>> = dependencies.collect();
+    let edges = dependencies.len();
+    let mut dependents = vec![vec![]; edges];
+    let mut waiting = vec![0; edges];
+    for (e, ds) in dependencies.iter().enumerate() {
+        for &d in ds.iter().filter(|&&d| d != e) {
+            dependents[d].push(e);
+            waiting[e] += 1;
+        }
+    }
+    let mut layer = vec![0; edges];
+    let mut placed = vec![false; edges];
+    let mut queue: VecDeque<usize> = (0..edges).filter(|&e| waiting[e] == 0).collect();
+    let mut unplaced = 0;
+    loop {
+        while let Some(e) = queue.pop_front() {
+            if placed[e] {
+                continue;
+            }
+            placed[e] = true;
+            layer[e] = 1 + dependencies[e]
+                .iter()
+                .filter(|&&d| d != e && placed[d])
+                .map(|&d| layer[d])
+                .max()
+                .unwrap_or(0);
+            for &d in &dependents[e] {
+                waiting[d] -= 1;
+                if waiting[d] == 0 {
+                    queue.push_back(d);
+                }
+            }
+        }
+        while unplaced < edges && placed[unplaced] {
+            unplaced += 1;
+        }
+        if unplaced == edges {
+            return layer;
+        }
+        queue.push_back(unplaced);
+    }
+}
@@ -1,9 +1,36 @@
 pub mod ast;
+pub mod builtin;
+pub mod cst;
+pub mod decompile;
+pub mod diagnostic;
+pub mod dot;
+pub mod hygiene;
 pub mod interpret;
+pub mod json;
+mod layer;
+pub mod limit;
 pub mod parser;
+pub mod pretty;
+pub mod span;
+pub mod svg;
+pub mod template;
 pub mod unify;
 
-pub use ast::{Hexpr, Operation, Variable};
-pub use interpret::{try_interpret, try_interpret_with_names, OpenHypergraphWithNames, Signature};
-pub use parser::{parse_hexprs, ParseError};
+pub use ast::{
+    Definition, Hexpr, Item, Literal, LiteralValue, Object, Operation, Param, Pattern, Ports,
+    Program, Template, Value, Variable,
+};
+pub use decompile::Notation;
+pub use diagnostic::Diagnostic;
+pub use dot::DotOptions;
+pub use interpret::{
+    try_interpret, try_interpret_program, try_interpret_program_with_options,
+    try_interpret_with_names, try_interpret_with_options, InterpretOptions,
+    OpenHypergraphWithNames, Signature,
+};
+pub use json::JsonSignature;
+pub use parser::{parse_hexprs, parse_hexprs_recovering, parse_program, ParseError, ParseOptions};
+pub use pretty::PrettyOptions;
+pub use span::Span;
+pub use svg::SvgOptions;
 pub use unify::unify;
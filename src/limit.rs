@@ -0,0 +1,58 @@
+//! Resource limits, for parsing and interpreting hexprs from untrusted sources.
+//!
+//! By default nothing is limited. [`ParseOptions`] bound the size of the syntax, and
+//! [`InterpretOptions`] the size of the open hypergraph it produces, since a short hexpr like
+//! `{f * 1000000000}` can describe a huge one. Exceeding a limit is reported as a
+//! [`LimitExceeded`] error rather than by running out of memory.
+//!
+//! [`ParseOptions`]: crate::parser::ParseOptions
+//! [`InterpretOptions`]: crate::interpret::InterpretOptions
+use thiserror::Error;
+
+/// A quantity which can be limited
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Limit {
+    /// Bytes of input
+    InputLength,
+    /// Brackets nested inside each other
+    Depth,
+    /// Operations and literals, or when interpreting, also Frobenius brackets
+    Operations,
+    /// Distinct variable names
+    Variables,
+    /// Nodes of the open hypergraph
+    Nodes,
+    /// Edges of the open hypergraph
+    Edges,
+}
+
+/// A limit which was exceeded, and its maximum
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub struct LimitExceeded {
+    pub limit: Limit,
+    pub max: usize,
+}
+
+impl LimitExceeded {
+    /// Check that `count` is within the maximum for `limit`
+    pub(crate) fn check(limit: Limit, count: usize, max: usize) -> Result<(), LimitExceeded> {
+        match count > max {
+            true => Err(LimitExceeded { limit, max }),
+            false => Ok(()),
+        }
+    }
+}
+
+impl std::fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let max = self.max;
+        match self.limit {
+            Limit::InputLength => write!(f, "input is longer than {} bytes", max),
+            Limit::Depth => write!(f, "brackets are nested more than {} deep", max),
+            Limit::Operations => write!(f, "more than {} operations", max),
+            Limit::Variables => write!(f, "more than {} distinct variables", max),
+            Limit::Nodes => write!(f, "more than {} nodes", max),
+            Limit::Edges => write!(f, "more than {} edges", max),
+        }
+    }
+}
@@ -0,0 +1,380 @@
+//! The `hexpr` command-line tool: parse, pretty-print, check and emit hexprs.
+//!
+//! Every subcommand reads a program, which is a sequence of hexprs and definitions, from its
+//! argument, a file, or stdin. Failures are reported as diagnostics on stderr, and by the exit
+//! status, so the tool can be used in CI.
+use std::io::{ErrorKind, IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use open_hypergraphs::lax::{NodeId, OpenHypergraph};
+use serde_json::{json, Value};
+
+use hexpr::diagnostic::{Diagnostic, RenderOptions};
+use hexpr::dot::{Direction, DotOptions};
+use hexpr::{cst, try_interpret_program, JsonSignature, OpenHypergraphWithNames, PrettyOptions};
+use hexpr::{svg, Operation, Program, SvgOptions};
+
+#[derive(Parser)]
+#[command(
+    name = "hexpr",
+    version,
+    about = "Parse, check and emit hexprs",
+    after_help = "Exits with status 0 on success, 1 if the input is not a valid program, \
+                  and 2 for usage errors and unreadable files."
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    /// Only report failure by the exit status, without printing diagnostics
+    #[arg(short, long, global = true)]
+    quiet: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Parse a program and print each of its items on one line
+    Parse(Input),
+    /// Print a program laid out to fit a line width, keeping its comments
+    Pretty {
+        #[command(flatten)]
+        input: Input,
+        /// The line width to fit within, where possible
+        #[arg(short, long, default_value_t = 80)]
+        width: usize,
+    },
+    /// Interpret and unify each hexpr, and print its interface `sources -> targets`
+    Check {
+        #[command(flatten)]
+        input: Input,
+        #[command(flatten)]
+        signature: SignatureFile,
+    },
+    /// Interpret and unify each hexpr, and print its open hypergraph
+    Unify {
+        #[command(flatten)]
+        input: Input,
+        #[command(flatten)]
+        signature: SignatureFile,
+        #[command(flatten)]
+        output: Output,
+    },
+    /// Interpret each hexpr, and print its open hypergraph before unification
+    Emit {
+        #[command(flatten)]
+        input: Input,
+        #[command(flatten)]
+        signature: SignatureFile,
+        #[command(flatten)]
+        output: Output,
+    },
+}
+
+/// Where to read the program from
+#[derive(Args)]
+struct Input {
+    /// The program to read, instead of a file or stdin
+    program: Option<String>,
+    /// Read the program from a file, or `-` for stdin
+    #[arg(short, long, conflicts_with = "program")]
+    file: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct SignatureFile {
+    /// A JSON signature giving the types of operations, like `signature.json`
+    #[arg(short, long)]
+    signature: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct Output {
+    /// How to print open hypergraphs
+    #[arg(long, value_enum, default_value_t = Format::Json)]
+    format: Format,
+    /// Draw DOT from top to bottom, rather than left to right
+    #[arg(long)]
+    top_to_bottom: bool,
+    /// Draw nodes identified by the quotient as one node in DOT
+    #[arg(long)]
+    quotient: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// One JSON object per line, with nodes and edges numbered from 0
+    Json,
+    /// Graphviz DOT, with a `digraph` per hexpr
+    Dot,
+    /// An SVG string diagram per hexpr
+    Svg,
+}
+
+/// Why the tool failed, rendered for stderr
+enum Failure {
+    /// The input isn't a valid program
+    Invalid(String),
+    /// The tool was used wrongly, or a file couldn't be read or written
+    Usage(String),
+}
+
+/// Why a subcommand stopped early
+enum Stop {
+    /// The input isn't a valid program
+    Invalid(Diagnostic),
+    /// Printing to stdout failed
+    Write(std::io::Error),
+}
+
+impl From<Diagnostic> for Stop {
+    fn from(diagnostic: Diagnostic) -> Self {
+        Stop::Invalid(diagnostic)
+    }
+}
+
+impl From<std::io::Error> for Stop {
+    fn from(error: std::io::Error) -> Self {
+        Stop::Write(error)
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(&cli.command) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => {
+            let (message, status) = match failure {
+                Failure::Invalid(message) => (message, 1),
+                Failure::Usage(message) => (message, 2),
+            };
+            if !cli.quiet {
+                eprint!("{}", message);
+            }
+            ExitCode::from(status)
+        }
+    }
+}
+
+fn run(command: &Command) -> Result<(), Failure> {
+    if let Command::Unify { output, .. } | Command::Emit { output, .. } = command {
+        output.check()?;
+    }
+    let input = match command {
+        Command::Parse(input)
+        | Command::Pretty { input, .. }
+        | Command::Check { input, .. }
+        | Command::Unify { input, .. }
+        | Command::Emit { input, .. } => input,
+    };
+    let (name, source) = input.read()?;
+    let mut out = std::io::stdout().lock();
+    let result = match command {
+        Command::Parse(_) => parse(&source, &mut out),
+        Command::Pretty { width, .. } => pretty(&source, *width, &mut out),
+        Command::Check { signature, .. } => check(&source, &signature.load()?, &mut out),
+        Command::Unify {
+            signature, output, ..
+        } => unify(&source, &signature.load()?, output, &mut out),
+        Command::Emit {
+            signature, output, ..
+        } => emit(&source, &signature.load()?, output, &mut out),
+    };
+    match result.and_then(|()| Ok(out.flush()?)) {
+        Ok(()) => Ok(()),
+        // Whatever reads the output has stopped early, like `head`
+        Err(Stop::Write(e)) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+        Err(Stop::Write(e)) => Err(Failure::Usage(format!(
+            "error: can't write stdout: {}\n",
+            e
+        ))),
+        Err(Stop::Invalid(diagnostic)) => {
+            let options = RenderOptions {
+                file_name: Some(name),
+                color: std::io::stderr().is_terminal(),
+            };
+            Err(Failure::Invalid(diagnostic.render(&source, &options)))
+        }
+    }
+}
+
+impl Input {
+    /// The name of the input, for diagnostics, and its text
+    fn read(&self) -> Result<(String, String), Failure> {
+        if let Some(program) = &self.program {
+            return Ok(("input".to_string(), program.clone()));
+        }
+        let mut source = String::new();
+        match &self.file {
+            Some(path) if path.as_os_str() != "-" => {
+                let name = path.display().to_string();
+                std::fs::File::open(path)
+                    .and_then(|mut file| file.read_to_string(&mut source))
+                    .map_err(|e| Failure::Usage(format!("error: can't read {}: {}\n", name, e)))?;
+                Ok((name, source))
+            }
+            _ => {
+                std::io::stdin()
+                    .read_to_string(&mut source)
+                    .map_err(|e| Failure::Usage(format!("error: can't read stdin: {}\n", e)))?;
+                Ok(("stdin".to_string(), source))
+            }
+        }
+    }
+}
+
+impl Output {
+    /// Fail if options only for DOT are given with another format
+    fn check(&self) -> Result<(), Failure> {
+        let dot_only = [
+            ("--top-to-bottom", self.top_to_bottom),
+            ("--quotient", self.quotient),
+        ];
+        match dot_only.iter().find(|(_, given)| *given) {
+            Some((flag, _)) if self.format != Format::Dot => Err(Failure::Usage(format!(
+                "error: {} only applies to --format dot\n",
+                flag
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl SignatureFile {
+    /// The signature, or an empty one if no file was given
+    fn load(&self) -> Result<JsonSignature, Failure> {
+        let Some(path) = &self.signature else {
+            return Ok(JsonSignature::default());
+        };
+        JsonSignature::from_file(path).map_err(|e| {
+            Failure::Usage(format!(
+                "error: can't load the signature {}: {}\n",
+                path.display(),
+                e
+            ))
+        })
+    }
+}
+
+fn parse(source: &str, out: &mut impl Write) -> Result<(), Stop> {
+    let program: Program = source.parse().map_err(Diagnostic::from)?;
+    let written = write!(out, "{}", program);
+    program.drop_iteratively();
+    Ok(written?)
+}
+
+fn pretty(source: &str, width: usize, out: &mut impl Write) -> Result<(), Stop> {
+    let node = cst::parse_program(source).map_err(Diagnostic::from)?;
+    let options = PrettyOptions {
+        width,
+        ..PrettyOptions::default()
+    };
+    write!(out, "{}", node.pretty(&options))?;
+    Ok(())
+}
+
+fn check(source: &str, signature: &JsonSignature, out: &mut impl Write) -> Result<(), Stop> {
+    for graph in interpret(source, signature)? {
+        let graph = graph.unify().map_err(Diagnostic::from)?.graph;
+        let objects = |nodes: &[NodeId]| -> Vec<String> {
+            nodes
+                .iter()
+                .map(|node| graph.hypergraph.nodes[node.0].clone())
+                .collect()
+        };
+        let (sources, targets) = (objects(&graph.sources), objects(&graph.targets));
+        let interface = [sources.join(" "), "->".to_string(), targets.join(" ")];
+        let interface: Vec<_> = interface.into_iter().filter(|s| !s.is_empty()).collect();
+        writeln!(out, "{}", interface.join(" "))?;
+    }
+    Ok(())
+}
+
+fn unify(
+    source: &str,
+    signature: &JsonSignature,
+    output: &Output,
+    out: &mut impl Write,
+) -> Result<(), Stop> {
+    for graph in interpret(source, signature)? {
+        let graph = graph.unify().map_err(Diagnostic::from)?;
+        let graph = OpenHypergraphWithNames {
+            graph: graph.graph.map_nodes(Some),
+            names: graph.names,
+            spans: graph.spans,
+        };
+        print_graph(graph, output, out)?;
+    }
+    Ok(())
+}
+
+fn emit(
+    source: &str,
+    signature: &JsonSignature,
+    output: &Output,
+    out: &mut impl Write,
+) -> Result<(), Stop> {
+    for graph in interpret(source, signature)? {
+        print_graph(graph, output, out)?;
+    }
+    Ok(())
+}
+
+/// The open hypergraph of each hexpr in a program
+fn interpret(
+    source: &str,
+    signature: &JsonSignature,
+) -> Result<Vec<OpenHypergraphWithNames<Option<String>, Operation>>, Diagnostic> {
+    let program: Program = source.parse()?;
+    let graphs = try_interpret_program(signature, &program);
+    program.drop_iteratively();
+    Ok(graphs?)
+}
+
+/// Print an open hypergraph, whose nodes have no label if their type is unknown
+fn print_graph(
+    graph: OpenHypergraphWithNames<Option<String>, Operation>,
+    output: &Output,
+    out: &mut impl Write,
+) -> std::io::Result<()> {
+    match output.format {
+        Format::Json => writeln!(out, "{}", to_json(&graph.graph.map_nodes(Value::from))),
+        Format::Dot => {
+            let options = DotOptions {
+                direction: match output.top_to_bottom {
+                    true => Direction::TopToBottom,
+                    false => Direction::LeftToRight,
+                },
+                quotient: output.quotient,
+            };
+            write!(out, "{}", graph.to_dot(&options))
+        }
+        Format::Svg => write!(out, "{}", svg::to_svg(&graph.graph, &SvgOptions::default())),
+    }
+}
+
+fn to_json(graph: &OpenHypergraph<Value, Operation>) -> Value {
+    let ids = |nodes: &[NodeId]| -> Vec<usize> { nodes.iter().map(|node| node.0).collect() };
+    let hypergraph = &graph.hypergraph;
+    let edges: Vec<Value> = hypergraph
+        .edges
+        .iter()
+        .zip(&hypergraph.adjacency)
+        .map(|(op, edge)| {
+            json!({
+                "label": op.to_string(),
+                "sources": ids(&edge.sources),
+                "targets": ids(&edge.targets),
+            })
+        })
+        .collect();
+    let (left, right) = &hypergraph.quotient;
+    let quotient: Vec<[usize; 2]> = left.iter().zip(right).map(|(l, r)| [l.0, r.0]).collect();
+    json!({
+        "sources": ids(&graph.sources),
+        "targets": ids(&graph.targets),
+        "nodes": hypergraph.nodes,
+        "edges": edges,
+        "quotient": quotient,
+    })
+}
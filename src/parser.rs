@@ -1,67 +1,914 @@
-use crate::ast::{Hexpr, Operation, Variable};
+use crate::ast::{
+    infinite_number, operation_name, Definition, Hexpr, Item, Literal, Object, Operation, Pattern,
+    Ports, PowerKind, Program, Template, Variable,
+};
+use crate::limit::{Limit, LimitExceeded};
+use crate::span::{LineIndex, Span};
+use pest::error::{ErrorVariant, InputLocation, LineColLocation};
+use pest::iterators::Pair;
 use pest::Parser;
 use pest_derive::Parser;
+use std::collections::HashSet;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
-#[error(transparent)]
-pub struct ParseError(#[from] pest::error::Error<Rule>);
+pub enum ParseError {
+    #[error(transparent)]
+    Syntax(#[from] pest::error::Error<Rule>),
+    /// The input exceeds one of the [`ParseOptions`]
+    #[error("{1}: {0}")]
+    Limit(LimitExceeded, Span),
+}
+
+impl ParseError {
+    /// Where in the input parsing failed
+    pub fn span(&self) -> Span {
+        let error = match self {
+            ParseError::Syntax(error) => error,
+            ParseError::Limit(_, span) => return *span,
+        };
+        let (start, end) = match error.location {
+            InputLocation::Pos(pos) => (pos, pos),
+            InputLocation::Span(span) => span,
+        };
+        let (line, column) = match error.line_col {
+            LineColLocation::Pos(pos) | LineColLocation::Span(pos, _) => pos,
+        };
+        Span {
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+
+    /// A description of the error, without location information
+    pub fn message(&self) -> String {
+        match self {
+            ParseError::Syntax(error) => error.variant.message().into_owned(),
+            ParseError::Limit(exceeded, _) => exceeded.to_string(),
+        }
+    }
+}
+
+/// Limits on the input to [`HExprParser`], for parsing untrusted hexprs. Nothing is limited by
+/// default.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// The maximum length of the input in bytes
+    pub max_input_len: usize,
+    /// How deeply brackets may be nested: `f` has depth 0 and `(f {g})` has depth 2
+    pub max_depth: usize,
+    /// The maximum number of operations and literals
+    pub max_operations: usize,
+    /// The maximum number of distinct variable names, not counting `_`
+    pub max_variables: usize,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            max_input_len: usize::MAX,
+            max_depth: usize::MAX,
+            max_operations: usize::MAX,
+            max_variables: usize::MAX,
+        }
+    }
+}
 
 /// Parse multiple H-expressions from a string
 pub fn parse_hexprs(input: &str) -> Result<Vec<Hexpr>, ParseError> {
     HExprParser::parse_hexprs(input)
 }
 
+/// Parse a program of definitions and H-expressions from a string
+pub fn parse_program(input: &str) -> Result<Program, ParseError> {
+    HExprParser::parse_program(input)
+}
+
+/// Parse multiple H-expressions from a string, recovering from syntax errors.
+///
+/// See [`HExprParser::parse_hexprs_recovering`].
+pub fn parse_hexprs_recovering(input: &str) -> (Vec<Hexpr>, Vec<ParseError>) {
+    HExprParser::parse_hexprs_recovering(input)
+}
+
 #[derive(Parser)]
 #[grammar = "grammar.pest"]
 pub struct HExprParser;
 
 impl HExprParser {
     pub fn parse_hexpr(input: &str) -> Result<Hexpr, ParseError> {
-        let pairs = HExprParser::parse(Rule::one_hexpr, input)?;
-        let one_hexpr = pairs.into_iter().next().unwrap();
-        let expr_pair = one_hexpr.into_inner().next().unwrap();
-        Ok(parse_hexpr(expr_pair))
+        HExprParser::parse_hexpr_with_options(input, &ParseOptions::default())
     }
 
     pub fn parse_hexprs(input: &str) -> Result<Vec<Hexpr>, ParseError> {
-        let pairs = HExprParser::parse(Rule::hexprs, input)?;
-        let hexprs = pairs.into_iter().next().unwrap();
-        Ok(hexprs
-            .into_inner()
-            .filter(|p| p.as_rule() == Rule::hexpr)
-            .map(parse_hexpr)
-            .collect())
+        HExprParser::parse_hexprs_with_options(input, &ParseOptions::default())
+    }
+
+    pub fn parse_program(input: &str) -> Result<Program, ParseError> {
+        HExprParser::parse_program_with_options(input, &ParseOptions::default())
+    }
+
+    /// Parse a single hexpr, failing if it exceeds any of the `options`
+    pub fn parse_hexpr_with_options(
+        input: &str,
+        options: &ParseOptions,
+    ) -> Result<Hexpr, ParseError> {
+        let mut reader = Reader::new(input, options)?;
+        let hexpr = reader
+            .hexpr()
+            .map_err(|e| grammar_error(Rule::one_hexpr, input, e))?;
+        if let Err(e) = reader.end() {
+            hexpr.drop_iteratively();
+            return Err(grammar_error(Rule::one_hexpr, input, e));
+        }
+        Ok(hexpr)
+    }
+
+    /// Parse multiple hexprs, failing if together they exceed any of the `options`
+    pub fn parse_hexprs_with_options(
+        input: &str,
+        options: &ParseOptions,
+    ) -> Result<Vec<Hexpr>, ParseError> {
+        let mut reader = Reader::new(input, options)?;
+        let mut hexprs = vec![];
+        while !reader.at_end() {
+            match reader.hexpr() {
+                Ok(hexpr) => hexprs.push(hexpr),
+                Err(e) => {
+                    hexprs.into_iter().for_each(Hexpr::drop_iteratively);
+                    return Err(grammar_error(Rule::hexprs, input, e));
+                }
+            }
+        }
+        Ok(hexprs)
+    }
+
+    /// Parse a program, failing if it exceeds any of the `options`
+    pub fn parse_program_with_options(
+        input: &str,
+        options: &ParseOptions,
+    ) -> Result<Program, ParseError> {
+        let mut reader = Reader::new(input, options)?;
+        let mut items = vec![];
+        while !reader.at_end() {
+            match reader.item() {
+                Ok(item) => items.push(item),
+                Err(e) => {
+                    Program { items }.drop_iteratively();
+                    return Err(grammar_error(Rule::program, input, e));
+                }
+            }
+        }
+        Ok(Program { items })
+    }
+
+    /// Parse multiple H-expressions, returning every syntax error instead of stopping at the
+    /// first.
+    ///
+    /// Unclosed brackets are closed at the next mismatched closing bracket (or the end of input),
+    /// and unexpected input is skipped. Skipped input and empty brackets become
+    /// [`Hexpr::Error`] nodes, so the rest of the input still produces a usable tree.
+    ///
+    /// Unlike [`HExprParser::parse_hexprs`], recovery uses the recursive rules of the grammar, so
+    /// it suits hand-written source rather than very deeply nested hexprs.
+    pub fn parse_hexprs_recovering(input: &str) -> (Vec<Hexpr>, Vec<ParseError>) {
+        let hexprs = match HExprParser::parse(Rule::recovering_hexprs, input) {
+            Ok(pairs) => pairs.into_iter().next().unwrap(),
+            Err(e) => return (vec![], vec![e.into()]),
+        };
+        let mut builder = Builder::new(input);
+        let hexprs = builder.group(hexprs);
+        let mut errors = builder.errors;
+        errors.sort_by_key(|e| e.span().start);
+        (hexprs, errors)
     }
 }
 
-fn parse_hexpr(pair: pest::iterators::Pair<Rule>) -> Hexpr {
-    match pair.as_rule() {
-        Rule::hexpr => pair.into_inner().map(parse_hexpr).next().unwrap(),
-        Rule::composition => Hexpr::Composition(pair.into_inner().map(parse_hexpr).collect()),
-        Rule::tensor => Hexpr::Tensor(pair.into_inner().map(parse_hexpr).collect()),
-        Rule::frobenius => parse_frobenius(pair),
-        Rule::operation => Hexpr::Operation(Operation(pair.as_str().to_string())),
-        x => panic!("unreachable: {:?}", x),
+/// Replace a syntax error found by the [`Reader`] with the one from parsing the whole input with
+/// `rule`, which says what was expected and agrees with the [syntax tree](crate::cst).
+///
+/// Pest stops with a custom error rather than overflowing the stack on deeply nested input, and
+/// then the reader's error is kept.
+fn grammar_error(rule: Rule, input: &str, error: ParseError) -> ParseError {
+    let ParseError::Syntax(_) = error else {
+        return error;
+    };
+    match HExprParser::parse(rule, input) {
+        Err(e) if matches!(e.variant, ErrorVariant::ParsingError { .. }) => e.into(),
+        _ => error,
     }
 }
 
-fn parse_frobenius(pair: pest::iterators::Pair<Rule>) -> Hexpr {
-    let mut it = pair.into_inner();
-    let sources = parse_vars(it.next().unwrap());
-    let targets = it.next().map(parse_vars).unwrap_or_else(|| sources.clone());
-    Hexpr::Frobenius { sources, targets }
+/// Reads hexprs with an explicit stack of open brackets rather than recursion, so that deeply
+/// nested input doesn't overflow the call stack.
+///
+/// The brackets are matched here, following the `hexpr` rule of the grammar. Everything
+/// between them (operations, literals, Frobenius brackets, ascriptions and so on) is parsed
+/// with its grammar rule.
+struct Reader<'a, 'o> {
+    input: &'a str,
+    /// Byte offset of the next unread character
+    pos: usize,
+    builder: Builder<'a>,
+    options: &'o ParseOptions,
+    /// Operations and literals read so far
+    operations: usize,
+    /// Variable names read so far
+    variables: HashSet<&'a str>,
 }
 
-fn parse_vars(pair: pest::iterators::Pair<Rule>) -> Vec<Variable> {
-    debug_assert_eq!(pair.as_rule(), Rule::vars);
-    pair.into_inner().map(parse_variable).collect()
+/// A bracket which has been opened but not yet closed
+struct Open {
+    kind: OpenKind,
+    /// Where the opening bracket is, computed when reading it since the column of each later
+    /// position is found incrementally
+    start: Span,
+    hexprs: Vec<Hexpr>,
+    /// A power or ascription, after which the bracket must close
+    power: Option<(PowerKind, usize)>,
+    ascription: Option<(Ports, Ports)>,
 }
 
-fn parse_variable(pair: pest::iterators::Pair<Rule>) -> Variable {
-    match pair.as_rule() {
-        Rule::variable => Variable(pair.as_str().to_string()),
-        x => panic!("unreachable: {:?}", x),
+enum OpenKind {
+    Composition,
+    Tensor,
+    Scope(Vec<Variable>),
+}
+
+/// The name and parameters of a definition, read before its body
+enum Head {
+    Definition(Operation),
+    Template(Operation, Vec<Pattern>),
+}
+
+impl<'a, 'o> Reader<'a, 'o> {
+    fn new(input: &'a str, options: &'o ParseOptions) -> Result<Self, ParseError> {
+        let reader = Reader {
+            input,
+            pos: 0,
+            builder: Builder::new(input),
+            options,
+            operations: 0,
+            variables: HashSet::new(),
+        };
+        if input.len() > options.max_input_len {
+            // Point at the character which crosses the limit
+            let mut pos = options.max_input_len;
+            while !input.is_char_boundary(pos) {
+                pos -= 1;
+            }
+            let at = reader.builder.lines.span(pos, input.len());
+            reader.check(Limit::InputLength, input.len(), options.max_input_len, at)?;
+        }
+        Ok(reader)
+    }
+
+    /// Skip whitespace and comments, returning whether there is no more input
+    fn at_end(&mut self) -> bool {
+        self.skip_trivia();
+        self.pos == self.input.len()
+    }
+
+    fn end(&mut self) -> Result<(), ParseError> {
+        match self.at_end() {
+            true => Ok(()),
+            false => Err(self.error("expected end of input")),
+        }
+    }
+
+    /// A definition or hexpr at the top level of a program
+    fn item(&mut self) -> Result<Item, ParseError> {
+        let start = self.builder.lines.span(self.pos, self.pos);
+        let Some(after) = self.keyword_after_paren("def") else {
+            return Ok(Item::Hexpr(self.hexpr()?));
+        };
+        self.pos = self.trivia_end(after);
+        let head = if let Some(head) = self.leaf(Rule::template_head) {
+            self.builder.template_head(head)
+        } else if let Some(name) = self.leaf(Rule::operation) {
+            Head::Definition(self.builder.operation(name))
+        } else {
+            return Err(self.error("expected a name or template head"));
+        };
+        self.built()?;
+        let body = self.hexpr()?;
+        self.skip_trivia();
+        if !self.input[self.pos..].starts_with(')') {
+            body.drop_iteratively();
+            return Err(self.error("expected `)`"));
+        }
+        self.pos += 1;
+        let span = Span {
+            end: self.pos,
+            ..start
+        };
+        Ok(match head {
+            Head::Definition(name) => Item::Definition(Definition { name, body, span }),
+            Head::Template(name, params) => Item::Template(Template {
+                name,
+                params,
+                body,
+                span,
+            }),
+        })
+    }
+
+    /// Read one hexpr
+    fn hexpr(&mut self) -> Result<Hexpr, ParseError> {
+        let mut stack: Vec<Open> = vec![];
+        let result = self.read_hexpr(&mut stack);
+        // After an error, the hexprs read so far may be deeply nested
+        for open in stack {
+            open.hexprs.into_iter().for_each(Hexpr::drop_iteratively);
+        }
+        result
+    }
+
+    /// Read one hexpr, keeping the brackets opened but not yet closed on `stack`
+    fn read_hexpr(&mut self, stack: &mut Vec<Open>) -> Result<Hexpr, ParseError> {
+        loop {
+            self.skip_trivia();
+            if let Some(open) = stack.last_mut() {
+                if !open.is_ended() && !self.closes(open) && self.group_end(open) {
+                    continue;
+                }
+            }
+            let hexpr = match stack.last() {
+                Some(open) if open.is_ended() || self.closes(open) => {
+                    let open = stack.pop().unwrap();
+                    self.close(open)?
+                }
+                _ => match self.open()? {
+                    Some(open) => {
+                        let (depth, at) = (stack.len() + 1, open.start);
+                        self.check(Limit::Depth, depth, self.options.max_depth, at)?;
+                        stack.push(open);
+                        continue;
+                    }
+                    None => self.leaf_hexpr(!stack.is_empty())?,
+                },
+            };
+            match stack.last_mut() {
+                Some(open) => open.hexprs.push(hexpr),
+                None => return Ok(hexpr),
+            }
+        }
+    }
+
+    /// Open a bracket, if there is one
+    fn open(&mut self) -> Result<Option<Open>, ParseError> {
+        let start = self.pos;
+        let span = self.builder.lines.span(start, start);
+        let kind = if let Some(after) = self.keyword_after_paren("let") {
+            self.pos = self.trivia_end(after);
+            let binder = self
+                .leaf(Rule::binder)
+                .ok_or_else(|| self.error("expected variables in `[...]`"))?;
+            let variables: Vec<Variable> = binder
+                .into_inner()
+                .map(|p| self.builder.variable(p))
+                .collect();
+            self.count_variables(&variables)?;
+            OpenKind::Scope(variables)
+        } else if self.input[start..].starts_with('(') {
+            self.pos += 1;
+            OpenKind::Composition
+        } else if self.input[start..].starts_with('{') {
+            self.pos += 1;
+            OpenKind::Tensor
+        } else {
+            return Ok(None);
+        };
+        Ok(Some(Open {
+            kind,
+            start: span,
+            hexprs: vec![],
+            power: None,
+            ascription: None,
+        }))
+    }
+
+    /// A hexpr with no nested hexprs
+    fn leaf_hexpr(&mut self, in_group: bool) -> Result<Hexpr, ParseError> {
+        let rest = &self.input[self.pos..];
+        // Only try rules which can match, since a failed parse is relatively expensive
+        let pair = if rest.starts_with('[') {
+            self.leaf(Rule::frobenius)
+        } else if rest.starts_with(|c: char| c == '"' || c == '-' || c.is_ascii_digit()) {
+            self.leaf(Rule::literal)
+                .or_else(|| self.leaf(Rule::operation))
+        } else {
+            self.leaf(Rule::operation)
+        };
+        let hexpr = match pair {
+            Some(pair) => self.builder.hexpr(pair),
+            None if in_group => return Err(self.error("expected hexpr or closing bracket")),
+            None => return Err(self.error("expected hexpr")),
+        };
+        self.built()?;
+        match &hexpr {
+            Hexpr::Operation(_) | Hexpr::Literal(_) => {
+                self.operations += 1;
+                let max = self.options.max_operations;
+                self.check(Limit::Operations, self.operations, max, hexpr.span())?;
+            }
+            Hexpr::Frobenius {
+                sources, targets, ..
+            } => {
+                self.count_variables(sources)?;
+                self.count_variables(targets)?;
+            }
+            _ => (),
+        }
+        Ok(hexpr)
+    }
+
+    /// Record the names of `variables`, failing if there are too many
+    fn count_variables(&mut self, variables: &[Variable]) -> Result<(), ParseError> {
+        for var in variables.iter().filter(|var| !var.is_anonymous()) {
+            // Names are slices of the input, so they can be kept without copying
+            self.variables
+                .insert(&self.input[var.span.start..var.span.end]);
+            let max = self.options.max_variables;
+            self.check(Limit::Variables, self.variables.len(), max, var.span)?;
+        }
+        Ok(())
+    }
+
+    /// Fail with the first error found while building from pairs, like a number too large
+    fn built(&mut self) -> Result<(), ParseError> {
+        match self.builder.errors.drain(..).next() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Fail at `at` if `count` exceeds `max`
+    fn check(&self, limit: Limit, count: usize, max: usize, at: Span) -> Result<(), ParseError> {
+        LimitExceeded::check(limit, count, max).map_err(|exceeded| ParseError::Limit(exceeded, at))
+    }
+
+    /// Read a power or ascription at the end of a composition or tensor, if there is one.
+    /// Anywhere else, a `*` or `:` is read as an operation.
+    fn group_end(&mut self, open: &mut Open) -> bool {
+        let start = self.pos;
+        let rest = &self.input[start..];
+        let power = match open.kind {
+            OpenKind::Scope(_) => return false,
+            _ if open.hexprs.is_empty() => return false,
+            OpenKind::Composition if rest.starts_with('^') => Rule::composition_power,
+            OpenKind::Tensor if rest.starts_with('*') => Rule::tensor_power,
+            _ if rest.starts_with(':') => {
+                let Some(ascription) = self.leaf(Rule::ascription) else {
+                    return false;
+                };
+                if !self.ends(open, start) {
+                    return false;
+                }
+                open.ascription = Some(self.builder.ascription_ports(ascription));
+                return true;
+            }
+            _ => return false,
+        };
+        if open.hexprs.len() > 1 {
+            return false;
+        }
+        match self.leaf(power) {
+            Some(power) if self.ends(open, start) => {
+                open.power = Some(power_count(power));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether `open` closes after the trivia at the current position, or else go back to `start`
+    fn ends(&mut self, open: &Open, start: usize) -> bool {
+        let pos = self.pos;
+        self.skip_trivia();
+        let ends = self.closes(open);
+        self.pos = if ends { pos } else { start };
+        ends
+    }
+
+    /// Whether the next character closes `open`
+    fn closes(&self, open: &Open) -> bool {
+        let close = match open.kind {
+            OpenKind::Composition | OpenKind::Scope(_) => ')',
+            OpenKind::Tensor => '}',
+        };
+        self.input[self.pos..].starts_with(close)
+    }
+
+    fn close(&mut self, open: Open) -> Result<Hexpr, ParseError> {
+        if !self.closes(&open) {
+            open.hexprs.into_iter().for_each(Hexpr::drop_iteratively);
+            return Err(self.error("expected closing bracket"));
+        }
+        if open.hexprs.is_empty() {
+            return Err(self.error("expected hexpr"));
+        }
+        self.pos += 1;
+        let span = Span {
+            end: self.pos,
+            ..open.start
+        };
+        let mut hexprs = open.hexprs;
+        if let Some((kind, count)) = open.power {
+            return Ok(Hexpr::Power {
+                hexpr: Box::new(hexprs.pop().unwrap()),
+                kind,
+                count,
+                span,
+            });
+        }
+        let hexpr = match open.kind {
+            OpenKind::Composition => Hexpr::Composition(hexprs, span),
+            OpenKind::Tensor => Hexpr::Tensor(hexprs, span),
+            OpenKind::Scope(variables) => Hexpr::Scope {
+                variables,
+                body: hexprs,
+                span,
+            },
+        };
+        Ok(Builder::ascribe(hexpr, open.ascription))
+    }
+
+    /// The position after `(` and `keyword`, if they come next
+    fn keyword_after_paren(&self, keyword: &str) -> Option<usize> {
+        if !self.input[self.pos..].starts_with('(') {
+            return None;
+        }
+        let start = self.trivia_end(self.pos + 1);
+        let after = self.input[start..].strip_prefix(keyword)?;
+        after
+            .starts_with(|c| is_whitespace(c) || c == '#')
+            .then(|| self.input.len() - after.len())
+    }
+
+    /// Parse `rule` at the current position, moving past it
+    fn leaf(&mut self, rule: Rule) -> Option<Pair<'a, Rule>> {
+        let pair = HExprParser::parse(rule, &self.input[self.pos..])
+            .ok()?
+            .next()?;
+        // Spans of the pair are relative to the current position
+        self.builder.offset = self.pos;
+        self.pos += pair.as_span().end();
+        Some(pair)
+    }
+
+    fn skip_trivia(&mut self) {
+        self.pos = self.trivia_end(self.pos);
+    }
+
+    /// The position after any whitespace and comments starting at `pos`
+    fn trivia_end(&self, mut pos: usize) -> usize {
+        loop {
+            let rest = &self.input[pos..];
+            let trimmed = rest.trim_start_matches(is_whitespace);
+            let trimmed = match trimmed.strip_prefix('#') {
+                Some(comment) => comment.trim_start_matches(|c| c != '\n'),
+                None => trimmed,
+            };
+            if trimmed.len() == rest.len() {
+                return pos;
+            }
+            pos = self.input.len() - trimmed.len();
+        }
+    }
+
+    fn error(&self, message: &str) -> ParseError {
+        let pos = pest::Position::new(self.input, self.pos).unwrap();
+        let variant = ErrorVariant::CustomError {
+            message: message.to_string(),
+        };
+        pest::error::Error::new_from_pos(variant, pos).into()
+    }
+}
+
+impl Open {
+    fn is_ended(&self) -> bool {
+        self.power.is_some() || self.ascription.is_some()
+    }
+}
+
+/// The `WHITESPACE` rule of the grammar
+fn is_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r')
+}
+
+/// The kind and count of a `composition_power` or `tensor_power` rule
+fn power_count(pair: Pair<Rule>) -> (PowerKind, usize) {
+    let kind = match pair.as_rule() {
+        Rule::tensor_power => PowerKind::Tensor,
+        _ => PowerKind::Composition,
+    };
+    let count = pair.into_inner().last().unwrap().as_str();
+    // Counts too large for a usize would run out of memory anyway
+    (kind, count.parse().unwrap_or(usize::MAX))
+}
+
+/// Builds [`Hexpr`]s from pest pairs, attaching source locations.
+pub(crate) struct Builder<'a> {
+    input: &'a str,
+    lines: LineIndex<'a>,
+    /// Where in `input` the pairs being built were parsed from
+    offset: usize,
+    /// Syntax errors found by the recovering rules
+    errors: Vec<ParseError>,
+}
+
+impl<'a> Builder<'a> {
+    pub(crate) fn new(input: &'a str) -> Self {
+        Builder {
+            input,
+            lines: LineIndex::new(input),
+            offset: 0,
+            errors: vec![],
+        }
+    }
+
+    fn span(&self, pair: &Pair<Rule>) -> Span {
+        let span = pair.as_span();
+        self.lines
+            .span(self.offset + span.start(), self.offset + span.end())
+    }
+
+    fn error(&mut self, start: usize, end: usize, message: String) {
+        let span = pest::Span::new(self.input, start, end).unwrap();
+        let variant = ErrorVariant::CustomError { message };
+        self.errors
+            .push(pest::error::Error::new_from_span(variant, span).into());
+    }
+
+    /// Record an error for any number in `pair` too large to be a finite float
+    fn check_finite(&mut self, pair: &Pair<Rule>) {
+        if let Some(number) = infinite_number(pair) {
+            let (start, end) = (self.offset + number.start(), self.offset + number.end());
+            self.error(start, end, "number is too large".to_string());
+        }
+    }
+
+    fn hexpr(&mut self, pair: Pair<Rule>) -> Hexpr {
+        let span = self.span(&pair);
+        match pair.as_rule() {
+            // Hexprs passed to templates are parsed by the grammar, not the reader
+            Rule::recovering_hexpr | Rule::param_hexpr | Rule::hexpr => {
+                self.hexpr(pair.into_inner().next().unwrap())
+            }
+            Rule::recovering_composition
+            | Rule::recovering_tensor
+            | Rule::composition
+            | Rule::tensor => {
+                let ascription = self.ascription(&pair).map(|p| self.ascription_ports(p));
+                let power = pair
+                    .clone()
+                    .into_inner()
+                    .find(|p| matches!(p.as_rule(), Rule::composition_power | Rule::tensor_power));
+                let rule = pair.as_rule();
+                let mut hexprs = self.group(pair);
+                if let Some(power) = power {
+                    let power_span = self.span(&power);
+                    let (kind, count) = power_count(power);
+                    let bracket = match rule {
+                        Rule::recovering_tensor | Rule::tensor => PowerKind::Tensor,
+                        _ => PowerKind::Composition,
+                    };
+                    if kind == bracket && hexprs.len() == 1 {
+                        return Hexpr::Power {
+                            hexpr: Box::new(hexprs.pop().unwrap()),
+                            kind,
+                            count,
+                            span,
+                        };
+                    }
+                    let text = &self.input[power_span.start..power_span.end];
+                    let message = format!("unexpected `{}`", text);
+                    self.error(power_span.start, power_span.end, message);
+                }
+                let hexpr = match rule {
+                    Rule::recovering_tensor | Rule::tensor => Hexpr::Tensor(hexprs, span),
+                    _ => Hexpr::Composition(hexprs, span),
+                };
+                Builder::ascribe(hexpr, ascription)
+            }
+            Rule::recovering_scope | Rule::scope => {
+                let binder = pair
+                    .clone()
+                    .into_inner()
+                    .find(|p| p.as_rule() == Rule::binder)
+                    .unwrap();
+                let variables = binder.into_inner().map(|p| self.variable(p)).collect();
+                Hexpr::Scope {
+                    variables,
+                    body: self.group(pair),
+                    span,
+                }
+            }
+            Rule::frobenius | Rule::recovering_frobenius => self.frobenius(pair),
+            Rule::literal => {
+                self.check_finite(&pair);
+                Hexpr::Literal(Literal {
+                    span,
+                    ..Literal::from_pair(pair)
+                })
+            }
+            Rule::operation => Hexpr::Operation(self.operation(pair)),
+            x => panic!("unreachable: {:?}", x),
+        }
+    }
+
+    pub(crate) fn operation(&mut self, pair: Pair<Rule>) -> Operation {
+        self.check_finite(&pair);
+        Operation {
+            span: self.span(&pair),
+            ..Operation::from_pair(pair, &mut |hexpr| self.hexpr(hexpr))
+        }
+    }
+
+    /// The name and parameters of a template definition, like `(fanout n)`
+    fn template_head(&self, pair: Pair<Rule>) -> Head {
+        let mut head = pair.into_inner();
+        let name = head.next().unwrap();
+        let name = Operation {
+            span: self.span(&name),
+            name: operation_name(name),
+            params: vec![],
+        };
+        let params = head
+            .map(|pattern| match pattern.as_str().parse() {
+                Ok(n) => Pattern::Integer(n),
+                Err(_) => Pattern::Name(pattern.as_str().to_string()),
+            })
+            .collect();
+        Head::Template(name, params)
+    }
+
+    /// The hexprs in a bracketed group or at the top level, with [`Hexpr::Error`] in place of
+    /// any skipped input.
+    fn group(&mut self, pair: Pair<Rule>) -> Vec<Hexpr> {
+        let rule = pair.as_rule();
+        let span = self.span(&pair);
+        let mut hexprs = vec![];
+        let mut skipped: Option<Span> = None;
+        for inner in pair.into_inner() {
+            match inner.as_rule() {
+                Rule::unexpected | Rule::unexpected_in_group => {
+                    let junk = self.span(&inner);
+                    skipped = Some(skipped.map_or(junk, |s| s.join(junk)));
+                }
+                Rule::unclosed => self.unclosed(span),
+                Rule::EOI
+                | Rule::let_keyword
+                | Rule::binder
+                | Rule::ascription
+                | Rule::composition_power
+                | Rule::tensor_power => (),
+                _ => {
+                    self.flush_skipped(&mut skipped, &mut hexprs);
+                    hexprs.push(self.hexpr(inner));
+                }
+            }
+        }
+        self.flush_skipped(&mut skipped, &mut hexprs);
+
+        let is_bracketed = matches!(
+            rule,
+            Rule::recovering_composition | Rule::recovering_tensor | Rule::recovering_scope
+        );
+        if is_bracketed && hexprs.is_empty() {
+            self.error(span.start, span.end, "expected hexpr".to_string());
+            hexprs.push(Hexpr::Error(span));
+        }
+        hexprs
+    }
+
+    /// The ascription at the end of a composition or tensor, if any
+    fn ascription<'i>(&self, group: &Pair<'i, Rule>) -> Option<Pair<'i, Rule>> {
+        group
+            .clone()
+            .into_inner()
+            .find(|p| p.as_rule() == Rule::ascription)
+    }
+
+    /// The sources and targets of an ascription
+    fn ascription_ports(&self, pair: Pair<Rule>) -> (Ports, Ports) {
+        let mut ports = pair
+            .into_inner()
+            .filter(|p| p.as_rule() == Rule::ports)
+            .map(|p| self.ports(p));
+        (ports.next().unwrap(), ports.next().unwrap())
+    }
+
+    fn ascribe(hexpr: Hexpr, ascription: Option<(Ports, Ports)>) -> Hexpr {
+        let Some((sources, targets)) = ascription else {
+            return hexpr;
+        };
+        let span = hexpr.span();
+        Hexpr::Ascription {
+            hexpr: Box::new(hexpr),
+            sources,
+            targets,
+            span,
+        }
+    }
+
+    fn ports(&self, pair: Pair<Rule>) -> Ports {
+        debug_assert_eq!(pair.as_rule(), Rule::ports);
+        let inner = pair.into_inner().next().unwrap();
+        match inner.as_rule() {
+            // Arities too large for a usize can't match any hexpr anyway
+            Rule::arity => Ports::Arity(inner.as_str().parse().unwrap_or(usize::MAX)),
+            Rule::objects => Ports::Objects(
+                inner
+                    .into_inner()
+                    .map(|object| Object {
+                        name: object.as_str().to_string(),
+                        span: self.span(&object),
+                    })
+                    .collect(),
+            ),
+            x => panic!("unreachable: {:?}", x),
+        }
+    }
+
+    fn flush_skipped(&mut self, skipped: &mut Option<Span>, hexprs: &mut Vec<Hexpr>) {
+        if let Some(span) = skipped.take() {
+            let text = &self.input[span.start..span.end];
+            self.error(span.start, span.end, format!("unexpected `{}`", text));
+            hexprs.push(Hexpr::Error(span));
+        }
+    }
+
+    /// Report a group whose closing bracket is missing
+    fn unclosed(&mut self, group: Span) {
+        let open = &self.input[group.start..group.start + 1];
+        self.error(group.start, group.start + 1, format!("unclosed `{}`", open));
+    }
+
+    fn frobenius(&mut self, pair: Pair<Rule>) -> Hexpr {
+        let span = self.span(&pair);
+        let mut vars = vec![];
+        let mut skipped: Option<Span> = None;
+        for inner in pair.into_inner() {
+            match inner.as_rule() {
+                Rule::vars => vars.push(self.vars(inner)),
+                Rule::unexpected_in_group => {
+                    let junk = self.span(&inner);
+                    skipped = Some(skipped.map_or(junk, |s| s.join(junk)));
+                }
+                Rule::unclosed => self.unclosed(span),
+                x => panic!("unreachable: {:?}", x),
+            }
+        }
+        if let Some(junk) = skipped {
+            let text = &self.input[junk.start..junk.end];
+            self.error(junk.start, junk.end, format!("unexpected `{}`", text));
+        }
+
+        let mut vars = vars.into_iter();
+        let sources = vars.next().unwrap();
+        // `[]` has no wires to be an identity on, so it's the same as `[ . ]`
+        let identity = vars.len() == 0 && !sources.is_empty();
+        let targets = vars.next().unwrap_or_else(|| sources.clone());
+        Hexpr::Frobenius {
+            sources,
+            targets,
+            identity,
+            span,
+        }
+    }
+
+    fn vars(&self, pair: Pair<Rule>) -> Vec<Variable> {
+        debug_assert_eq!(pair.as_rule(), Rule::vars);
+        pair.into_inner().map(|p| self.wire(p)).collect()
+    }
+
+    /// A variable in a Frobenius bracket, with its optional type annotation
+    fn wire(&self, pair: Pair<Rule>) -> Variable {
+        debug_assert_eq!(pair.as_rule(), Rule::wire);
+        let mut inner = pair.into_inner();
+        let variable = self.variable(inner.next().unwrap());
+        match inner.next() {
+            Some(object) => variable.with_annotation(Object {
+                name: object.as_str().to_string(),
+                span: self.span(&object),
+            }),
+            None => variable,
+        }
+    }
+
+    fn variable(&self, pair: Pair<Rule>) -> Variable {
+        match pair.as_rule() {
+            Rule::variable => Variable {
+                name: pair.as_str().to_string(),
+                span: self.span(&pair),
+                annotation: None,
+            },
+            x => panic!("unreachable: {:?}", x),
+        }
     }
 }
 
@@ -69,23 +916,34 @@ fn parse_variable(pair: pest::iterators::Pair<Rule>) -> Variable {
 mod tests {
     use super::*;
 
+    fn op(name: &str) -> Hexpr {
+        Hexpr::Operation(Operation {
+            name: name.to_string(),
+            params: vec![],
+            span: Span::default(),
+        })
+    }
+
+    fn var(name: &str) -> Variable {
+        Variable {
+            name: name.to_string(),
+            span: Span::default(),
+            annotation: None,
+        }
+    }
+
     #[test]
     fn test_basic_operation() -> anyhow::Result<()> {
         let result = HExprParser::parse_hexpr("add")?;
-        assert_eq!(result, Hexpr::Operation(Operation("add".to_string())));
+        assert_eq!(result, op("add"));
         Ok(())
     }
 
     #[test]
     fn test_frobenius_identity() {
         let result = HExprParser::parse_hexpr("[x]").unwrap();
-        assert_eq!(
-            result,
-            Hexpr::Frobenius {
-                sources: vec![Variable("x".to_string())],
-                targets: vec![Variable("x".to_string())],
-            }
-        );
+        assert_eq!(result, Hexpr::identity(vec![var("x")]));
+        assert_ne!(result, Hexpr::frobenius(vec![var("x")], vec![var("x")]));
     }
 
     #[test]
@@ -93,58 +951,78 @@ mod tests {
         let result = HExprParser::parse_hexpr("[x x . x]").unwrap();
         assert_eq!(
             result,
-            Hexpr::Frobenius {
-                sources: vec![Variable("x".to_string()), Variable("x".to_string())],
-                targets: vec![Variable("x".to_string())],
-            }
+            Hexpr::frobenius(vec![var("x"), var("x")], vec![var("x")])
         );
     }
 
     #[test]
     fn test_composition() {
         let result = HExprParser::parse_hexpr("(add sub)").unwrap();
-        assert_eq!(
-            result,
-            Hexpr::Composition(vec![
-                Hexpr::Operation(Operation("add".to_string())),
-                Hexpr::Operation(Operation("sub".to_string())),
-            ])
-        );
+        assert_eq!(result, Hexpr::composition(vec![op("add"), op("sub"),]));
     }
 
     #[test]
     fn test_tensor() {
         let result = HExprParser::parse_hexpr("{add sub}").unwrap();
-        assert_eq!(
-            result,
-            Hexpr::Tensor(vec![
-                Hexpr::Operation(Operation("add".to_string())),
-                Hexpr::Operation(Operation("sub".to_string())),
-            ])
-        );
+        assert_eq!(result, Hexpr::tensor(vec![op("add"), op("sub"),]));
     }
 
     #[test]
     fn test_frobenius_empty() {
         let result = HExprParser::parse_hexpr("[]").unwrap();
-        assert_eq!(
-            result,
-            Hexpr::Frobenius {
-                sources: vec![],
-                targets: vec![],
-            }
-        );
+        assert_eq!(result, Hexpr::frobenius(vec![], vec![]));
     }
 
     #[test]
     fn test_comments_in_expressions() {
         let result = HExprParser::parse_hexpr("(foo # this is a comment\n bar)").unwrap();
+        assert_eq!(result, Hexpr::composition(vec![op("foo"), op("bar"),]));
+    }
+
+    #[test]
+    fn test_spans() {
+        let result = HExprParser::parse_hexpr("(foo\n  [x . y])").unwrap();
         assert_eq!(
-            result,
-            Hexpr::Composition(vec![
-                Hexpr::Operation(Operation("foo".to_string())),
-                Hexpr::Operation(Operation("bar".to_string())),
-            ])
+            result.span(),
+            Span {
+                start: 0,
+                end: 15,
+                line: 1,
+                column: 1
+            }
+        );
+        let Hexpr::Composition(children, _) = &result else {
+            panic!("expected composition");
+        };
+        assert_eq!(
+            children[0].span(),
+            Span {
+                start: 1,
+                end: 4,
+                line: 1,
+                column: 2
+            }
+        );
+        assert_eq!(
+            children[1].span(),
+            Span {
+                start: 7,
+                end: 14,
+                line: 2,
+                column: 3
+            }
+        );
+        let Hexpr::Frobenius { targets, .. } = &children[1] else {
+            panic!("expected frobenius");
+        };
+        assert_eq!(
+            targets[0].span(),
+            Span {
+                start: 12,
+                end: 13,
+                line: 2,
+                column: 8
+            }
         );
     }
 }
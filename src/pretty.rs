@@ -0,0 +1,372 @@
+//! Printing hexprs across several lines to fit a width.
+//!
+//! [`Display`](std::fmt::Display) prints a hexpr on one line. The pretty-printer instead lays
+//! out compositions, tensors, scopes and definitions Wadler-style: a bracket which fits on the
+//! rest of the line is printed flat, and otherwise each of its elements goes on its own line,
+//! indented. Frobenius brackets which don't fit break before the `.`, aligned with the sources.
+//!
+//! Printing a [`SyntaxNode`] keeps its comments and blank lines between items, so it can be used
+//! to format source files.
+//!
+//! Unlike [`Display`](std::fmt::Display), the layout is computed recursively, so it suits
+//! hand-written source rather than very deeply nested hexprs.
+use crate::ast::{Hexpr, Program};
+use crate::cst::{self, SyntaxElement, SyntaxNode, SyntaxToken, TokenKind};
+use crate::parser::Rule;
+
+/// How to lay out pretty-printed hexprs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrettyOptions {
+    /// The line width to fit within, where possible
+    pub width: usize,
+    /// How many spaces to indent the elements of a broken bracket
+    pub indent: usize,
+    /// Print identities `[x y . x y]` as `[x y]`, unless they have anonymous wires
+    pub identity_shorthand: bool,
+}
+
+impl Default for PrettyOptions {
+    fn default() -> Self {
+        PrettyOptions {
+            width: 80,
+            indent: 2,
+            identity_shorthand: false,
+        }
+    }
+}
+
+impl Hexpr {
+    /// Print this hexpr over as many lines as needed to fit `options.width`
+    pub fn pretty(&self, options: &PrettyOptions) -> String {
+        let text = self.to_string();
+        // Hexprs with syntax errors don't parse back, so are printed on one line
+        match cst::parse_hexpr(&text) {
+            Ok(node) => node.pretty(options),
+            Err(_) => text,
+        }
+    }
+}
+
+impl Program {
+    /// Print each item of this program over as many lines as needed to fit `options.width`
+    pub fn pretty(&self, options: &PrettyOptions) -> String {
+        let text = self.to_string();
+        match cst::parse_program(&text) {
+            Ok(node) => node.pretty(options),
+            Err(_) => text,
+        }
+    }
+}
+
+impl SyntaxNode {
+    /// Print this node over as many lines as needed to fit `options.width`, keeping comments.
+    ///
+    /// Other whitespace is replaced, except for single blank lines between the items of a
+    /// program.
+    pub fn pretty(&self, options: &PrettyOptions) -> String {
+        render(&Printer { options }.doc(self), options.width)
+    }
+}
+
+/// A document to lay out, as in Wadler's "A prettier printer"
+enum Doc {
+    Text(String),
+    /// A space, or a newline if the enclosing group doesn't fit on one line
+    Line,
+    /// Always a newline, e.g. after a comment
+    HardLine,
+    /// A comment, which may run past the width
+    Comment(String),
+    /// Indent lines in `Doc` by a further amount
+    Nest(usize, Box<Doc>),
+    /// Indent lines in `Doc` to the column it starts at
+    Align(Box<Doc>),
+    /// Lay out `Doc` on one line if it fits
+    Group(Box<Doc>),
+    Concat(Vec<Doc>),
+}
+
+/// An element of a bracket
+enum Piece {
+    Doc(Doc),
+    /// A comment, and whether it starts its own line in the source
+    Comment(String, bool),
+}
+
+struct Printer<'a> {
+    options: &'a PrettyOptions,
+}
+
+impl Printer<'_> {
+    fn doc(&self, node: &SyntaxNode) -> Doc {
+        match node.rule() {
+            Rule::program => self.items(node),
+            Rule::hexpr => Doc::Concat(node.nodes().map(|n| self.doc(n)).collect()),
+            Rule::scope | Rule::definition => self.bracket(node, 2),
+            Rule::composition | Rule::tensor => self.bracket(node, 0),
+            Rule::frobenius => self.frobenius(node),
+            _ => inline(node),
+        }
+    }
+
+    /// The items of a program, one per line
+    fn items(&self, node: &SyntaxNode) -> Doc {
+        let mut docs = vec![];
+        let mut newlines = 0;
+        for child in node.children() {
+            let doc = match child {
+                SyntaxElement::Token(token) if token.kind() == TokenKind::Whitespace => {
+                    newlines += token.as_str().matches('\n').count();
+                    continue;
+                }
+                SyntaxElement::Token(token) => Doc::Comment(token.as_str().to_string()),
+                SyntaxElement::Node(node) => self.doc(node),
+            };
+            if !docs.is_empty() {
+                match (child, newlines) {
+                    // A comment at the end of a line stays there
+                    (SyntaxElement::Token(_), 0) => docs.push(Doc::Text(" ".to_string())),
+                    (_, 0 | 1) => docs.push(Doc::HardLine),
+                    _ => docs.extend([Doc::HardLine, Doc::HardLine]),
+                }
+            }
+            docs.push(doc);
+            newlines = 0;
+        }
+        if !docs.is_empty() {
+            docs.push(Doc::HardLine);
+        }
+        Doc::Concat(docs)
+    }
+
+    /// A bracket whose elements go on separate lines if it doesn't fit. The first `head`
+    /// elements, like `let [x]`, stay on the first line.
+    fn bracket(&self, node: &SyntaxNode, head: usize) -> Doc {
+        let children = node.children();
+        let (open, close) = match (children.first(), children.last()) {
+            (Some(SyntaxElement::Token(open)), Some(SyntaxElement::Token(close))) => (open, close),
+            _ => return inline(node),
+        };
+        let pieces = self.pieces(&children[1..children.len() - 1]);
+        let ends_with_comment = matches!(pieces.last(), Some(Piece::Comment(..)));
+
+        let mut docs = vec![];
+        for (i, piece) in pieces.into_iter().enumerate() {
+            match piece {
+                Piece::Doc(doc) => {
+                    if i > 0 && !matches!(docs.last(), Some(Doc::HardLine)) {
+                        docs.push(if i < head {
+                            Doc::Text(" ".to_string())
+                        } else {
+                            Doc::Line
+                        });
+                    }
+                    docs.push(doc);
+                }
+                Piece::Comment(text, own_line) => {
+                    if own_line && i > 0 && !matches!(docs.last(), Some(Doc::HardLine)) {
+                        docs.push(Doc::HardLine);
+                    } else if !own_line && i > 0 {
+                        docs.push(Doc::Text(" ".to_string()));
+                    }
+                    docs.push(Doc::Comment(text));
+                    docs.push(Doc::HardLine);
+                }
+            }
+        }
+        // The closing bracket goes on a line of its own after a comment, at the outer indent
+        if ends_with_comment {
+            docs.pop();
+        }
+        Doc::Group(Box::new(Doc::Concat(vec![
+            Doc::Text(open.as_str().to_string()),
+            Doc::Nest(self.options.indent, Box::new(Doc::Concat(docs))),
+            if ends_with_comment {
+                Doc::HardLine
+            } else {
+                Doc::Concat(vec![])
+            },
+            Doc::Text(close.as_str().to_string()),
+        ])))
+    }
+
+    /// A Frobenius bracket, breaking before the `.` if it doesn't fit
+    fn frobenius(&self, node: &SyntaxNode) -> Doc {
+        let has_comments = node
+            .tokens()
+            .iter()
+            .any(|token| token.kind() == TokenKind::Comment);
+        if has_comments {
+            return self.bracket(node, 0);
+        }
+
+        let wires = |vars: &SyntaxNode| -> Vec<String> { vars.nodes().map(inline_text).collect() };
+        let mut vars = node.nodes();
+        let sources = vars.next().map(wires).unwrap_or_default();
+        let mut targets = vars.next().map(wires);
+        // `[_ . _]` is two wires, while `[_]` would be one
+        let anonymous = sources
+            .iter()
+            .any(|wire| wire.split(':').next() == Some("_"));
+        if self.options.identity_shorthand && !anonymous && targets.as_ref() == Some(&sources) {
+            targets = None;
+        }
+
+        let join = |wires: &[String]| -> Doc {
+            let mut docs = vec![];
+            for (i, wire) in wires.iter().enumerate() {
+                if i > 0 {
+                    docs.push(Doc::Line);
+                }
+                docs.push(Doc::Text(wire.clone()));
+            }
+            Doc::Group(Box::new(Doc::Align(Box::new(Doc::Concat(docs)))))
+        };
+        let mut docs = vec![join(&sources)];
+        match targets {
+            None => (),
+            // `[x y . z]`, `[x.]` and `[.x]`
+            Some(targets) if !sources.is_empty() && !targets.is_empty() => {
+                docs.extend([Doc::Line, Doc::Text(". ".to_string()), join(&targets)])
+            }
+            Some(targets) => docs.extend([Doc::Text(".".to_string()), join(&targets)]),
+        }
+        Doc::Group(Box::new(Doc::Concat(vec![
+            Doc::Text("[".to_string()),
+            Doc::Align(Box::new(Doc::Concat(docs))),
+            Doc::Text("]".to_string()),
+        ])))
+    }
+
+    fn pieces(&self, children: &[SyntaxElement]) -> Vec<Piece> {
+        let mut pieces = vec![];
+        let mut own_line = false;
+        for child in children {
+            match child {
+                SyntaxElement::Node(node) => pieces.push(Piece::Doc(self.doc(node))),
+                SyntaxElement::Token(token) => match token.kind() {
+                    TokenKind::Whitespace => {
+                        own_line = token.as_str().contains('\n');
+                        continue;
+                    }
+                    TokenKind::Comment => {
+                        pieces.push(Piece::Comment(token.as_str().to_string(), own_line))
+                    }
+                    TokenKind::Text => {
+                        pieces.push(Piece::Doc(Doc::Text(token.as_str().to_string())))
+                    }
+                },
+            }
+            own_line = false;
+        }
+        pieces
+    }
+}
+
+/// A node printed on one line, with runs of whitespace replaced by a space. Comments still end
+/// the line.
+fn inline(node: &SyntaxNode) -> Doc {
+    let mut docs = vec![];
+    let mut text = String::new();
+    let mut space = false;
+    for token in node.tokens() {
+        match token.kind() {
+            TokenKind::Whitespace => space = true,
+            TokenKind::Comment => {
+                text.push(' ');
+                docs.push(Doc::Text(std::mem::take(&mut text)));
+                docs.push(Doc::Comment(token.as_str().to_string()));
+                docs.push(Doc::HardLine);
+                space = false;
+            }
+            TokenKind::Text => {
+                if space && !text.is_empty() && !opens(&text) && !closes(token) {
+                    text.push(' ');
+                }
+                text.push_str(token.as_str());
+                space = false;
+            }
+        }
+    }
+    docs.push(Doc::Text(text));
+    Doc::Concat(docs)
+}
+
+/// The text of a node with no comments, printed on one line
+fn inline_text(node: &SyntaxNode) -> String {
+    render(&inline(node), usize::MAX)
+}
+
+fn opens(text: &str) -> bool {
+    text.ends_with(['(', '[', '{', '<'])
+}
+
+fn closes(token: &SyntaxToken) -> bool {
+    token.as_str().starts_with([')', ']', '}', '>'])
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+fn render(doc: &Doc, width: usize) -> String {
+    let mut out = String::new();
+    let mut column = 0;
+    let mut stack = vec![(0, Mode::Break, doc)];
+    while let Some((indent, mode, doc)) = stack.pop() {
+        match doc {
+            Doc::Text(text) | Doc::Comment(text) => {
+                out.push_str(text);
+                column += text.chars().count();
+            }
+            Doc::Line if mode == Mode::Flat => {
+                out.push(' ');
+                column += 1;
+            }
+            Doc::Line | Doc::HardLine => {
+                let trimmed = out.trim_end_matches(' ').len();
+                out.truncate(trimmed);
+                out.push('\n');
+                out.extend(std::iter::repeat_n(' ', indent));
+                column = indent;
+            }
+            Doc::Nest(n, doc) => stack.push((indent + n, mode, doc)),
+            Doc::Align(doc) => stack.push((column, mode, doc)),
+            Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (indent, mode, doc))),
+            Doc::Group(doc) => {
+                let flat = mode == Mode::Flat || fits(width.saturating_sub(column), doc, &stack);
+                let mode = if flat { Mode::Flat } else { Mode::Break };
+                stack.push((indent, mode, doc));
+            }
+        }
+    }
+    out
+}
+
+/// Whether `doc` fits in `width` on one line, along with what follows it up to the next line
+/// break
+fn fits(width: usize, doc: &Doc, rest: &[(usize, Mode, &Doc)]) -> bool {
+    let mut remaining = width;
+    let mut stack = vec![(Mode::Flat, doc)];
+    let mut rest = rest.iter().rev().map(|&(_, mode, doc)| (mode, doc));
+    while let Some((mode, doc)) = stack.pop().or_else(|| rest.next()) {
+        match doc {
+            Doc::Text(text) => match remaining.checked_sub(text.chars().count()) {
+                Some(r) => remaining = r,
+                None => return false,
+            },
+            Doc::Line if mode == Mode::Flat => match remaining.checked_sub(1) {
+                Some(r) => remaining = r,
+                None => return false,
+            },
+            Doc::Line => return true,
+            // A group can't be flat if it contains a comment, but a comment after it is fine
+            Doc::HardLine | Doc::Comment(_) => return mode == Mode::Break,
+            Doc::Nest(_, doc) | Doc::Align(doc) | Doc::Group(doc) => stack.push((mode, doc)),
+            Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (mode, doc))),
+        }
+    }
+    true
+}
@@ -0,0 +1,561 @@
+//! Drawing open hypergraphs as string diagrams in SVG, without needing Graphviz.
+//!
+//! Operations are boxes in layers from left to right, each in the first layer after every
+//! operation it depends on, and ordered within a layer to follow the wires into it. Wires are
+//! curves between the ordered ports of boxes, passing between the boxes of any layers they
+//! skip, and the `sources` and `targets` of the open hypergraph are the ends of wires on the
+//! left and right edges.
+//!
+//! Nodes identified by the quotient are drawn as one wire, so the open hypergraph needn't be
+//! unified first. A wire which doesn't connect exactly one output to one input, like those of
+//! `[x . x x]` or `[x.]`, is a Frobenius spider, drawn as a dot.
+use std::fmt::{Display, Write};
+use std::ops::Range;
+
+use open_hypergraphs::lax::OpenHypergraph;
+
+use crate::layer::layers;
+
+/// How to draw a string diagram
+#[derive(Debug, Clone, PartialEq)]
+pub struct SvgOptions {
+    /// The color of wires, boxes and text
+    pub foreground: String,
+    /// The color behind the diagram and inside boxes, or `None` for transparent
+    pub background: Option<String>,
+    /// The size of text, which all other sizes are proportional to
+    pub font_size: f64,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        SvgOptions {
+            foreground: "white".to_string(),
+            background: Some("#4a4a4a".to_string()),
+            font_size: 14.0,
+        }
+    }
+}
+
+/// Draw an open hypergraph, as produced by the interpreter, as a string diagram.
+/// Wires of unknown type are unlabelled.
+pub fn to_svg<O: Display + Clone, A: Display + Clone>(
+    graph: &OpenHypergraph<Option<O>, A>,
+    options: &SvgOptions,
+) -> String {
+    to_svg_with(graph, options, |obj| obj.as_ref().map(ToString::to_string))
+}
+
+/// Draw an open hypergraph as a string diagram, labelling each wire by `object`.
+///
+/// Unified open hypergraphs can be drawn with `|obj| Some(obj.to_string())`.
+pub fn to_svg_with<O: Clone, A: Display + Clone>(
+    graph: &OpenHypergraph<O, A>,
+    options: &SvgOptions,
+    object: impl Fn(&O) -> Option<String>,
+) -> String {
+    let hypergraph = &graph.hypergraph;
+    let coequalizer = hypergraph.coequalizer();
+    let class = &coequalizer.table.0;
+
+    // The labels of each wire, which differ only if it fails to unify
+    let mut labels: Vec<Vec<String>> = vec![vec![]; coequalizer.target];
+    for (i, node) in hypergraph.nodes.iter().enumerate() {
+        if let Some(label) = object(node) {
+            if !labels[class[i]].contains(&label) {
+                labels[class[i]].push(label);
+            }
+        }
+    }
+    let labels: Vec<String> = labels.iter().map(|labels| labels.join(" ≠ ")).collect();
+    let ops: Vec<String> = hypergraph.edges.iter().map(ToString::to_string).collect();
+
+    let wiring = Wiring::new(graph, class, coequalizer.target);
+    let layout = Layout::new(&wiring, &ops, options.font_size);
+    render(&wiring, &layout, &ops, &labels, options)
+}
+
+/// Where a wire ends: at the left or right edge of the diagram, or at a port of a box
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum End {
+    Source(usize),
+    Target(usize),
+    Input(usize, usize),
+    Output(usize, usize),
+}
+
+/// One end of a curve: where a wire ends, or the dot of a spider
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Point {
+    End(End),
+    Dot(usize),
+}
+
+/// A curve, passing through a slot between the boxes of each of the columns `through`
+#[derive(Debug, Clone)]
+struct Segment {
+    wire: usize,
+    from: Point,
+    to: Point,
+    through: Range<usize>,
+}
+
+/// Which boxes connect to which, arranged in columns: the sources, each layer of boxes, and
+/// then the targets
+struct Wiring {
+    /// The number of inputs and outputs of each box
+    arity: Vec<(usize, usize)>,
+    /// The column of each box
+    layer: Vec<usize>,
+    /// The column of the targets
+    last: usize,
+    sources: usize,
+    targets: usize,
+    /// For each wire which is a spider, the column its dot comes after
+    dots: Vec<Option<usize>>,
+    segments: Vec<Segment>,
+}
+
+impl Wiring {
+    fn new<O: Clone, A: Clone>(
+        graph: &OpenHypergraph<O, A>,
+        class: &[usize],
+        wires: usize,
+    ) -> Self {
+        let hypergraph = &graph.hypergraph;
+
+        // The ends of each wire: where its value comes from, and where it goes
+        let mut producers: Vec<Vec<End>> = vec![vec![]; wires];
+        let mut consumers: Vec<Vec<End>> = vec![vec![]; wires];
+        for (i, node) in graph.sources.iter().enumerate() {
+            producers[class[node.0]].push(End::Source(i));
+        }
+        for (e, edge) in hypergraph.adjacency.iter().enumerate() {
+            for (i, node) in edge.sources.iter().enumerate() {
+                consumers[class[node.0]].push(End::Input(e, i));
+            }
+            for (i, node) in edge.targets.iter().enumerate() {
+                producers[class[node.0]].push(End::Output(e, i));
+            }
+        }
+        for (i, node) in graph.targets.iter().enumerate() {
+            consumers[class[node.0]].push(End::Target(i));
+        }
+
+        let layer = layers(hypergraph.adjacency.iter().map(|edge| {
+            let mut dependencies: Vec<usize> = edge
+                .sources
+                .iter()
+                .flat_map(|node| &producers[class[node.0]])
+                .filter_map(|end| match end {
+                    End::Output(e, _) => Some(*e),
+                    _ => None,
+                })
+                .collect();
+            dependencies.sort_unstable();
+            dependencies.dedup();
+            dependencies
+        }));
+        let last = layer.iter().copied().max().unwrap_or(0) + 1;
+        let mut wiring = Wiring {
+            arity: hypergraph
+                .adjacency
+                .iter()
+                .map(|edge| (edge.sources.len(), edge.targets.len()))
+                .collect(),
+            layer,
+            last,
+            sources: graph.sources.len(),
+            targets: graph.targets.len(),
+            dots: vec![None; wires],
+            segments: vec![],
+        };
+
+        for w in 0..wires {
+            let (from, to) = (&producers[w], &consumers[w]);
+            if let ([producer], [consumer]) = (&from[..], &to[..]) {
+                wiring.connect(w, Point::End(*producer), Point::End(*consumer));
+                continue;
+            }
+            // Spiders sit in the gap after the last column their wire comes from
+            let column = match from.iter().map(|end| wiring.column(end)).max() {
+                Some(k) => k,
+                None => to.iter().map(|end| wiring.column(end)).min().unwrap_or(1) - 1,
+            };
+            wiring.dots[w] = Some(column);
+            for producer in from {
+                wiring.connect(w, Point::End(*producer), Point::Dot(w));
+            }
+            for consumer in to {
+                wiring.connect(w, Point::Dot(w), Point::End(*consumer));
+            }
+        }
+        wiring
+    }
+
+    fn column(&self, end: &End) -> usize {
+        match *end {
+            End::Source(_) => 0,
+            End::Target(_) => self.last,
+            End::Input(e, _) | End::Output(e, _) => self.layer[e],
+        }
+    }
+
+    fn connect(&mut self, wire: usize, from: Point, to: Point) {
+        let after = match from {
+            Point::End(end) => self.column(&end),
+            Point::Dot(w) => self.dots[w].unwrap_or(0),
+        };
+        let before = match to {
+            Point::End(end) => self.column(&end),
+            Point::Dot(w) => self.dots[w].unwrap_or(0) + 1,
+        };
+        self.segments.push(Segment {
+            wire,
+            from,
+            to,
+            through: after + 1..before.max(after + 1),
+        });
+    }
+}
+
+/// Something stacked in a column: a box, or a slot for a segment to pass between boxes
+#[derive(Debug, Clone, Copy)]
+enum Item {
+    Box(usize),
+    Slot(usize),
+}
+
+/// The coordinates of everything in a diagram
+struct Layout {
+    width: f64,
+    height: f64,
+    font: f64,
+    sources: Vec<(f64, f64)>,
+    targets: Vec<(f64, f64)>,
+    inputs: Vec<Vec<(f64, f64)>>,
+    outputs: Vec<Vec<(f64, f64)>>,
+    /// The `(x, y, width, height)` of each box
+    boxes: Vec<(f64, f64, f64, f64)>,
+    dots: Vec<(f64, f64)>,
+    /// Where each segment passes through each column, as `(left, right, y)`
+    slots: Vec<Vec<(f64, f64, f64)>>,
+}
+
+impl Layout {
+    fn new(wiring: &Wiring, ops: &[String], font: f64) -> Self {
+        // Sizes, all proportional to the font size
+        let pitch = font * 1.8;
+        let gap = font * 4.0;
+        let margin = font;
+        let last = wiring.last;
+        let box_size = |e: usize| {
+            let (inputs, outputs) = wiring.arity[e];
+            let width = ops[e].chars().count() as f64 * font * 0.6 + font * 1.5;
+            (
+                width.max(font * 3.0),
+                inputs.max(outputs).max(1) as f64 * pitch,
+            )
+        };
+        let item_height = |item: &Item| match *item {
+            Item::Box(e) => box_size(e).1,
+            Item::Slot(_) => pitch / 2.0,
+        };
+
+        let mut columns: Vec<Vec<Item>> = vec![vec![]; last + 1];
+        for (e, &k) in wiring.layer.iter().enumerate() {
+            columns[k].push(Item::Box(e));
+        }
+        for (s, segment) in wiring.segments.iter().enumerate() {
+            for k in segment.through.clone() {
+                columns[k].push(Item::Slot(s));
+            }
+        }
+        let heights: Vec<f64> = (0..=last)
+            .map(|k| match k {
+                0 => wiring.sources as f64 * pitch,
+                k if k == last => wiring.targets as f64 * pitch,
+                k => {
+                    let items: f64 = columns[k].iter().map(item_height).sum();
+                    items + pitch / 2.0 * columns[k].len().saturating_sub(1) as f64
+                }
+            })
+            .collect();
+        let height = heights.iter().copied().fold(0.0, f64::max) + 2.0 * margin;
+        let top = |k: usize| (height - heights[k]) / 2.0;
+        let mut left = vec![margin; last + 1];
+        let mut widths = vec![0.0; last + 1];
+        for k in 0..=last {
+            for item in &columns[k] {
+                if let Item::Box(e) = *item {
+                    widths[k] = f64::max(widths[k], box_size(e).0);
+                }
+            }
+            if k > 0 {
+                left[k] = left[k - 1] + widths[k - 1] + gap;
+            }
+        }
+
+        let edges = wiring.arity.len();
+        let mut layout = Layout {
+            width: left[last] + margin,
+            height,
+            font,
+            sources: (0..wiring.sources)
+                .map(|i| (left[0], top(0) + (i as f64 + 0.5) * pitch))
+                .collect(),
+            targets: (0..wiring.targets)
+                .map(|i| (left[last], top(last) + (i as f64 + 0.5) * pitch))
+                .collect(),
+            inputs: vec![vec![]; edges],
+            outputs: vec![vec![]; edges],
+            boxes: vec![(0.0, 0.0, 0.0, 0.0); edges],
+            dots: vec![(0.0, 0.0); wiring.dots.len()],
+            slots: vec![vec![]; wiring.segments.len()],
+        };
+
+        // The height of each segment where it was last placed, and of each wire where it was
+        // first placed, for ordering the boxes and slots of the next column
+        let mut segment_y: Vec<Option<f64>> = vec![None; wiring.segments.len()];
+        let mut wire_y: Vec<Option<f64>> = vec![None; wiring.dots.len()];
+        let mut incoming: Vec<Vec<usize>> = vec![vec![]; edges];
+        for (s, segment) in wiring.segments.iter().enumerate() {
+            if let Point::End(End::Source(i)) = segment.from {
+                segment_y[s] = Some(layout.sources[i].1);
+                wire_y[segment.wire].get_or_insert(layout.sources[i].1);
+            }
+            if let Point::End(End::Input(e, _)) = segment.to {
+                incoming[e].push(s);
+            }
+        }
+
+        for (k, column) in columns.iter_mut().enumerate().take(last).skip(1) {
+            let current = |s: usize| match (segment_y[s], wiring.segments[s].from) {
+                (Some(y), _) => y,
+                (None, Point::Dot(w)) => wire_y[w].unwrap_or(f64::INFINITY),
+                (None, Point::End(_)) => f64::INFINITY,
+            };
+            let key = |item: &Item| match *item {
+                Item::Slot(s) => current(s),
+                Item::Box(e) if incoming[e].is_empty() => f64::INFINITY,
+                Item::Box(e) => {
+                    let ys: f64 = incoming[e].iter().map(|&s| current(s)).sum();
+                    ys / incoming[e].len() as f64
+                }
+            };
+            column.sort_by(|a, b| key(a).total_cmp(&key(b)));
+
+            let mut y = top(k);
+            for item in column.iter() {
+                match *item {
+                    Item::Slot(s) => {
+                        let slot_y = y + pitch / 4.0;
+                        layout.slots[s].push((left[k], left[k] + widths[k], slot_y));
+                        segment_y[s] = Some(slot_y);
+                    }
+                    Item::Box(e) => {
+                        let (w, h) = box_size(e);
+                        let x = left[k] + (widths[k] - w) / 2.0;
+                        layout.boxes[e] = (x, y, w, h);
+                        let ports = |n: usize, x: f64| -> Vec<(f64, f64)> {
+                            (0..n)
+                                .map(|i| (x, y + (i as f64 + 0.5) * h / n as f64))
+                                .collect()
+                        };
+                        let (inputs, outputs) = wiring.arity[e];
+                        layout.inputs[e] = ports(inputs, x);
+                        layout.outputs[e] = ports(outputs, x + w);
+                    }
+                }
+                y += item_height(item) + pitch / 2.0;
+            }
+            // Segments leaving the boxes of this column
+            for (s, segment) in wiring.segments.iter().enumerate() {
+                if let Point::End(End::Output(e, i)) = segment.from {
+                    if wiring.layer[e] == k {
+                        let port_y = layout.outputs[e][i].1;
+                        segment_y[s] = Some(port_y);
+                        wire_y[segment.wire].get_or_insert(port_y);
+                    }
+                }
+            }
+        }
+
+        // Each dot is level with the mean of the points next to it on its segments
+        for (w, column) in wiring.dots.iter().enumerate() {
+            let Some(k) = *column else { continue };
+            let ys: Vec<f64> = wiring
+                .segments
+                .iter()
+                .zip(&layout.slots)
+                .filter_map(|(segment, slots)| match (segment.from, segment.to) {
+                    (Point::Dot(d), to) if d == w => Some(match slots.first() {
+                        Some(&(_, _, y)) => y,
+                        None => layout.point(to).1,
+                    }),
+                    (from, Point::Dot(d)) if d == w => Some(match slots.last() {
+                        Some(&(_, _, y)) => y,
+                        None => layout.point(from).1,
+                    }),
+                    _ => None,
+                })
+                .collect();
+            let y = match ys.is_empty() {
+                true => height / 2.0,
+                false => ys.iter().sum::<f64>() / ys.len() as f64,
+            };
+            layout.dots[w] = (left[k] + widths[k] + gap / 2.0, y);
+        }
+        layout
+    }
+
+    fn point(&self, point: Point) -> (f64, f64) {
+        match point {
+            Point::End(End::Source(i)) => self.sources[i],
+            Point::End(End::Target(i)) => self.targets[i],
+            Point::End(End::Input(e, i)) => self.inputs[e][i],
+            Point::End(End::Output(e, i)) => self.outputs[e][i],
+            Point::Dot(w) => self.dots[w],
+        }
+    }
+}
+
+fn render(
+    wiring: &Wiring,
+    layout: &Layout,
+    ops: &[String],
+    labels: &[String],
+    options: &SvgOptions,
+) -> String {
+    let font = layout.font;
+    let foreground = escape(&options.foreground);
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{:.1}\" height=\"{:.1}\" \
+         viewBox=\"0 0 {:.1} {:.1}\" font-family=\"sans-serif\" font-size=\"{}\">",
+        layout.width, layout.height, layout.width, layout.height, font
+    );
+    if let Some(background) = &options.background {
+        let _ = writeln!(
+            out,
+            "<rect width=\"100%\" height=\"100%\" fill=\"{}\"/>",
+            escape(background)
+        );
+    }
+
+    // Wires, then the dots of spiders
+    let _ = writeln!(
+        out,
+        "<g fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\">",
+        foreground
+    );
+    for (segment, slots) in wiring.segments.iter().zip(&layout.slots) {
+        let (mut x, mut y) = layout.point(segment.from);
+        let _ = write!(out, "<path d=\"M {:.1} {:.1}", x, y);
+        for &(left, right, slot_y) in slots {
+            curve(&mut out, (x, y), (left, slot_y), font);
+            let _ = write!(out, " L {:.1} {:.1}", right, slot_y);
+            (x, y) = (right, slot_y);
+        }
+        curve(&mut out, (x, y), layout.point(segment.to), font);
+        let _ = writeln!(out, "\"/>");
+    }
+    let _ = writeln!(out, "</g>");
+    let _ = writeln!(out, "<g fill=\"{}\">", foreground);
+    for (column, &(x, y)) in wiring.dots.iter().zip(&layout.dots) {
+        if column.is_some() {
+            let _ = writeln!(
+                out,
+                "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{:.1}\"/>",
+                x,
+                y,
+                font / 4.0
+            );
+        }
+    }
+    let _ = writeln!(out, "</g>");
+
+    // Boxes, over the wires
+    let fill = options.background.as_deref().unwrap_or("none");
+    let _ = writeln!(
+        out,
+        "<g fill=\"{}\" stroke=\"{}\" stroke-width=\"1.5\">",
+        escape(fill),
+        foreground
+    );
+    for &(x, y, w, h) in &layout.boxes {
+        let _ = writeln!(
+            out,
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"{:.1}\"/>",
+            x,
+            y,
+            w,
+            h,
+            font / 3.0
+        );
+    }
+    let _ = writeln!(out, "</g>");
+
+    // Operations, and the type of each wire above its dot or where it starts
+    let _ = writeln!(out, "<g fill=\"{}\">", foreground);
+    for (op, &(x, y, w, h)) in ops.iter().zip(&layout.boxes) {
+        let _ = writeln!(
+            out,
+            "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\" \
+             dominant-baseline=\"central\">{}</text>",
+            x + w / 2.0,
+            y + h / 2.0,
+            escape(op)
+        );
+    }
+    for (w, label) in labels.iter().enumerate() {
+        if label.is_empty() {
+            continue;
+        }
+        let (x, y, anchor) = match wiring.dots[w] {
+            Some(_) => (layout.dots[w].0, layout.dots[w].1 - font / 2.0, "middle"),
+            None => match wiring.segments.iter().find(|segment| segment.wire == w) {
+                Some(segment) => {
+                    let (x, y) = layout.point(segment.from);
+                    (x + font / 3.0, y - font / 3.0, "start")
+                }
+                None => continue,
+            },
+        };
+        let _ = writeln!(
+            out,
+            "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"{}\">{}</text>",
+            x,
+            y,
+            anchor,
+            escape(label)
+        );
+    }
+    let _ = writeln!(out, "</g>");
+    let _ = writeln!(out, "</svg>");
+    out
+}
+
+/// Continue a path from `start` to `end` with a curve leaving and arriving horizontally
+fn curve(out: &mut String, start: (f64, f64), end: (f64, f64), font: f64) {
+    let bend = ((end.0 - start.0).abs() / 2.0).max(font);
+    let _ = write!(
+        out,
+        " C {:.1} {:.1}, {:.1} {:.1}, {:.1} {:.1}",
+        start.0 + bend,
+        start.1,
+        end.0 - bend,
+        end.1,
+        end.0,
+        end.1
+    );
+}
+
+/// Escape text for SVG content and attribute values
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,398 @@
+//! Expanding templates, the parametric definitions `(def (name params...) body)`.
+//!
+//! Expansion happens before interpretation, and replaces each use `name<args...>` of a template
+//! by a copy of its body in which:
+//!  - an operation named by a parameter is replaced by the argument: an operation for a symbol
+//!    argument like `neg`, a literal for a number, or a copy of a bracketed hexpr argument like
+//!    `(f g)` with its own wires;
+//!  - parameter values of operations naming a parameter are replaced by the argument, so
+//!    `(def (split n) copy<n>)` passes `n` on. Symbols like `n-1`, `n+1` and `n*2` compute with
+//!    an integer parameter.
+//!
+//! Each expansion has its own wires: the variables of the body are local to it.
+use std::cell::Cell;
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::ast::{
+    Definition, Hexpr, Item, Literal, LiteralValue, Operation, Param, Pattern, Program, Value,
+};
+use crate::interpret::InterpretOptions;
+use crate::limit::{Limit, LimitExceeded};
+use crate::span::Span;
+
+/// How deeply template uses may be nested, unless given to [`expand_with_limit`]
+pub const DEFAULT_RECURSION_LIMIT: usize = 128;
+
+#[derive(Debug, Error)]
+pub enum ExpandError {
+    #[error("{span}: No definition of template {name} matches {0}", span = .0.span(), name = .0.as_str())]
+    NoMatchingDefinition(Operation),
+    #[error("{span}: Expanding {op} exceeded the recursion limit of {limit}", span = .op.span())]
+    RecursionLimit { op: Operation, limit: usize },
+    #[error("{span}: Template argument {value} can't be used as a hexpr")]
+    NotAHexpr { span: Span, value: Value },
+    #[error("{span}: {exceeded}")]
+    Limit {
+        exceeded: LimitExceeded,
+        /// The template use whose expansion exceeded the limit
+        span: Span,
+    },
+}
+
+impl ExpandError {
+    /// The source range responsible for this error
+    pub fn span(&self) -> Span {
+        match self {
+            ExpandError::NoMatchingDefinition(op) => op.span(),
+            ExpandError::RecursionLimit { op, .. } => op.span(),
+            ExpandError::NotAHexpr { span, .. } | ExpandError::Limit { span, .. } => *span,
+        }
+    }
+}
+
+/// Expand every use of a template in the definitions and hexprs of a program, with the
+/// [default recursion limit](DEFAULT_RECURSION_LIMIT).
+///
+/// The result has no templates. A template can be used by any later item, and by itself.
+pub fn expand(program: &Program) -> Result<Program, ExpandError> {
+    expand_with_limit(program, DEFAULT_RECURSION_LIMIT)
+}
+
+/// Expand templates, failing if uses are nested more than `limit` deep.
+pub fn expand_with_limit(program: &Program, limit: usize) -> Result<Program, ExpandError> {
+    expand_with_options(program, limit, &InterpretOptions::default())
+}
+
+/// Expand templates, failing if uses are nested more than `limit` deep, or with
+/// [`ExpandError::Limit`] if the expansion of any definition or hexpr has more operations,
+/// literals and Frobenius brackets than `options` allows.
+///
+/// A short program can expand to a huge one, so this bounds the work done before
+/// interpretation can check the `options` itself.
+pub fn expand_with_options(
+    program: &Program,
+    limit: usize,
+    options: &InterpretOptions,
+) -> Result<Program, ExpandError> {
+    let mut expander = Expander {
+        templates: HashMap::new(),
+        limit,
+        options,
+        operations: Cell::new(0),
+    };
+    let mut items = vec![];
+    for item in &program.items {
+        let expanded = match item {
+            Item::Template(template) => {
+                expander
+                    .templates
+                    .entry(template.name.as_str().to_string())
+                    .or_default()
+                    .push((&template.params, &template.body));
+                continue;
+            }
+            Item::Definition(definition) => expander.expand(&definition.body).map(|body| {
+                Item::Definition(Definition {
+                    name: definition.name.clone(),
+                    body,
+                    span: definition.span,
+                })
+            }),
+            Item::Hexpr(hexpr) => expander.expand(hexpr).map(Item::Hexpr),
+        };
+        match expanded {
+            Ok(item) => items.push(item),
+            Err(e) => {
+                // The expansions so far may be deeply nested
+                Program { items }.drop_iteratively();
+                return Err(e);
+            }
+        }
+    }
+    Ok(Program { items })
+}
+
+/// Arguments bound to the parameters of the template being expanded
+type Env = HashMap<String, Value>;
+
+struct Expander<'p> {
+    /// The parameters and body of each definition of a template, in order
+    templates: HashMap<String, Vec<(&'p [Pattern], &'p Hexpr)>>,
+    limit: usize,
+    options: &'p InterpretOptions,
+    /// Operations, literals and Frobenius brackets in the expansion of the current item
+    operations: Cell<usize>,
+}
+
+/// A step in expanding a hexpr
+enum Task<'t> {
+    /// Expand a hexpr, with the arguments `envs[env]` of the template it is in
+    Expand {
+        hexpr: &'t Hexpr,
+        env: usize,
+        depth: usize,
+        /// The outermost template use the hexpr is in, or the hexpr itself at the top level
+        site: Span,
+    },
+    /// Replace the expansions of the hexprs directly inside a hexpr with the hexpr itself
+    Build(&'t Hexpr),
+    /// Give the expansion of a template its own wires
+    Localize,
+}
+
+/// What an operation expands to
+enum Expansion<'t> {
+    Hexpr(Hexpr),
+    /// The body of a template, to expand with the arguments bound to its parameters
+    Template(&'t Hexpr, Env),
+}
+
+impl<'p> Expander<'p> {
+    /// Expand the templates used in a hexpr
+    fn expand(&self, hexpr: &Hexpr) -> Result<Hexpr, ExpandError> {
+        self.operations.set(0);
+        self.expand_in(hexpr, &Env::new(), 0, hexpr.span())
+    }
+
+    /// Expand a hexpr in the template use with arguments `env`, `depth` deep in template uses
+    fn expand_in(
+        &self,
+        hexpr: &Hexpr,
+        env: &Env,
+        depth: usize,
+        site: Span,
+    ) -> Result<Hexpr, ExpandError> {
+        let mut expanded = vec![];
+        match self.expand_into(hexpr, env, depth, site, &mut expanded) {
+            Ok(()) => Ok(expanded.pop().unwrap()),
+            Err(e) => {
+                // The expansions so far may be deeply nested
+                expanded.into_iter().for_each(Hexpr::drop_iteratively);
+                Err(e)
+            }
+        }
+    }
+
+    /// Expand with an explicit stack rather than recursively, so that deeply nested hexprs and
+    /// templates don't overflow the call stack. The expansion is pushed onto `expanded`.
+    fn expand_into(
+        &self,
+        hexpr: &Hexpr,
+        env: &Env,
+        depth: usize,
+        site: Span,
+        expanded: &mut Vec<Hexpr>,
+    ) -> Result<(), ExpandError> {
+        // The arguments of each template use
+        let mut envs = vec![env.clone()];
+        let mut tasks = vec![Task::Expand {
+            hexpr,
+            env: 0,
+            depth,
+            site,
+        }];
+        let count = |hexpr: &Hexpr, site: Span| {
+            self.operations.set(self.operations.get() + size(hexpr));
+            let max = self.options.max_operations;
+            LimitExceeded::check(Limit::Operations, self.operations.get(), max).map_err(
+                |exceeded| ExpandError::Limit {
+                    exceeded,
+                    span: site,
+                },
+            )
+        };
+        while let Some(task) = tasks.pop() {
+            match task {
+                Task::Expand {
+                    hexpr,
+                    env,
+                    depth,
+                    site,
+                } => match hexpr {
+                    Hexpr::Operation(op) => match self.operation(op, &envs[env], depth, site)? {
+                        Expansion::Hexpr(hexpr) => {
+                            count(&hexpr, site)?;
+                            expanded.push(hexpr);
+                        }
+                        Expansion::Template(body, body_env) => {
+                            envs.push(body_env);
+                            tasks.push(Task::Localize);
+                            tasks.push(Task::Expand {
+                                hexpr: body,
+                                env: envs.len() - 1,
+                                depth: depth + 1,
+                                site,
+                            });
+                        }
+                    },
+                    Hexpr::Frobenius { .. } | Hexpr::Literal(_) | Hexpr::Error(_) => {
+                        count(hexpr, site)?;
+                        expanded.push(hexpr.with_children(vec![]));
+                    }
+                    _ => {
+                        tasks.push(Task::Build(hexpr));
+                        let children = hexpr.children().iter().rev();
+                        tasks.extend(children.map(|hexpr| Task::Expand {
+                            hexpr,
+                            env,
+                            depth,
+                            site: if depth == 0 { hexpr.span() } else { site },
+                        }));
+                    }
+                },
+                Task::Build(hexpr) => {
+                    let children = expanded.split_off(expanded.len() - hexpr.children().len());
+                    expanded.push(hexpr.with_children(children));
+                }
+                Task::Localize => {
+                    let body = expanded.pop().unwrap();
+                    expanded.push(body.localized());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn operation(
+        &self,
+        op: &Operation,
+        env: &Env,
+        depth: usize,
+        site: Span,
+    ) -> Result<Expansion<'p>, ExpandError> {
+        // A parameter standing for a hexpr
+        if let (Some(value), true) = (env.get(op.as_str()), op.params.is_empty()) {
+            let hexpr = argument_hexpr(value, op.span())?;
+            return match hexpr {
+                // The argument may itself name a template
+                Hexpr::Operation(ref op) => self.operation(op, &Env::new(), depth, site),
+                hexpr => Ok(Expansion::Hexpr(hexpr)),
+            };
+        }
+
+        let params = op.params.iter().map(|param| {
+            Ok(Param {
+                name: param.name.clone(),
+                value: self.substitute(&param.value, env, depth, site)?,
+            })
+        });
+        let op = Operation {
+            params: params.collect::<Result<_, _>>()?,
+            ..op.clone()
+        };
+        let Some(definitions) = self.templates.get(op.as_str()) else {
+            return Ok(Expansion::Hexpr(Hexpr::Operation(op)));
+        };
+        if depth >= self.limit {
+            return Err(ExpandError::RecursionLimit {
+                op,
+                limit: self.limit,
+            });
+        }
+
+        let args: Vec<&Value> = op.positional().collect();
+        let (env, body) = definitions
+            .iter()
+            // Templates only take positional arguments
+            .filter(|_| op.params.len() == args.len())
+            .find_map(|(params, body)| Some((bind(params, &args)?, body)))
+            .ok_or_else(|| ExpandError::NoMatchingDefinition(op.clone()))?;
+        Ok(Expansion::Template(body, env))
+    }
+
+    /// Replace parameters in an operation parameter value, and expand hexpr values
+    fn substitute(
+        &self,
+        value: &Value,
+        env: &Env,
+        depth: usize,
+        site: Span,
+    ) -> Result<Value, ExpandError> {
+        Ok(match value {
+            Value::Symbol(symbol) => env
+                .get(symbol)
+                .cloned()
+                .or_else(|| arithmetic(symbol, env).map(Value::Integer))
+                .unwrap_or_else(|| value.clone()),
+            Value::List(values) => Value::List(
+                values
+                    .iter()
+                    .map(|value| self.substitute(value, env, depth, site))
+                    .collect::<Result<_, _>>()?,
+            ),
+            // Expanded in the template use it's written in, so it can use the parameters
+            Value::Hexpr(hexpr) => Value::Hexpr(Box::new(self.expand_in(hexpr, env, depth, site)?)),
+            _ => value.clone(),
+        })
+    }
+}
+
+/// Operations, literals and Frobenius brackets in an expanded hexpr
+fn size(hexpr: &Hexpr) -> usize {
+    let mut size = 0;
+    let mut stack = vec![hexpr];
+    while let Some(hexpr) = stack.pop() {
+        if hexpr.children().is_empty() {
+            size += 1;
+        }
+        stack.extend(hexpr.children());
+    }
+    size
+}
+
+/// Match arguments against the parameters of one definition of a template
+fn bind(params: &[Pattern], args: &[&Value]) -> Option<Env> {
+    if params.len() != args.len() {
+        return None;
+    }
+    let mut env = Env::new();
+    for (param, &arg) in params.iter().zip(args) {
+        match param {
+            Pattern::Name(name) => {
+                env.insert(name.clone(), arg.clone());
+            }
+            Pattern::Integer(n) => {
+                if arg.as_integer() != Some(*n) {
+                    return None;
+                }
+            }
+        }
+    }
+    Some(env)
+}
+
+/// Evaluate a symbol like `n-1`, where `n` is an integer parameter
+fn arithmetic(symbol: &str, env: &Env) -> Option<i128> {
+    let (i, operator) = symbol.char_indices().find(|(_, c)| "+-*".contains(*c))?;
+    let left = env.get(&symbol[..i])?.as_integer()?;
+    let right: i128 = symbol[i + 1..].parse().ok()?;
+    match operator {
+        '+' => left.checked_add(right),
+        '-' => left.checked_sub(right),
+        _ => left.checked_mul(right),
+    }
+}
+
+/// The hexpr an argument stands for when a parameter is used as an operation
+fn argument_hexpr(value: &Value, span: Span) -> Result<Hexpr, ExpandError> {
+    let literal = |text: String, value: LiteralValue| Hexpr::Literal(Literal { text, value, span });
+    match value {
+        Value::Symbol(name) => Ok(Hexpr::Operation(Operation {
+            name: name.clone(),
+            params: vec![],
+            span,
+        })),
+        Value::Integer(n) => Ok(literal(value.to_string(), LiteralValue::Integer(*n))),
+        Value::Float(x) => Ok(literal(value.to_string(), LiteralValue::Float(*x))),
+        Value::String(s) => Ok(literal(value.to_string(), LiteralValue::String(s.clone()))),
+        // Each use is a copy with its own wires, shared with neither the template body nor other
+        // copies
+        Value::Hexpr(hexpr) => Ok(hexpr.as_ref().clone().localized()),
+        Value::List(_) => Err(ExpandError::NotAHexpr {
+            span,
+            value: value.clone(),
+        }),
+    }
+}
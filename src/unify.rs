@@ -6,61 +6,111 @@ use open_hypergraphs::category::Arrow;
 use open_hypergraphs::lax::{NodeId, OpenHypergraph};
 
 use crate::interpret::OpenHypergraphWithNames;
+use crate::span::Span;
 use crate::Variable;
 
+/// Errors from unification. Where known, each carries the source locations of the offending nodes.
 #[derive(Debug, Error)]
 pub enum UnifyError {
-    #[error("Not all node labels known")]
-    NotAllLabelsKnown,
-    #[error("Could not unify {0:?}")]
-    Mismatch(NodeId),
+    #[error("Not all node labels known: no label for {node:?}{}", at(.spans))]
+    NotAllLabelsKnown { node: NodeId, spans: Vec<Span> },
+    #[error("Could not unify {node:?}{} with {other:?}{}", at(.spans), at(.other_spans))]
+    Mismatch {
+        /// The node whose label conflicts
+        node: NodeId,
+        spans: Vec<Span>,
+        /// The node whose label was seen first
+        other: NodeId,
+        other_spans: Vec<Span>,
+    },
     #[error("Quotient failed")]
     Quotient,
 }
 
+impl UnifyError {
+    /// The primary source location of this error, if known
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            UnifyError::NotAllLabelsKnown { spans, .. } => spans.first().copied(),
+            UnifyError::Mismatch { spans, .. } => spans.first().copied(),
+            UnifyError::Quotient => None,
+        }
+    }
+}
+
+fn at(spans: &[Span]) -> String {
+    match spans.first() {
+        Some(span) => format!(" at {}", span),
+        None => String::new(),
+    }
+}
+
 /// Unify the variables of an unquotiented open hypergraph with nodes labels `Option<O>`.
 pub fn unify<O: Clone + PartialEq, A: Clone>(
     f: OpenHypergraph<Option<O>, A>,
 ) -> Result<OpenHypergraph<O, A>, UnifyError> {
+    unify_located(f, &HashMap::new())
+}
+
+fn unify_located<O: Clone + PartialEq, A: Clone>(
+    f: OpenHypergraph<Option<O>, A>,
+    spans: &HashMap<NodeId, Vec<Span>>,
+) -> Result<OpenHypergraph<O, A>, UnifyError> {
+    let spans_of = |i: usize| spans.get(&NodeId(i)).cloned().unwrap_or_default();
+
     // coequalizer of the quotient map
     let coequalizer = f.hypergraph.coequalizer();
 
     // Compute node values for each equivalence class by merging all values in each class.
-    // Done in a single pass over node labels, storing results for each class in class_labels
-    let mut class_labels: Vec<Option<O>> = vec![None; coequalizer.target()];
+    // Done in a single pass over node labels, storing results for each class in class_labels,
+    // along with the node each label came from.
+    let mut class_labels: Vec<Option<(usize, O)>> = vec![None; coequalizer.target()];
     for (i, node) in f.hypergraph.nodes.iter().enumerate() {
         let class = coequalizer.table[i];
         let label = &mut class_labels[class];
         match (node, label.as_mut()) {
             // update u with value of x
-            (Some(x), None) => *label = Some(x.clone()),
+            (Some(x), None) => *label = Some((i, x.clone())),
             // no new x, do nothing
             (None, _) => (),
             // If x != u, unification error
-            (Some(x), Some(u)) => {
+            (Some(x), Some((j, u))) => {
                 if x != u {
-                    return Err(UnifyError::Mismatch(NodeId(i)));
+                    return Err(UnifyError::Mismatch {
+                        node: NodeId(i),
+                        spans: spans_of(i),
+                        other: NodeId(*j),
+                        other_spans: spans_of(*j),
+                    });
                 }
             }
         };
     }
 
+    if let Some(class) = class_labels.iter().position(Option::is_none) {
+        let i = (0..f.hypergraph.nodes.len())
+            .find(|&i| coequalizer.table[i] == class)
+            .unwrap();
+        return Err(UnifyError::NotAllLabelsKnown {
+            node: NodeId(i),
+            spans: spans_of(i),
+        });
+    }
+
     let class_labels: Vec<O> = class_labels
         .into_iter()
-        .collect::<Option<_>>()
-        .ok_or(UnifyError::NotAllLabelsKnown)?;
+        .map(|label| label.unwrap().1)
+        .collect();
     let nodes = (0..f.hypergraph.nodes.len())
         .map(|i| class_labels[coequalizer.table[i]].clone())
         .collect();
-    let mut f = f
-        .with_nodes(|_| nodes)
-        .ok_or(UnifyError::NotAllLabelsKnown)?;
+    let mut f = f.with_nodes(|_| nodes).ok_or(UnifyError::Quotient)?;
     f.quotient().map_err(|_| UnifyError::Quotient)?;
     Ok(f)
 }
 
 impl<O: Clone + PartialEq, A: Clone> OpenHypergraphWithNames<Option<O>, A> {
-    /// Unify and quotient the open hypergraph, carrying names to quotient nodes.
+    /// Unify and quotient the open hypergraph, carrying names and spans to quotient nodes.
     pub fn unify(self) -> Result<OpenHypergraphWithNames<O, A>, UnifyError> {
         let coequalizer = self.graph.hypergraph.coequalizer();
         let names: HashMap<NodeId, Vec<Variable>> =
@@ -74,7 +124,24 @@ impl<O: Clone + PartialEq, A: Clone> OpenHypergraphWithNames<Option<O>, A> {
                     names
                 });
 
-        let graph = unify(self.graph)?;
-        Ok(OpenHypergraphWithNames { graph, names })
+        let graph = unify_located(self.graph, &self.spans)?;
+        let mut spans: HashMap<NodeId, Vec<Span>> =
+            self.spans
+                .into_iter()
+                .fold(Default::default(), |mut spans, (node, node_spans)| {
+                    spans
+                        .entry(NodeId(coequalizer.table[node.0]))
+                        .or_default()
+                        .extend(node_spans);
+                    spans
+                });
+        for node_spans in spans.values_mut() {
+            node_spans.sort_by_key(|span| span.start);
+        }
+        Ok(OpenHypergraphWithNames {
+            graph,
+            names,
+            spans,
+        })
     }
 }
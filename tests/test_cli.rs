@@ -0,0 +1,191 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+const SIGNATURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/signature.json");
+
+/// Run the `hexpr` binary with `args`, writing `stdin` to its standard input
+fn hexpr(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_hexpr"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> &str {
+    std::str::from_utf8(&output.stdout).unwrap()
+}
+
+fn stderr(output: &Output) -> &str {
+    std::str::from_utf8(&output.stderr).unwrap()
+}
+
+#[test]
+fn test_parse() {
+    let output = hexpr(&["parse", "(def sq ([x.] [.x x] mul))  ( sq\n neg )"], "");
+    assert!(output.status.success());
+    assert_eq!(
+        stdout(&output),
+        "(def sq ([x . ] [ . x x] mul))\n(sq neg)\n"
+    );
+
+    let output = hexpr(&["parse"], "{f g} # from stdin");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "{f g}\n");
+}
+
+#[test]
+fn test_pretty() {
+    let output = hexpr(&["pretty", "--width", "12"], "# keep me\n(aaaa bbbb cccc)");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "# keep me\n(aaaa\n  bbbb\n  cccc)\n");
+}
+
+#[test]
+fn test_check() {
+    let output = hexpr(&["check", "-s", SIGNATURE, "(add neg copy) 1"], "");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "ℝ ℝ -> ℝ ℝ\n-> ℝ\n");
+
+    // Wires with no known type fail to unify
+    let output = hexpr(&["check", "[x y]"], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("couldn't infer the type of a wire"));
+}
+
+#[test]
+fn test_emit_and_unify() {
+    let output = hexpr(&["emit", "-s", SIGNATURE, "(neg [x . x x])"], "");
+    assert!(output.status.success());
+    let graph: serde_json::Value = serde_json::from_str(stdout(&output)).unwrap();
+    assert_eq!(graph["edges"][0]["label"], "neg");
+    assert_eq!(graph["nodes"][0], "ℝ");
+    assert!(graph["nodes"]
+        .as_array()
+        .unwrap()
+        .contains(&serde_json::Value::Null));
+    assert!(!graph["quotient"].as_array().unwrap().is_empty());
+
+    let output = hexpr(&["unify", "-s", SIGNATURE, "(neg [x . x x])"], "");
+    assert!(output.status.success());
+    let graph: serde_json::Value = serde_json::from_str(stdout(&output)).unwrap();
+    assert_eq!(graph["nodes"], serde_json::json!(["ℝ", "ℝ"]));
+    assert_eq!(graph["sources"], serde_json::json!([0]));
+    assert_eq!(graph["targets"], serde_json::json!([1, 1]));
+    assert_eq!(graph["quotient"], serde_json::json!([]));
+}
+
+#[test]
+fn test_exit_codes() {
+    let output = hexpr(&["parse", "(f"], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("--> input:1:"));
+
+    let output = hexpr(&["emit", "-q", "-s", SIGNATURE, "(add add)"], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr(&output), "");
+
+    let output = hexpr(&["parse", "--file", "no/such/file.hexpr"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("can't read no/such/file.hexpr"));
+
+    let output = hexpr(&["check", "-s", "no/such/signature.json", "f"], "");
+    assert_eq!(output.status.code(), Some(2));
+
+    let output = hexpr(&["frobnicate"], "");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_emit_dot() {
+    let output = hexpr(
+        &[
+            "emit",
+            "--format",
+            "dot",
+            "--top-to-bottom",
+            "-s",
+            SIGNATURE,
+            "neg",
+        ],
+        "",
+    );
+    assert!(output.status.success());
+    assert!(stdout(&output).starts_with("digraph {\n  rankdir=TB;\n"));
+    assert!(stdout(&output).contains("e0 [label=\"{{<i0>}|neg|{<o0>}}\"];"));
+}
+
+#[test]
+fn test_emit_svg() {
+    let output = hexpr(
+        &[
+            "emit",
+            "--format",
+            "svg",
+            "-s",
+            SIGNATURE,
+            "(neg [x . x x])",
+        ],
+        "",
+    );
+    assert!(output.status.success());
+    assert!(stdout(&output).starts_with("<svg "));
+    assert!(stdout(&output).contains(">neg</text>"));
+    assert_eq!(stdout(&output).matches("<circle").count(), 1);
+}
+
+#[test]
+fn test_dot_options_need_dot() {
+    for (format, flag) in [("svg", "--top-to-bottom"), ("json", "--quotient")] {
+        let output = hexpr(&["unify", "--format", format, flag, "neg"], "");
+        assert_eq!(output.status.code(), Some(2));
+        assert_eq!(stdout(&output), "");
+        assert_eq!(
+            stderr(&output),
+            format!("error: {} only applies to --format dot\n", flag)
+        );
+    }
+    // JSON is the default format
+    let output = hexpr(&["emit", "--quotient", "neg"], "");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_deep_parse() {
+    let source = format!("{}[x]{}", "(".repeat(100_000), ")".repeat(100_000));
+    let output = hexpr(&["parse"], &source);
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), format!("{}\n", source));
+}
+
+#[test]
+fn test_closed_stdout() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_hexpr"))
+        .arg("parse")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Like `hexpr parse | head`, stop reading before anything is printed
+    drop(child.stdout.take());
+    let source = "(f g) ".repeat(100_000);
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(source.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(stderr(&output), "");
+}
@@ -0,0 +1,153 @@
+use hexpr::cst::{parse_hexpr, parse_program, SyntaxNode, TokenKind};
+use hexpr::parser::Rule;
+
+const SOURCE: &str = "# Squares
+(def square ([x.] [.x x] mul))   # x * x
+
+(def sum-of-squares
+  ({square square}  # in parallel
+   add : 2 -> 1))
+{ reshape<2, 3>{ mode = \"same\" } [ ] [a:ℝ . a a] 'my op' (let [y] (neg ^ 2)) {f * 3} -1.5 }
+";
+
+#[test]
+fn test_lossless() -> anyhow::Result<()> {
+    let cst = parse_program(SOURCE)?;
+    assert_eq!(cst.rule(), Rule::program);
+    assert_eq!(cst.to_string(), SOURCE);
+
+    // Every node prints as the source text it was parsed from
+    for node in cst.descendants() {
+        assert!(SOURCE.contains(&node.text()));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_trivia() -> anyhow::Result<()> {
+    let cst = parse_program(SOURCE)?;
+    let comments: Vec<&str> = cst
+        .tokens()
+        .into_iter()
+        .filter(|token| token.kind() == TokenKind::Comment)
+        .map(|token| token.as_str())
+        .collect();
+    assert_eq!(comments, ["# Squares", "# x * x", "# in parallel"]);
+
+    let hexpr = parse_hexpr("([x .] f->g : 1->1)")?;
+    let texts: Vec<&str> = hexpr
+        .tokens()
+        .into_iter()
+        .filter(|token| !token.is_trivia())
+        .map(|token| token.as_str())
+        .collect();
+    assert_eq!(
+        texts,
+        ["(", "[", "x", ".", "]", "f->g", ":", "1", "->", "1", ")"]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_derive_ast() -> anyhow::Result<()> {
+    let cst = parse_program(SOURCE)?;
+    assert_eq!(cst.to_program()?, hexpr::parse_program(SOURCE)?);
+
+    let definition = cst
+        .nodes()
+        .find(|node| node.rule() == Rule::definition)
+        .unwrap();
+    let body = definition
+        .nodes()
+        .find(|node| node.rule() == Rule::hexpr)
+        .unwrap();
+    assert_eq!(body.to_hexpr()?, "([x.] [.x x] mul)".parse()?);
+    Ok(())
+}
+
+#[test]
+fn test_rewrite() -> anyhow::Result<()> {
+    let mut cst = parse_program(SOURCE)?;
+    let replacement = parse_hexpr("(square   # renamed\n  id<1>)")?;
+    cst.rewrite(&mut |node: &SyntaxNode| {
+        (node.rule() == Rule::hexpr && node.text() == "square").then(|| replacement.clone())
+    });
+
+    // Only the rewritten nodes change
+    let expected = SOURCE.replace("{square square}", &format!("{{{0} {0}}}", replacement));
+    assert_eq!(cst.to_string(), expected);
+    assert_eq!(cst.to_program()?, hexpr::parse_program(&expected)?);
+    Ok(())
+}
+
+/// Valid and invalid programs, for checking that the syntax tree agrees with the AST parser
+const CORPUS: &[&str] = &[
+    SOURCE,
+    "",
+    "# only a comment",
+    "f g h",
+    "(f (g {h [x]}) [])",
+    "[x y . y x] [x] [ . ] [x:ℝ . x:ℝ] [_ . _]",
+    "(let [x y] [x . y] f)",
+    "{f * 3} (g ^ 2) ({f g} * 2)",
+    "(f g : 2 -> 1) {f : ℝ ℕ -> } (: ->)",
+    "copy<3> reshape<2, 3>{ mode = \"same\" } 'quoted op' -1.5 \"text\"",
+    "(def f neg) (def (g n) (f ^ n)) g<2>",
+    "(def (twice f) (f f)) twice<(f [x . x x])> twice<{f g}> twice<(let [x] [x.])>",
+    "(",
+    ")",
+    "{f g)",
+    "(f g}",
+    "(f {g (h)}",
+    "[x y",
+    "[x . y . z]",
+    "(f ^)",
+    "(f ^ 2 g)",
+    "{f * x}",
+    "(f : 1)",
+    "(f :)",
+    "(f ^ 1.5)",
+    "[x:]",
+    "[x . :y]",
+    "f<1",
+    "(f : 1 -> 1 g)",
+    "(f : g) (f * g) {f * 2 g} (f * 2) {f g * 2}",
+    "(let [x] f : 1 -> 1)",
+    "(let x f)",
+    "(def)",
+    "(def f)",
+    "(def f g h)",
+    "(def (f) g)",
+    "f<",
+    "'unclosed",
+    "\"unclosed",
+    "f ) g",
+    "(f) (g",
+    "([x .] [. x]",
+];
+
+#[test]
+fn test_parsers_agree() -> anyhow::Result<()> {
+    for source in CORPUS {
+        match (hexpr::parse_program(source), parse_program(source)) {
+            (Ok(program), Ok(cst)) => assert_eq!(cst.to_program()?, program, "{}", source),
+            (Err(reader), Err(cst)) => assert_eq!(
+                (
+                    reader.span().start,
+                    reader.span().line,
+                    reader.span().column
+                ),
+                (cst.span().start, cst.span().line, cst.span().column),
+                "{}: {} vs {}",
+                source,
+                reader,
+                cst
+            ),
+            (reader, cst) => panic!(
+                "{}: parsed to {:?} but the syntax tree is {:?}",
+                source, reader, cst
+            ),
+        }
+    }
+    Ok(())
+}
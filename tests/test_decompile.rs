@@ -0,0 +1,188 @@
+use hexpr::decompile::Style;
+use hexpr::*;
+use open_hypergraphs::lax::{Hypergraph, NodeId, OpenHypergraph};
+
+const EXAMPLES: &[&str] = &[
+    "(add neg copy)",
+    "{add copy}",
+    "[x:ℝ y:ℕ . y x]",
+    "[x:ℝ x . x]",
+    "[x:ℝ . x x]",
+    "[x:ℝ .]",
+    "[. x:ℝ]",
+    "([.x:ℕ] [x.])",
+    "([a b.] { ([.a b] add [acc.]) ([.a acc] mul [result.]) } [.result])",
+    "({[x:ℝ] neg} [a b . b a a] {add nat/zero [x]})",
+    "([x.] [.x y] add [y.] [.y])",
+    "({0 1} [a a . a] neg)",
+    "[]",
+];
+
+fn signature() -> JsonSignature {
+    JsonSignature::from_file(concat!(env!("CARGO_MANIFEST_DIR"), "/signature.json")).unwrap()
+}
+
+fn interpret(hexpr: &Hexpr) -> OpenHypergraph<Option<String>, Operation> {
+    try_interpret(&signature(), hexpr).unwrap()
+}
+
+/// Map the nodes `x` of one graph to `y` of another, failing if either is already mapped elsewhere
+fn bind(nodes: &mut [Option<usize>], x: NodeId, y: NodeId) -> bool {
+    match nodes[x.0] {
+        Some(z) => z == y.0,
+        None if nodes.contains(&Some(y.0)) => false,
+        None => {
+            nodes[x.0] = Some(y.0);
+            true
+        }
+    }
+}
+
+/// Find a map of the edges from `e` onwards to unused edges of `h`, consistent with `nodes`
+fn match_edges(
+    g: &Hypergraph<String, Operation>,
+    h: &Hypergraph<String, Operation>,
+    e: usize,
+    nodes: &[Option<usize>],
+    used: &mut [bool],
+) -> Option<Vec<Option<usize>>> {
+    if e == g.edges.len() {
+        return Some(nodes.to_vec());
+    }
+    for f in 0..h.edges.len() {
+        let (x, y) = (&g.adjacency[e], &h.adjacency[f]);
+        if used[f]
+            || g.edges[e] != h.edges[f]
+            || x.sources.len() != y.sources.len()
+            || x.targets.len() != y.targets.len()
+        {
+            continue;
+        }
+        let mut nodes = nodes.to_vec();
+        let ports = x.sources.iter().zip(&y.sources);
+        if ports
+            .chain(x.targets.iter().zip(&y.targets))
+            .all(|(&p, &q)| bind(&mut nodes, p, q))
+        {
+            used[f] = true;
+            if let Some(nodes) = match_edges(g, h, e + 1, &nodes, used) {
+                return Some(nodes);
+            }
+            used[f] = false;
+        }
+    }
+    None
+}
+
+/// Whether two open hypergraphs with no quotient are isomorphic, preserving labels
+fn isomorphic(
+    a: &OpenHypergraph<String, Operation>,
+    b: &OpenHypergraph<String, Operation>,
+) -> bool {
+    let (g, h) = (&a.hypergraph, &b.hypergraph);
+    if g.nodes.len() != h.nodes.len()
+        || g.edges.len() != h.edges.len()
+        || a.sources.len() != b.sources.len()
+        || a.targets.len() != b.targets.len()
+    {
+        return false;
+    }
+    let mut nodes = vec![None; g.nodes.len()];
+    let mut interface = a
+        .sources
+        .iter()
+        .zip(&b.sources)
+        .chain(a.targets.iter().zip(&b.targets));
+    if !interface.all(|(&x, &y)| bind(&mut nodes, x, y)) {
+        return false;
+    }
+    let Some(nodes) = match_edges(g, h, 0, &nodes, &mut vec![false; h.edges.len()]) else {
+        return false;
+    };
+    // Isolated nodes are matched by label
+    let mut isolated: Vec<&String> = (0..h.nodes.len())
+        .filter(|y| !nodes.contains(&Some(*y)))
+        .map(|y| &h.nodes[y])
+        .collect();
+    nodes.iter().enumerate().all(|(x, y)| match y {
+        Some(y) => g.nodes[x] == h.nodes[*y],
+        None => match isolated.iter().position(|&label| *label == g.nodes[x]) {
+            Some(i) => {
+                isolated.swap_remove(i);
+                true
+            }
+            None => false,
+        },
+    })
+}
+
+#[test]
+fn test_round_trip() {
+    for example in EXAMPLES {
+        let graph = interpret(&example.parse().unwrap());
+        let expected = unify(graph.clone()).unwrap();
+        for style in [Style::Imperative, Style::Layered] {
+            let hexpr = Hexpr::from_open_hypergraph(&graph, &signature(), style);
+            // Decompiled hexprs print and parse back
+            let printed = hexpr.to_string();
+            assert_eq!(printed.parse::<Hexpr>().unwrap(), hexpr, "{}", printed);
+            let actual = unify(interpret(&hexpr)).unwrap();
+            assert!(
+                isomorphic(&expected, &actual),
+                "{} decompiled to {}",
+                example,
+                printed
+            );
+        }
+    }
+}
+
+#[test]
+fn test_styles() {
+    let graph = interpret(&"([x.] [.x x] add neg)".parse().unwrap());
+    let imperative = Hexpr::from_open_hypergraph(&graph, &signature(), Style::Imperative);
+    assert_eq!(
+        imperative.to_string(),
+        "([x0 . ] {([ . x0 x0] add [x1 . ]) ([ . x1] neg [x2 . ])} [ . x2])"
+    );
+    let layered = Hexpr::from_open_hypergraph(&graph, &signature(), Style::Layered);
+    assert_eq!(layered.to_string(), "((let [x0] [x0 . x0 x0]) add neg)");
+
+    // Wires with no operation to give them a type are annotated
+    let graph = interpret(&"[x:ℝ y:ℕ . y x]".parse().unwrap());
+    let layered = Hexpr::from_open_hypergraph(&graph, &signature(), Style::Layered);
+    assert_eq!(layered.to_string(), "(let [x0 x1] [x0:ℝ x1:ℕ . x1 x0])");
+}
+
+/// Arrows named by index, as another tool might produce them
+struct Numbered;
+
+impl Notation for Numbered {
+    type Arr = usize;
+    type Obj = ();
+
+    fn operation(&self, arr: &usize) -> Operation {
+        Operation::new(format!("op {}", arr))
+    }
+}
+
+#[test]
+fn test_foreign_graph() {
+    let mut graph: OpenHypergraph<Option<()>, usize> = OpenHypergraph::empty();
+    let (_, (inputs, outputs)) = graph.new_operation(7, vec![None, None], vec![None]);
+    let (_, (negated, _)) = graph.new_operation(8, vec![None], vec![]);
+    graph.unify(outputs[0], negated[0]);
+    graph.sources = inputs.clone();
+    graph.targets = vec![inputs[1]];
+
+    let hexpr = Hexpr::from_open_hypergraph(&graph, &Numbered, Style::Imperative);
+    assert_eq!(
+        hexpr.to_string(),
+        "([x0 x1 . ] {([ . x0 x1] 'op 7' [x2 . ]) ([ . x2] 'op 8')} [ . x1])"
+    );
+    let layered = Hexpr::from_open_hypergraph(&graph, &Numbered, Style::Layered);
+    assert_eq!(
+        layered.to_string(),
+        "((let [x0 x1] [x0 x1 . x1 x0 x1]) {(let [x0] [x0 . x0]) 'op 7'} {(let [x0] [x0 . x0]) 'op 8'})"
+    );
+}
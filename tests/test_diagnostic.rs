@@ -0,0 +1,212 @@
+use hexpr::diagnostic::{Diagnostic, RenderOptions};
+use hexpr::*;
+
+#[derive(Debug, Clone)]
+enum Op {
+    Neg,
+    Add,
+    Zero,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("unknown operation")]
+struct UnknownOp;
+
+/// Negation and addition on reals, and a natural number zero
+struct Sig;
+
+impl Signature for Sig {
+    type Arr = Op;
+    type Obj = &'static str;
+    type Error = UnknownOp;
+
+    fn try_parse_op(&self, op: &Operation) -> Result<Self::Arr, Self::Error> {
+        match op.as_str() {
+            "neg" => Ok(Op::Neg),
+            "add" => Ok(Op::Add),
+            "zero" => Ok(Op::Zero),
+            _ => Err(UnknownOp),
+        }
+    }
+
+    fn profile(&self, op: &Self::Arr) -> (Vec<Option<Self::Obj>>, Vec<Option<Self::Obj>>) {
+        let r = Some("R");
+        match op {
+            Op::Neg => (vec![r], vec![r]),
+            Op::Add => (vec![r, r], vec![r]),
+            Op::Zero => (vec![], vec![Some("N")]),
+        }
+    }
+
+    fn try_parse_obj(&self, obj: &Object) -> Option<Self::Obj> {
+        match obj.as_str() {
+            "R" => Some("R"),
+            "N" => Some("N"),
+            _ => None,
+        }
+    }
+}
+
+fn render(source: &str) -> String {
+    let diagnostic: Diagnostic = match source.parse::<Hexpr>() {
+        Err(e) => e.into(),
+        Ok(hexpr) => match try_interpret_with_names(&Sig, &hexpr) {
+            Err(e) => e.into(),
+            Ok(result) => result.unify().unwrap_err().into(),
+        },
+    };
+    diagnostic.render(source, &RenderOptions::default())
+}
+
+#[test]
+fn test_render_composition_error() {
+    assert_eq!(
+        render("(neg add)"),
+        "\
+error: failed to compose
+ --> input:1:2
+  |
+1 | (neg add)
+  |  ^^^ these outputs have arity 1
+  |      --- these inputs have arity 2
+  |
+  = note: each expression in a composition must have as many outputs as the next has inputs
+"
+    );
+}
+
+#[test]
+fn test_render_signature_error() {
+    assert_eq!(
+        render("(neg\n  frob)"),
+        "\
+error: couldn't parse operation `frob`
+ --> input:2:3
+  |
+2 |   frob)
+  |   ^^^^ unknown operation
+"
+    );
+}
+
+#[test]
+fn test_render_parse_error() {
+    let rendered = render("(neg add");
+    assert!(rendered.starts_with("error: syntax error\n --> input:1:9\n"));
+    assert!(rendered.contains("1 | (neg add\n  |         ^ expected"));
+}
+
+#[test]
+fn test_render_mismatch() {
+    assert_eq!(
+        render("(zero\n\n neg)"),
+        "\
+error: mismatched wire types
+ --> input:3:2
+  |
+1 | (zero
+  |  ---- ...with the type given here
+...
+3 |  neg)
+  |  ^^^ this wire's type conflicts...
+  |
+  = note: connected wires must all have the same type
+"
+    );
+}
+
+#[test]
+fn test_render_colored() {
+    let diagnostic = Diagnostic::error("oops").with_label(Span::default(), "here");
+    let options = RenderOptions {
+        file_name: Some("example.hexpr".to_string()),
+        color: true,
+    };
+    let rendered = diagnostic.render("neg", &options);
+    assert!(rendered.contains("\x1b[1;31merror\x1b[0m"));
+    assert!(rendered.contains("example.hexpr:1:1"));
+}
+
+#[test]
+fn test_render_annotation_mismatch() {
+    assert_eq!(
+        render("(zero [x:R])"),
+        "\
+error: mismatched wire types
+ --> input:1:8
+  |
+1 | (zero [x:R])
+  |  ---- ...with the type given here
+  |        ^ this wire's type conflicts...
+  |
+  = note: connected wires must all have the same type
+"
+    );
+}
+
+#[test]
+fn test_render_ascription_arity() {
+    assert_eq!(
+        render("(neg (add : 1 -> 1))"),
+        "\
+error: interface doesn't match its ascription
+ --> input:1:6
+  |
+1 | (neg (add : 1 -> 1))
+  |      ^^^^^^^^^^^^^^ this has 2 inputs
+  |
+  = note: the ascription expects 1 inputs
+"
+    );
+}
+
+#[test]
+fn test_render_ascription_mismatch() {
+    assert_eq!(
+        render("(zero : -> R)"),
+        "\
+error: mismatched wire types
+ --> input:1:12
+  |
+1 | (zero : -> R)
+  |  ---- ...with the type given here
+  |            ^ this wire's type conflicts...
+  |
+  = note: connected wires must all have the same type
+"
+    );
+}
+
+#[test]
+fn test_render_literal_error() {
+    // By default, literals are parsed as operations
+    assert_eq!(
+        render("(2.5 neg)"),
+        "\
+error: couldn't parse literal `2.5`
+ --> input:1:2
+  |
+1 | (2.5 neg)
+  |  ^^^ unknown operation
+"
+    );
+}
+
+#[test]
+fn test_render_recursion_limit() {
+    let source = "(def (loop n) loop<n+1>)\nloop<0>";
+    let program: Program = source.parse().unwrap();
+    let diagnostic: Diagnostic = template::expand_with_limit(&program, 2).unwrap_err().into();
+    assert_eq!(
+        diagnostic.render(source, &RenderOptions::default()),
+        "\
+error: recursion limit reached while expanding `loop<2>`
+ --> input:1:15
+  |
+1 | (def (loop n) loop<n+1>)
+  |               ^^^^^^^^^ nested 2 templates deep
+  |
+  = note: is a definition for the base case missing?
+"
+    );
+}
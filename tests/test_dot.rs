@@ -0,0 +1,79 @@
+use hexpr::dot::{Direction, DotOptions};
+use hexpr::*;
+use serde_json::json;
+
+fn signature() -> JsonSignature {
+    JsonSignature::try_from(json!({
+        "add": { "inputs": ["R", "R"], "outputs": ["R"] },
+        "neg": { "inputs": ["R"], "outputs": ["R"] },
+        "a|b": { "inputs": [], "outputs": ["R"] },
+    }))
+    .unwrap()
+}
+
+fn dot(hexpr: &str, options: &DotOptions) -> String {
+    let hexpr = hexpr.parse().unwrap();
+    try_interpret_with_names(&signature(), &hexpr)
+        .unwrap()
+        .to_dot(options)
+}
+
+#[test]
+fn test_edges_and_interface() {
+    let dot = dot("(add neg)", &DotOptions::default());
+    assert!(dot.starts_with("digraph {\n  rankdir=LR;\n"));
+    assert!(dot.contains("sources [label=\"<s0> 0|<s1> 1\", style=dashed]"));
+    assert!(dot.contains("targets [label=\"<t0> 0\", style=dashed]"));
+    assert!(dot.contains("  e0 [label=\"{{<i0>|<i1>}|add|{<o0>}}\"];\n"));
+    assert!(dot.contains("  e1 [label=\"{{<i0>}|neg|{<o0>}}\"];\n"));
+    assert!(dot.contains("  n0 -> e0:i0:w;\n  n1 -> e0:i1:w;\n  e0:o0:e -> n2;\n"));
+    assert!(dot.contains("  sources:s0:e -> n0;\n  sources:s1:e -> n1;\n"));
+    assert!(dot.contains("  n2 [shape=point, xlabel=\"R\"];\n"));
+    assert!(dot.ends_with("}\n"));
+}
+
+#[test]
+fn test_names_and_quotient() {
+    let unquotiented = dot("([x . x] [y . y y])", &DotOptions::default());
+    assert!(unquotiented.contains("  n0 [shape=point, xlabel=\"x\"];\n"));
+    assert!(unquotiented.contains("  n1 [shape=point, xlabel=\"y\"];\n"));
+    assert!(unquotiented.contains("  n0 -> n1 [style=dashed, dir=none];\n"));
+
+    let options = DotOptions {
+        quotient: true,
+        ..DotOptions::default()
+    };
+    let quotiented = dot("([x . x] [y . y y])", &options);
+    assert!(quotiented.contains("  n0 [shape=point, xlabel=\"x, y\"];\n"));
+    assert!(!quotiented.contains("n1 "));
+    assert!(!quotiented.contains("dir=none"));
+    assert!(quotiented.contains("  n0 -> targets:t0:w;\n  n0 -> targets:t1:w;\n"));
+
+    // Names and types are merged with the nodes they are identified with
+    let typed = dot("([x.] [.x] neg)", &options);
+    assert!(typed.contains("xlabel=\"x : R\""));
+}
+
+#[test]
+fn test_top_to_bottom() {
+    let options = DotOptions {
+        direction: Direction::TopToBottom,
+        quotient: false,
+    };
+    let dot = dot("neg", &options);
+    assert!(dot.contains("rankdir=TB;"));
+    assert!(dot.contains("  n0 -> e0:i0:n;\n  e0:o0:s -> n1;\n"));
+}
+
+#[test]
+fn test_unified_and_escaped() {
+    let hexpr = "({a|b [x]} add)".parse().unwrap();
+    let graph = try_interpret_with_names(&signature(), &hexpr)
+        .unwrap()
+        .unify()
+        .unwrap();
+    let dot = graph.to_dot_with(&DotOptions::default(), |obj| Some(obj.clone()));
+    assert!(dot.contains("[label=\"{a\\|b|{<o0>}}\"]"));
+    assert!(dot.contains("xlabel=\"x : R\""));
+    assert!(!dot.contains("dir=none"));
+}
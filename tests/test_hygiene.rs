@@ -0,0 +1,94 @@
+use std::collections::HashSet;
+
+use hexpr::{Hexpr, Variable};
+
+fn vars(names: &[&str]) -> anyhow::Result<HashSet<Variable>> {
+    Ok(names
+        .iter()
+        .map(|name| name.parse())
+        .collect::<Result<_, _>>()?)
+}
+
+#[test]
+fn test_variables() -> anyhow::Result<()> {
+    let hexpr: Hexpr = "([x _ .] (let [y] [.y x]) add)".parse()?;
+    assert_eq!(hexpr.variables(), vars(&["x", "y"])?);
+    Ok(())
+}
+
+#[test]
+fn test_freshen() -> anyhow::Result<()> {
+    let hexpr: Hexpr = "([x y x_1 .] [. x y _] add)".parse()?;
+    let fresh = hexpr.freshen(&vars(&["y"])?, &vars(&["x_2"])?);
+    assert_eq!(fresh.to_string(), "([x_3 y x_4 . ] [ . x_3 y _] add)");
+    assert!(fresh.alpha_eq(&hexpr));
+    Ok(())
+}
+
+#[test]
+fn test_splice() -> anyhow::Result<()> {
+    let f: Hexpr = "([x.] [.x x] mul [y.] [.y])".parse()?;
+    let g: Hexpr = "([x.] [.x] neg [y.] [.y y])".parse()?;
+    let spliced = Hexpr::splice(&[f.clone(), g.clone()], &vars(&["y"])?);
+
+    // The first fragment is unchanged, and only the clashing x is renamed in the second
+    assert_eq!(spliced[0], f);
+    assert!(spliced[1].alpha_eq(&g));
+    assert_eq!(
+        spliced[1].variables(),
+        vars(&["x_1", "y"])?,
+        "{}",
+        spliced[1]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_alpha_eq() -> anyhow::Result<()> {
+    let alpha_eq = |a: &str, b: &str| -> anyhow::Result<bool> {
+        Ok(a.parse::<Hexpr>()?.alpha_eq(&b.parse()?))
+    };
+
+    assert!(alpha_eq("[x y . y x]", "[a b . b a]")?);
+    assert!(!alpha_eq("[x y . y x]", "[a b . a b]")?);
+    // renaming must be one-to-one
+    assert!(!alpha_eq("[x y . x]", "[a a . a]")?);
+    assert!(!alpha_eq("[x x . x]", "[a b . a]")?);
+    // operations are not renamed
+    assert!(!alpha_eq("(add [x])", "(mul [x])")?);
+    // anonymous variables only match each other
+    assert!(alpha_eq("[_ x . x]", "[_ y . y]")?);
+    assert!(!alpha_eq("[_ x . x]", "[z y . y]")?);
+    // scoped variables are distinct from top-level variables of the same name
+    assert!(alpha_eq(
+        "{(let [x] [x . x x]) [x]}",
+        "{(let [a] [a . a a]) [b]}"
+    )?);
+    assert!(!alpha_eq("{(let [x] [x . x x]) [x]}", "{[a . a a] [a]}")?);
+    // so are the variables of a power
+    assert!(alpha_eq("{{[x . x] * 2} [x]}", "{{[a . a] * 2} [b]}")?);
+    assert!(!alpha_eq("{[x . x] * 2}", "{[x . x] * 3}")?);
+    Ok(())
+}
+
+#[test]
+fn test_deep_nesting() -> anyhow::Result<()> {
+    // Renaming, comparing and copying don't recurse, so deeply nested hexprs work too
+    let depth = 100_000;
+    let nested = |x: &str| {
+        let body = format!("(let [{0}] [{0} . {0} y])", x);
+        format!("{}{}{}", "({".repeat(depth), body, "})".repeat(depth))
+    };
+    let hexpr: Hexpr = nested("x").parse()?;
+    let fresh = hexpr.freshen(&vars(&["y"])?, &HashSet::new());
+    assert_eq!(fresh.to_string(), nested("x_1"));
+    assert!(fresh.alpha_eq(&hexpr));
+    assert_ne!(fresh, hexpr);
+    let copy = hexpr.clone();
+    assert_eq!(copy, hexpr);
+
+    for hexpr in [hexpr, fresh, copy] {
+        hexpr.drop_iteratively();
+    }
+    Ok(())
+}
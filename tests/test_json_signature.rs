@@ -0,0 +1,120 @@
+use hexpr::interpret::Error;
+use hexpr::json::{JsonOpError, JsonSignatureError};
+use hexpr::*;
+use serde_json::json;
+
+fn signature() -> JsonSignature {
+    JsonSignature::from_file(concat!(env!("CARGO_MANIFEST_DIR"), "/signature.json")).unwrap()
+}
+
+#[test]
+fn test_repository_signature() -> anyhow::Result<()> {
+    let signature = signature();
+    assert_eq!(
+        signature.operation("nat/add"),
+        Some((
+            &["ℕ".to_string(), "ℕ".to_string()][..],
+            &["ℕ".to_string()][..]
+        ))
+    );
+
+    let hexpr = "(add neg copy)".parse()?;
+    let result = try_interpret(&signature, &hexpr)?;
+    let result = unify(result)?;
+    assert_eq!(result.sources.len(), 2);
+    assert_eq!(result.targets.len(), 2);
+    let labels: Vec<_> = result
+        .hypergraph
+        .edges
+        .iter()
+        .map(|op| op.as_str())
+        .collect();
+    assert_eq!(labels, ["add", "neg", "copy"]);
+    assert!(result.hypergraph.nodes.iter().all(|obj| obj == "ℝ"));
+
+    Ok(())
+}
+
+#[test]
+fn test_literals_and_annotations() -> anyhow::Result<()> {
+    let signature = signature();
+
+    let hexpr = "({1 [x:ℝ]} add)".parse()?;
+    let result = unify(try_interpret(&signature, &hexpr)?)?;
+    assert_eq!(result.sources.len(), 1);
+    assert_eq!(result.targets.len(), 1);
+
+    let hexpr = "[x:ℂ]".parse()?;
+    assert!(matches!(
+        try_interpret(&signature, &hexpr),
+        Err(Error::Object(_))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_unknown_operations() -> anyhow::Result<()> {
+    let signature = signature();
+
+    let hexpr = "frob".parse()?;
+    assert!(matches!(
+        try_interpret(&signature, &hexpr),
+        Err(Error::Signature(_, JsonOpError::Unknown))
+    ));
+
+    let hexpr = "add<2>".parse()?;
+    assert!(matches!(
+        try_interpret(&signature, &hexpr),
+        Err(Error::Signature(_, JsonOpError::Params))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_load_from_value() -> anyhow::Result<()> {
+    let signature = JsonSignature::try_from(json!({
+        "f": { "inputs": ["A"], "outputs": ["B", "B"] },
+    }))?;
+    let result = unify(try_interpret(&signature, &"(f [x y . y x])".parse()?)?)?;
+    assert_eq!(result.sources.len(), 1);
+    assert_eq!(result.targets.len(), 2);
+    assert_eq!(signature.operations().collect::<Vec<_>>(), ["f"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_invalid_signatures() {
+    let invalid = |value: serde_json::Value| JsonSignature::from_value(&value).unwrap_err();
+
+    assert!(matches!(
+        invalid(json!(["add"])),
+        JsonSignatureError::NotAnObject
+    ));
+    assert_eq!(
+        invalid(json!({ "f": { "inputs": [] } })).to_string(),
+        "operation `f`: missing `outputs`"
+    );
+    assert_eq!(
+        invalid(json!({ "f": { "inputs": [], "outputs": [], "arity": 2 } })).to_string(),
+        "operation `f`: unexpected field `arity`"
+    );
+    assert_eq!(
+        invalid(json!({ "f": { "inputs": "ℝ", "outputs": [] } })).to_string(),
+        "operation `f`: `inputs` must be a list of object names"
+    );
+    assert_eq!(
+        invalid(json!({ "f": { "inputs": [1], "outputs": [] } })).to_string(),
+        "operation `f`: `inputs` contains 1, which is not an object name"
+    );
+    assert_eq!(
+        invalid(json!({ "f": { "inputs": [], "outputs": ["a b"] } })).to_string(),
+        "operation `f`: `outputs` contains \"a b\", which is not an object name"
+    );
+    assert!(matches!(
+        "{".parse::<JsonSignature>(),
+        Err(JsonSignatureError::Json(_))
+    ));
+}
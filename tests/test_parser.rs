@@ -1,13 +1,16 @@
-use hexpr::ast::Hexpr;
-use hexpr::parser::HExprParser;
+use hexpr::ast::{
+    Definition, Hexpr, Item, LiteralValue, Operation, Param, Pattern, Ports, PowerKind, Template,
+    Value, Variable,
+};
+use hexpr::limit::{Limit, LimitExceeded};
+use hexpr::parser::{
+    parse_hexprs, parse_hexprs_recovering, parse_program, HExprParser, ParseError, ParseOptions,
+};
 
 #[test]
 fn test_basic_frobenius_join() -> anyhow::Result<()> {
     let result = HExprParser::parse_hexpr("[x x . x]").unwrap();
-    let expected = Hexpr::Frobenius {
-        sources: vec!["x".parse()?, "x".parse()?],
-        targets: vec!["x".parse()?],
-    };
+    let expected = Hexpr::frobenius(vec!["x".parse()?, "x".parse()?], vec!["x".parse()?]);
 
     assert_eq!(result, expected);
     Ok(())
@@ -18,10 +21,7 @@ fn test_basic_frobenius_split() -> anyhow::Result<()> {
     let result = HExprParser::parse_hexpr("[x . x x]").unwrap();
     assert_eq!(
         result,
-        Hexpr::Frobenius {
-            sources: vec!["x".parse()?],
-            targets: vec!["x".parse()?, "x".parse()?],
-        }
+        Hexpr::frobenius(vec!["x".parse()?], vec!["x".parse()?, "x".parse()?])
     );
     Ok(())
 }
@@ -29,26 +29,16 @@ fn test_basic_frobenius_split() -> anyhow::Result<()> {
 #[test]
 fn test_identity_shorthand() -> anyhow::Result<()> {
     let result = HExprParser::parse_hexpr("[x y]").unwrap();
-    assert_eq!(
-        result,
-        Hexpr::Frobenius {
-            sources: vec!["x".parse()?, "y".parse()?],
-            targets: vec!["x".parse()?, "y".parse()?],
-        }
-    );
+    assert_eq!(result, Hexpr::identity(vec!["x".parse()?, "y".parse()?]));
+    // The shorthand is printed back as it was written
+    assert_eq!(result.to_string(), "[x y]");
     Ok(())
 }
 
 #[test]
 fn test_named_identity() -> anyhow::Result<()> {
     let result = HExprParser::parse_hexpr("[a]").unwrap();
-    assert_eq!(
-        result,
-        Hexpr::Frobenius {
-            sources: vec!["a".parse()?],
-            targets: vec!["a".parse()?],
-        }
-    );
+    assert_eq!(result, Hexpr::identity(vec!["a".parse()?]));
     Ok(())
 }
 
@@ -57,15 +47,9 @@ fn test_identity_via_composition() -> anyhow::Result<()> {
     let result = HExprParser::parse_hexpr("([x.][.x])").unwrap();
     assert_eq!(
         result,
-        Hexpr::Composition(vec![
-            Hexpr::Frobenius {
-                sources: vec!["x".parse()?],
-                targets: vec![],
-            },
-            Hexpr::Frobenius {
-                sources: vec![],
-                targets: vec!["x".parse()?],
-            },
+        Hexpr::composition(vec![
+            Hexpr::frobenius(vec!["x".parse()?], vec![]),
+            Hexpr::frobenius(vec![], vec!["x".parse()?]),
         ])
     );
     Ok(())
@@ -76,12 +60,9 @@ fn test_subtraction_pointfree() -> anyhow::Result<()> {
     let result = HExprParser::parse_hexpr("({[a] -} +)").unwrap();
     assert_eq!(
         result,
-        Hexpr::Composition(vec![
-            Hexpr::Tensor(vec![
-                Hexpr::Frobenius {
-                    sources: vec!["a".parse()?],
-                    targets: vec!["a".parse()?],
-                },
+        Hexpr::composition(vec![
+            Hexpr::tensor(vec![
+                Hexpr::identity(vec!["a".parse()?]),
                 Hexpr::Operation("-".parse()?),
             ]),
             Hexpr::Operation("+".parse()?),
@@ -95,26 +76,14 @@ fn test_subtraction_pointed() -> anyhow::Result<()> {
     let result = HExprParser::parse_hexpr("([x y.] ([.y] - [z.]) [.x z] +)").unwrap();
     assert_eq!(
         result,
-        Hexpr::Composition(vec![
-            Hexpr::Frobenius {
-                sources: vec!["x".parse()?, "y".parse()?],
-                targets: vec![],
-            },
-            Hexpr::Composition(vec![
-                Hexpr::Frobenius {
-                    sources: vec![],
-                    targets: vec!["y".parse()?],
-                },
+        Hexpr::composition(vec![
+            Hexpr::frobenius(vec!["x".parse()?, "y".parse()?], vec![]),
+            Hexpr::composition(vec![
+                Hexpr::frobenius(vec![], vec!["y".parse()?]),
                 Hexpr::Operation("-".parse()?),
-                Hexpr::Frobenius {
-                    sources: vec!["z".parse()?],
-                    targets: vec![],
-                },
+                Hexpr::frobenius(vec!["z".parse()?], vec![]),
             ]),
-            Hexpr::Frobenius {
-                sources: vec![],
-                targets: vec!["x".parse()?, "z".parse()?],
-            },
+            Hexpr::frobenius(vec![], vec!["x".parse()?, "z".parse()?]),
             Hexpr::Operation("+".parse()?),
         ])
     );
@@ -126,10 +95,10 @@ fn test_explicit_swap_relation() -> anyhow::Result<()> {
     let result = HExprParser::parse_hexpr("[x y . y x]").unwrap();
     assert_eq!(
         result,
-        Hexpr::Frobenius {
-            sources: vec!["x".parse()?, "y".parse()?],
-            targets: vec!["y".parse()?, "x".parse()?],
-        }
+        Hexpr::frobenius(
+            vec!["x".parse()?, "y".parse()?],
+            vec!["y".parse()?, "x".parse()?]
+        )
     );
     Ok(())
 }
@@ -137,38 +106,20 @@ fn test_explicit_swap_relation() -> anyhow::Result<()> {
 #[test]
 fn test_empty_inputs_outputs() {
     let result = HExprParser::parse_hexpr("[.]").unwrap();
-    assert_eq!(
-        result,
-        Hexpr::Frobenius {
-            sources: vec![],
-            targets: vec![],
-        }
-    );
+    assert_eq!(result, Hexpr::frobenius(vec![], vec![]));
 }
 
 #[test]
 fn test_discard_variable() -> anyhow::Result<()> {
     let result = HExprParser::parse_hexpr("[x .]").unwrap();
-    assert_eq!(
-        result,
-        Hexpr::Frobenius {
-            sources: vec!["x".parse()?],
-            targets: vec![],
-        }
-    );
+    assert_eq!(result, Hexpr::frobenius(vec!["x".parse()?], vec![]));
     Ok(())
 }
 
 #[test]
 fn test_create_variable() -> anyhow::Result<()> {
     let result = HExprParser::parse_hexpr("[. x]").unwrap();
-    assert_eq!(
-        result,
-        Hexpr::Frobenius {
-            sources: vec![],
-            targets: vec!["x".parse()?],
-        }
-    );
+    assert_eq!(result, Hexpr::frobenius(vec![], vec!["x".parse()?]));
     Ok(())
 }
 
@@ -177,10 +128,7 @@ fn test_dispell_summon_named() -> anyhow::Result<()> {
     let result = HExprParser::parse_hexpr("[a b . c]").unwrap();
     assert_eq!(
         result,
-        Hexpr::Frobenius {
-            sources: vec!["a".parse()?, "b".parse()?],
-            targets: vec!["c".parse()?],
-        }
+        Hexpr::frobenius(vec!["a".parse()?, "b".parse()?], vec!["c".parse()?])
     );
     Ok(())
 }
@@ -190,7 +138,7 @@ fn test_complex_composition() -> anyhow::Result<()> {
     let result = HExprParser::parse_hexpr("(add sub mul)").unwrap();
     assert_eq!(
         result,
-        Hexpr::Composition(vec![
+        Hexpr::composition(vec![
             Hexpr::Operation("add".parse()?),
             Hexpr::Operation("sub".parse()?),
             Hexpr::Operation("mul".parse()?),
@@ -204,7 +152,7 @@ fn test_complex_tensor() -> anyhow::Result<()> {
     let result = HExprParser::parse_hexpr("{add sub mul}").unwrap();
     assert_eq!(
         result,
-        Hexpr::Tensor(vec![
+        Hexpr::tensor(vec![
             Hexpr::Operation("add".parse()?),
             Hexpr::Operation("sub".parse()?),
             Hexpr::Operation("mul".parse()?),
@@ -218,9 +166,9 @@ fn test_nested_expressions() -> anyhow::Result<()> {
     let result = HExprParser::parse_hexpr("({add} (sub mul))").unwrap();
     assert_eq!(
         result,
-        Hexpr::Composition(vec![
-            Hexpr::Tensor(vec![Hexpr::Operation("add".parse()?)]),
-            Hexpr::Composition(vec![
+        Hexpr::composition(vec![
+            Hexpr::tensor(vec![Hexpr::Operation("add".parse()?)]),
+            Hexpr::composition(vec![
                 Hexpr::Operation("sub".parse()?),
                 Hexpr::Operation("mul".parse()?),
             ]),
@@ -241,7 +189,7 @@ fn test_whitespace_handling() -> anyhow::Result<()> {
     let result = HExprParser::parse_hexpr("  ( add   sub  )  ").unwrap();
     assert_eq!(
         result,
-        Hexpr::Composition(vec![
+        Hexpr::composition(vec![
             Hexpr::Operation("add".parse()?),
             Hexpr::Operation("sub".parse()?),
         ])
@@ -256,3 +204,603 @@ fn test_invalid_syntax() {
     assert!(HExprParser::parse_hexpr("{").is_err());
     assert!(HExprParser::parse_hexpr("").is_err());
 }
+
+#[test]
+fn test_recovering_well_formed() -> anyhow::Result<()> {
+    let input = "(add sub) {[x] neg}";
+    let (hexprs, errors) = parse_hexprs_recovering(input);
+    assert!(errors.is_empty());
+    assert_eq!(hexprs, parse_hexprs(input)?);
+    Ok(())
+}
+
+#[test]
+fn test_recovering_unclosed() -> anyhow::Result<()> {
+    let (hexprs, errors) = parse_hexprs_recovering("(add {neg) (sub");
+    assert_eq!(
+        hexprs,
+        vec![
+            Hexpr::composition(vec![
+                Hexpr::Operation("add".parse()?),
+                Hexpr::tensor(vec![Hexpr::Operation("neg".parse()?)]),
+            ]),
+            Hexpr::composition(vec![Hexpr::Operation("sub".parse()?)]),
+        ]
+    );
+    let messages: Vec<_> = errors.iter().map(|e| e.message()).collect();
+    assert_eq!(messages, vec!["unclosed `{`", "unclosed `(`"]);
+    assert_eq!(errors[0].span().start, 5);
+    assert_eq!(errors[1].span().start, 11);
+    Ok(())
+}
+
+#[test]
+fn test_recovering_unexpected() -> anyhow::Result<()> {
+    let (hexprs, errors) = parse_hexprs_recovering("] (add , ; neg) [x . y @]");
+    assert_eq!(
+        hexprs,
+        vec![
+            Hexpr::Error(Default::default()),
+            Hexpr::composition(vec![
+                Hexpr::Operation("add".parse()?),
+                Hexpr::Error(Default::default()),
+                Hexpr::Operation("neg".parse()?),
+            ]),
+            Hexpr::frobenius(vec!["x".parse()?], vec!["y".parse()?]),
+        ]
+    );
+    let messages: Vec<_> = errors.iter().map(|e| e.message()).collect();
+    assert_eq!(
+        messages,
+        vec!["unexpected `]`", "unexpected `, ;`", "unexpected `@`"]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_recovering_empty_group() {
+    let (hexprs, errors) = parse_hexprs_recovering("()");
+    assert_eq!(
+        hexprs,
+        vec![Hexpr::composition(vec![Hexpr::Error(Default::default())])]
+    );
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].message(), "expected hexpr");
+}
+
+#[test]
+fn test_anonymous_variable() -> anyhow::Result<()> {
+    let result = HExprParser::parse_hexpr("[_ x . _]")?;
+    let Hexpr::Frobenius {
+        sources, targets, ..
+    } = &result
+    else {
+        panic!("expected frobenius");
+    };
+    assert!(sources[0].is_anonymous());
+    assert!(!sources[1].is_anonymous());
+    assert!(targets[0].is_anonymous());
+    assert!(!"_x".parse::<Variable>()?.is_anonymous());
+    Ok(())
+}
+
+#[test]
+fn test_program_definitions() -> anyhow::Result<()> {
+    let program = parse_program("(def square ([x.] [.x x] mul))\n(square add)")?;
+    assert_eq!(
+        program.items,
+        vec![
+            Item::Definition(Definition {
+                name: "square".parse()?,
+                body: HExprParser::parse_hexpr("([x.] [.x x] mul)")?,
+                span: Default::default(),
+            }),
+            Item::Hexpr(HExprParser::parse_hexpr("(square add)")?),
+        ]
+    );
+    assert_eq!(
+        program.to_string(),
+        "(def square ([x . ] [ . x x] mul))\n(square add)\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_program_templates() -> anyhow::Result<()> {
+    let program = parse_program("(def (tree 1) id)\n(def (tree n f) (f tree<n-1,f>))")?;
+    assert_eq!(
+        program.items,
+        vec![
+            Item::Template(Template {
+                name: "tree".parse()?,
+                params: vec![Pattern::Integer(1)],
+                body: HExprParser::parse_hexpr("id")?,
+                span: Default::default(),
+            }),
+            Item::Template(Template {
+                name: "tree".parse()?,
+                params: vec![
+                    Pattern::Name("n".to_string()),
+                    Pattern::Name("f".to_string())
+                ],
+                body: HExprParser::parse_hexpr("(f tree<n-1,f>)")?,
+                span: Default::default(),
+            }),
+        ]
+    );
+    assert_eq!(
+        program.to_string(),
+        "(def (tree 1) id)\n(def (tree n f) (f tree<n-1,f>))\n"
+    );
+    assert!(parse_program("(def (f<1> n) g)").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_program_def_keyword() -> anyhow::Result<()> {
+    // `def` is only a keyword when followed by whitespace
+    let program = parse_program("(define x)")?;
+    assert_eq!(
+        program.items,
+        vec![Item::Hexpr(HExprParser::parse_hexpr("(define x)")?)]
+    );
+
+    // a malformed definition is an error, not a composition
+    assert!(parse_program("(def a b c)").is_err());
+    assert!(parse_program("(def a)").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_scope() -> anyhow::Result<()> {
+    let result = HExprParser::parse_hexpr("(let [x y] [.x] neg)")?;
+    assert_eq!(
+        result,
+        Hexpr::scope(
+            vec!["x".parse()?, "y".parse()?],
+            vec![
+                Hexpr::frobenius(vec![], vec!["x".parse()?]),
+                Hexpr::Operation("neg".parse()?),
+            ]
+        )
+    );
+    assert_eq!(result.to_string(), "(let [x y] [ . x] neg)");
+
+    // `let` is only a keyword when followed by whitespace
+    assert!(matches!(
+        HExprParser::parse_hexpr("(letter x)")?,
+        Hexpr::Composition(..)
+    ));
+    assert!(HExprParser::parse_hexpr("(let x)").is_err());
+    assert!(HExprParser::parse_hexpr("(let [x])").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_annotated_variables() -> anyhow::Result<()> {
+    let result = HExprParser::parse_hexpr("[x:ℝ y:nat/N . x]")?;
+    let Hexpr::Frobenius {
+        sources, targets, ..
+    } = &result
+    else {
+        panic!("expected frobenius");
+    };
+    assert_eq!(sources[0].annotation(), Some(&"ℝ".parse()?));
+    assert_eq!(sources[1].annotation(), Some(&"nat/N".parse()?));
+    assert_eq!(targets[0].annotation(), None);
+    assert_eq!(result.to_string(), "[x:ℝ y:nat/N . x]");
+
+    // Annotations are part of the expression
+    assert_ne!(result, HExprParser::parse_hexpr("[x y . x]")?);
+    // but must be attached to their variable
+    assert!(HExprParser::parse_hexpr("[x : ℝ]").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_ascription() -> anyhow::Result<()> {
+    let result = HExprParser::parse_hexpr("(f g : ℝ ℝ -> ℝ)")?;
+    assert_eq!(
+        result,
+        Hexpr::ascription(
+            Hexpr::composition(vec![
+                Hexpr::Operation("f".parse()?),
+                Hexpr::Operation("g".parse()?),
+            ]),
+            Ports::Objects(vec!["ℝ".parse()?, "ℝ".parse()?]),
+            Ports::Objects(vec!["ℝ".parse()?]),
+        )
+    );
+    assert_eq!(result.to_string(), "(f g : ℝ ℝ -> ℝ)");
+
+    let result = HExprParser::parse_hexpr("{f g : 2 -> 1}")?;
+    assert_eq!(
+        result,
+        Hexpr::ascription(
+            Hexpr::tensor(vec![
+                Hexpr::Operation("f".parse()?),
+                Hexpr::Operation("g".parse()?),
+            ]),
+            Ports::Arity(2),
+            Ports::Arity(1),
+        )
+    );
+    assert_eq!(result.to_string(), "{f g : 2 -> 1}");
+
+    // Either side may be empty, and the arrow needs no spaces around objects
+    let result = HExprParser::parse_hexpr("(zero : ->ℝ)")?;
+    assert_eq!(result.to_string(), "(zero : -> ℝ)");
+    assert_eq!(
+        HExprParser::parse_hexpr("([x.] : ℝ ->)")?.to_string(),
+        "([x . ] : ℝ ->)"
+    );
+
+    // Operations may contain `:`, and a lone `:` is an operation unless an ascription
+    // ends the brackets there
+    assert!(matches!(
+        HExprParser::parse_hexpr("(a:b ::)")?,
+        Hexpr::Composition(..)
+    ));
+    let result = HExprParser::parse_hexpr("(f : g)")?;
+    assert_eq!(
+        result,
+        Hexpr::composition(vec![
+            Hexpr::Operation("f".parse()?),
+            Hexpr::Operation(Operation::new(":")),
+            Hexpr::Operation("g".parse()?),
+        ])
+    );
+    // Printed quoted, so that it can't be read as an ascription
+    assert_eq!(result.to_string(), "(f ':' g)");
+    assert!(matches!(
+        HExprParser::parse_hexpr("(f :)")?,
+        Hexpr::Composition(..)
+    ));
+    let result = HExprParser::parse_hexpr("(f : 1 -> 1 g)")?;
+    assert!(matches!(&result, Hexpr::Composition(hexprs, _) if hexprs.len() == 6));
+    assert_eq!(HExprParser::parse_hexpr(&result.to_string())?, result);
+    Ok(())
+}
+
+#[test]
+fn test_ascription_recovering() {
+    // Scopes have no ascriptions
+    let (hexprs, errors) = parse_hexprs_recovering("(let [x] f : 1 -> 1) (h : 1 -> 1)");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].message(), "unexpected `:`");
+    assert!(matches!(hexprs[1], Hexpr::Ascription { .. }));
+}
+
+#[test]
+fn test_literals() -> anyhow::Result<()> {
+    let result = HExprParser::parse_hexpr(r#"(2.5 -2 0x1f 1e-3 "say \"hi\"\n" add)"#)?;
+    let Hexpr::Composition(hexprs, _) = &result else {
+        panic!("expected composition");
+    };
+    let values: Vec<Option<LiteralValue>> = hexprs
+        .iter()
+        .map(|h| match h {
+            Hexpr::Literal(literal) => Some(literal.value().clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        values,
+        vec![
+            Some(LiteralValue::Float(2.5)),
+            Some(LiteralValue::Integer(-2)),
+            Some(LiteralValue::Integer(31)),
+            Some(LiteralValue::Float(0.001)),
+            Some(LiteralValue::String("say \"hi\"\n".to_string())),
+            None,
+        ]
+    );
+    // Literals are printed as written
+    assert_eq!(
+        result.to_string(),
+        r#"(2.5 -2 0x1f 1e-3 "say \"hi\"\n" add)"#
+    );
+
+    // Numbers running into operation characters are operations
+    for op in ["1.2.3", "2x", "-", "-x", "0xg", "1+"] {
+        assert!(
+            matches!(HExprParser::parse_hexpr(op)?, Hexpr::Operation(_)),
+            "{}",
+            op
+        );
+    }
+    assert!(HExprParser::parse_hexpr(r#""unterminated"#).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_operation_params() -> anyhow::Result<()> {
+    let op: Operation = "reshape<2,3>".parse()?;
+    assert_eq!(op.as_str(), "reshape");
+    assert_eq!(
+        op.positional().collect::<Vec<_>>(),
+        vec![&Value::Integer(2), &Value::Integer(3)]
+    );
+
+    let op: Operation = r#"conv2d{kernel=3 stride=-2, padding="same" dims=[1 2.5 [x]]}"#.parse()?;
+    assert_eq!(op.as_str(), "conv2d");
+    assert_eq!(op.param("kernel"), Some(&Value::Integer(3)));
+    assert_eq!(op.param("stride"), Some(&Value::Integer(-2)));
+    assert_eq!(
+        op.param("padding"),
+        Some(&Value::String("same".to_string()))
+    );
+    assert_eq!(
+        op.param("dims"),
+        Some(&Value::List(vec![
+            Value::Integer(1),
+            Value::Float(2.5),
+            Value::List(vec![Value::Symbol("x".to_string())]),
+        ]))
+    );
+    assert_eq!(
+        op.to_string(),
+        r#"conv2d{kernel=3 stride=-2 padding="same" dims=[1,2.5,[x]]}"#
+    );
+
+    // Parameters are part of an operation's identity
+    assert_ne!(op, "conv2d".parse()?);
+    assert_eq!(
+        HExprParser::parse_hexpr("(f<1>{a=b} g)")?.to_string(),
+        "(f<1>{a=b} g)"
+    );
+
+    // Parameters must follow the name immediately
+    assert_eq!(
+        HExprParser::parse_hexpr("(f {x=1})")?,
+        Hexpr::composition(vec![
+            Hexpr::Operation("f".parse()?),
+            Hexpr::tensor(vec![Hexpr::Operation("x=1".parse()?)]),
+        ])
+    );
+    assert!(HExprParser::parse_hexpr("f<1").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_float_params() -> anyhow::Result<()> {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    let hash = |op: &Operation| {
+        let mut hasher = DefaultHasher::new();
+        op.hash(&mut hasher);
+        hasher.finish()
+    };
+    // `-0.0` and `0.0` are equal, so they hash and order the same
+    let (negative, positive): (Operation, Operation) = ("f<-0.0>".parse()?, "f<0.0>".parse()?);
+    assert_eq!(negative, positive);
+    assert_eq!(hash(&negative), hash(&positive));
+    assert_eq!(negative.cmp(&positive), std::cmp::Ordering::Equal);
+    let (small, large): (Operation, Operation) = ("f<9.5>".parse()?, "f<10.5>".parse()?);
+    assert!(small < large);
+
+    // Numbers too large for an `f64` have no finite value to print back
+    assert!("f<1e999>".parse::<Operation>().is_err());
+    assert!("-1e999".parse::<hexpr::ast::Literal>().is_err());
+    for source in ["(f<[1 -1e999]> g)", "(g 1e999)", "(def f<1e999> g)"] {
+        let error = HExprParser::parse_program(source).unwrap_err();
+        assert_eq!(error.message(), "number is too large", "{}", source);
+    }
+    let (_, errors) = parse_hexprs_recovering("(f<1e999> g) 1e999");
+    let messages: Vec<String> = errors.iter().map(|e| e.message()).collect();
+    assert_eq!(messages, vec!["number is too large"; 2]);
+    Ok(())
+}
+
+#[test]
+fn test_power() -> anyhow::Result<()> {
+    let result = HExprParser::parse_hexpr("{neg * 8}")?;
+    assert_eq!(
+        result,
+        Hexpr::power(Hexpr::Operation("neg".parse()?), PowerKind::Tensor, 8)
+    );
+    assert_eq!(result.to_string(), "{neg * 8}");
+
+    let result = HExprParser::parse_hexpr("(([x . x x] add)^4)")?;
+    assert_eq!(
+        result,
+        Hexpr::power(
+            HExprParser::parse_hexpr("([x . x x] add)")?,
+            PowerKind::Composition,
+            4
+        )
+    );
+    assert_eq!(result.to_string(), "(([x . x x] add) ^ 4)");
+
+    // A lone `*` is an operation unless a power ends the brackets there
+    let result = HExprParser::parse_hexpr("(f * g)")?;
+    assert_eq!(
+        result,
+        Hexpr::composition(vec![
+            Hexpr::Operation("f".parse()?),
+            Hexpr::Operation(Operation::new("*")),
+            Hexpr::Operation("g".parse()?),
+        ])
+    );
+    assert_eq!(result.to_string(), "(f '*' g)");
+    for source in ["{neg *}", "(neg * 2)", "{neg * 2 add}", "{neg ** *2}"] {
+        let result = HExprParser::parse_hexpr(source)?;
+        assert!(!matches!(result, Hexpr::Power { .. }), "{}", source);
+        assert_eq!(HExprParser::parse_hexpr(&result.to_string())?, result);
+    }
+    // Powers repeat a single hexpr a positive number of times
+    assert!(HExprParser::parse_hexpr("{neg add * 2}").is_err());
+    assert!(HExprParser::parse_hexpr("(neg ^ 0)").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_unicode_identifiers() -> anyhow::Result<()> {
+    let result = HExprParser::parse_hexpr("({σ ⊕} ∂ [α β . β α] ×<2> f→g)")?;
+    let Hexpr::Composition(hexprs, _) = &result else {
+        panic!("expected composition");
+    };
+    assert_eq!(
+        hexprs[1..],
+        [
+            Hexpr::Operation("∂".parse()?),
+            Hexpr::frobenius(
+                vec!["α".parse()?, "β".parse()?],
+                vec!["β".parse()?, "α".parse()?]
+            ),
+            Hexpr::Operation("×<2>".parse()?),
+            Hexpr::Operation("f→g".parse()?),
+        ]
+    );
+    assert_eq!(result.to_string(), "({σ ⊕} ∂ [α β . β α] ×<2> f→g)");
+
+    // Names round-trip through `Display` and `FromStr`
+    for name in ["σ", "⊕", "nat/add", "conv2d{kernel=3}", "x-1"] {
+        assert_eq!(name.parse::<Operation>()?.to_string(), name);
+    }
+    for name in ["α", "x_1", "wire-2", "x:ℝ"] {
+        assert_eq!(name.parse::<Variable>()?.to_string(), name);
+    }
+    let var: Variable = "x:ℝ".parse()?;
+    assert_eq!(var.annotation(), Some(&"ℝ".parse()?));
+
+    // The whole input must be a single name
+    assert!("f g".parse::<Operation>().is_err());
+    assert!("x y".parse::<Variable>().is_err());
+    // Variables are identifiers, not symbols
+    assert!("⊕".parse::<Variable>().is_err());
+    Ok(())
+}
+
+#[test]
+fn test_quoted_operation_names() -> anyhow::Result<()> {
+    let result = HExprParser::parse_hexpr(r"('my op (v2)' 'it\'s'<2> 'a\\b' 'add')")?;
+    assert_eq!(
+        result,
+        Hexpr::composition(vec![
+            Hexpr::Operation(Operation::new("my op (v2)")),
+            Hexpr::Operation(Operation::new("it's").with_param(Param {
+                name: None,
+                value: Value::Integer(2),
+            })),
+            Hexpr::Operation(Operation::new(r"a\b")),
+            // Quoting a plain name makes no difference
+            Hexpr::Operation(Operation::new("add")),
+        ])
+    );
+    assert_eq!(result.to_string(), r"('my op (v2)' 'it\'s'<2> 'a\\b' add)");
+
+    // Names which wouldn't parse back as the same operation are quoted
+    for (name, printed) in [
+        ("", "''"),
+        ("x y", "'x y'"),
+        ("0", "'0'"),
+        ("-1.5", "'-1.5'"),
+        ("\"hello\"", "'\"hello\"'"),
+        (":", "':'"),
+        ("*", "'*'"),
+        ("let", "'let'"),
+        ("line\nbreak", r"'line\nbreak'"),
+        ("onnx::Gemm_0", "onnx::Gemm_0"),
+    ] {
+        let op = Operation::new(name);
+        assert_eq!(op.to_string(), printed);
+        assert_eq!(printed.parse::<Operation>()?, op);
+        let hexpr = Hexpr::composition(vec![Hexpr::Operation(op)]);
+        assert_eq!(HExprParser::parse_hexpr(&hexpr.to_string())?, hexpr);
+    }
+
+    // Templates and definitions can have quoted names
+    let program = parse_program(r"(def 'my op' add) (def ('n ops' n) neg)")?;
+    assert_eq!(program.definitions().next().unwrap().name.as_str(), "my op");
+    assert_eq!(program.templates().next().unwrap().name.as_str(), "n ops");
+
+    assert!(HExprParser::parse_hexpr("'unclosed").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_deep_nesting() -> anyhow::Result<()> {
+    let depth = 100_000;
+    let source = format!("{}f{}", "({".repeat(depth), "})".repeat(depth));
+    let parsed = HExprParser::parse_hexpr(&source)?;
+    let mut hexpr = &parsed;
+    let mut levels = 0;
+    while let [child] = hexpr.children() {
+        hexpr = child;
+        levels += 1;
+    }
+    assert_eq!(levels, 2 * depth);
+    assert_eq!(hexpr.span().start, 2 * depth);
+    // Dropping as usual would recurse
+    parsed.drop_iteratively();
+
+    // Printing doesn't recurse either
+    let mut source = String::new();
+    for i in 0..depth {
+        source.push_str(["(neg ", "{", "(let [x] ", "(", "{"][i % 5]);
+    }
+    source.push_str("[x]");
+    for i in (0..depth).rev() {
+        source.push_str([")", "}", ")", " ^ 1)", " : 1 -> 1}"][i % 5]);
+    }
+    let parsed = HExprParser::parse_hexpr(&source)?;
+    assert_eq!(parsed.to_string(), source);
+    parsed.drop_iteratively();
+
+    // Errors are still reported at their location
+    let unclosed = format!("(def f {}f{})", "(".repeat(depth), ")".repeat(depth - 1));
+    let error = HExprParser::parse_program(&unclosed).unwrap_err();
+    assert_eq!(error.span().start, unclosed.len());
+    Ok(())
+}
+
+#[test]
+fn test_move_out_of_hexpr() -> anyhow::Result<()> {
+    // Hexprs have no `Drop` impl, so their fields can be moved out
+    let Hexpr::Power { hexpr, count, .. } = HExprParser::parse_hexpr("(f ^ 3)")? else {
+        panic!("expected a power");
+    };
+    assert_eq!((*hexpr, count), ("f".parse()?, 3));
+    Ok(())
+}
+
+#[test]
+fn test_parse_limits() -> anyhow::Result<()> {
+    let options = ParseOptions {
+        max_input_len: 40,
+        max_depth: 2,
+        max_operations: 3,
+        max_variables: 2,
+    };
+    let parse = |input: &str| HExprParser::parse_hexprs_with_options(input, &options);
+    assert!(parse("(f {g [x y . y x]}) h")?.len() == 2);
+
+    for (input, limit, start) in [
+        (
+            "(f {g [x . x]} # a comment that is too long",
+            Limit::InputLength,
+            40,
+        ),
+        ("(f {g (h)})", Limit::Depth, 6),
+        ("(f g) h 1.5", Limit::Operations, 8),
+        ("[x y] [_ _ . _] [y z]", Limit::Variables, 19),
+    ] {
+        match parse(input) {
+            Err(ParseError::Limit(LimitExceeded { limit: l, .. }, span)) => {
+                assert_eq!((l, span.start), (limit, start), "{}", input);
+            }
+            other => panic!(
+                "expected {:?} to be exceeded by {}, got {:?}",
+                limit, input, other
+            ),
+        }
+    }
+
+    // Limits apply to the whole program, including definitions
+    let program = "(def f (a b)) (def g (c d))";
+    let error = HExprParser::parse_program_with_options(program, &options).unwrap_err();
+    assert_eq!(error.message(), "more than 3 operations");
+    Ok(())
+}
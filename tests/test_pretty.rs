@@ -0,0 +1,135 @@
+use hexpr::ast::Hexpr;
+use hexpr::{cst, parse_program, PrettyOptions};
+
+const IMPERATIVE: &str = "([a b.] {([.a b] add [acc.]) ([.a acc] mul [result.])} [.result])";
+
+fn width(width: usize) -> PrettyOptions {
+    PrettyOptions {
+        width,
+        ..PrettyOptions::default()
+    }
+}
+
+#[test]
+fn test_fits_on_one_line() -> anyhow::Result<()> {
+    let hexpr: Hexpr = IMPERATIVE.parse()?;
+    assert_eq!(hexpr.pretty(&PrettyOptions::default()), IMPERATIVE);
+    Ok(())
+}
+
+#[test]
+fn test_breaks_to_width() -> anyhow::Result<()> {
+    let hexpr: Hexpr = IMPERATIVE.parse()?;
+    assert_eq!(
+        hexpr.pretty(&width(40)),
+        "\
+([a b.]
+  {([.a b] add [acc.])
+    ([.a acc] mul [result.])}
+  [.result])"
+    );
+
+    let indent = PrettyOptions {
+        indent: 4,
+        ..width(30)
+    };
+    let hexpr: Hexpr = "(let [x] ((first-operation long-operation-name) ^ 2) {f * 3})".parse()?;
+    assert_eq!(
+        hexpr.pretty(&indent),
+        "\
+(let [x]
+    ((first-operation
+            long-operation-name)
+        ^ 2)
+    {f * 3})"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_frobenius_alignment() -> anyhow::Result<()> {
+    let hexpr: Hexpr = "(f [first second third . third second first])".parse()?;
+    assert_eq!(
+        hexpr.pretty(&width(25)),
+        "\
+(f
+  [first second third
+   . third second first])"
+    );
+    assert_eq!(
+        hexpr.pretty(&width(16)),
+        "\
+(f
+  [first
+   second
+   third
+   . third
+     second
+     first])"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_identity_shorthand() -> anyhow::Result<()> {
+    let hexpr: Hexpr = "([x y . x y] [x:ℝ . x:ℝ] [x . y] [.] [_ . _])".parse()?;
+    assert_eq!(
+        hexpr.pretty(&PrettyOptions::default()),
+        "([x y . x y] [x:ℝ . x:ℝ] [x . y] [] [_ . _])"
+    );
+    let shorthand = PrettyOptions {
+        identity_shorthand: true,
+        ..PrettyOptions::default()
+    };
+    let pretty = hexpr.pretty(&shorthand);
+    // Anonymous wires are fresh at every occurrence, so `[_ . _]` isn't an identity
+    assert_eq!(pretty, "([x y] [x:ℝ] [x . y] [] [_ . _])");
+    assert_eq!(pretty.parse::<Hexpr>()?.pretty(&shorthand), pretty);
+    Ok(())
+}
+
+#[test]
+fn test_round_trip() -> anyhow::Result<()> {
+    let program = parse_program(
+        "(def (negs n) (neg negs<n-1>))
+         (def square ([x.] [.x x] mul : ℝ -> ℝ))
+         {reshape<2,3>{mode=\"same\"} 'my op' -1.5 (let [y] [y . y y] {add * 2})}",
+    )?;
+    for w in [0, 10, 20, 40, 80] {
+        let pretty = program.pretty(&width(w));
+        assert_eq!(parse_program(&pretty)?, program, "width {}:\n{}", w, pretty);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_keeps_comments() -> anyhow::Result<()> {
+    let source = "# Squares
+(def square ([x.]   [.x x] mul))   # x * x
+
+
+
+(def sum-of-squares
+  ({square square}  # in parallel
+   # then
+   add))
+";
+    let node = cst::parse_program(source)?;
+    let pretty = node.pretty(&width(32));
+    assert_eq!(
+        pretty,
+        "# Squares
+(def square ([x.] [.x x] mul)) # x * x
+
+(def sum-of-squares
+  ({square square} # in parallel
+    # then
+    add))
+"
+    );
+    assert_eq!(
+        cst::parse_program(&pretty)?.to_program()?,
+        node.to_program()?
+    );
+    Ok(())
+}
@@ -0,0 +1,82 @@
+use hexpr::svg::{to_svg, to_svg_with};
+use hexpr::*;
+use serde_json::json;
+
+fn signature() -> JsonSignature {
+    JsonSignature::try_from(json!({
+        "add": { "inputs": ["R", "R"], "outputs": ["R"] },
+        "neg": { "inputs": ["R"], "outputs": ["R"] },
+        "a<b": { "inputs": [], "outputs": ["R"] },
+    }))
+    .unwrap()
+}
+
+fn svg(hexpr: &str, options: &SvgOptions) -> String {
+    let hexpr = hexpr.parse().unwrap();
+    let graph = try_interpret_with_names(&signature(), &hexpr).unwrap();
+    to_svg(&graph.graph, options)
+}
+
+/// The x coordinate of the box around the text `op`
+fn box_x(svg: &str, op: &str) -> f64 {
+    let text = svg.find(&format!(">{}</text>", op)).unwrap();
+    let x = &svg[..text].rsplit("<text x=\"").next().unwrap();
+    x.split('"').next().unwrap().parse().unwrap()
+}
+
+#[test]
+fn test_boxes_and_wires() {
+    let svg = svg("(add neg)", &SvgOptions::default());
+    assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" "));
+    assert!(svg.ends_with("</svg>\n"));
+    assert!(svg.contains("<rect width=\"100%\" height=\"100%\" fill=\"#4a4a4a\"/>"));
+    assert_eq!(svg.matches("<rect x=").count(), 2);
+    // Two wires into `add`, one from `add` to `neg`, and one out of `neg`
+    assert_eq!(svg.matches("<path ").count(), 4);
+    assert!(!svg.contains("<circle"));
+    assert!(box_x(&svg, "add") < box_x(&svg, "neg"));
+    assert!(svg.contains(">R</text>"));
+}
+
+#[test]
+fn test_spiders() {
+    let options = SvgOptions::default();
+    assert_eq!(svg("[x . x x]", &options).matches("<circle").count(), 1);
+    assert_eq!(svg("[x.]", &options).matches("<circle").count(), 1);
+    assert_eq!(
+        svg("{[.x] [y . y y]}", &options).matches("<circle").count(),
+        2
+    );
+    // Identities and wires identified by the quotient are plain wires
+    assert!(!svg("[x y]", &options).contains("<circle"));
+    assert!(!svg("([x . x] [y . y] neg)", &options).contains("<circle"));
+}
+
+#[test]
+fn test_options_and_escaping() {
+    let options = SvgOptions {
+        foreground: "black".to_string(),
+        background: None,
+        font_size: 10.0,
+    };
+    let svg = svg("({'a<b' [x]} add)", &options);
+    assert!(svg.contains(">'a&lt;b'</text>"));
+    assert!(svg.contains("font-size=\"10\""));
+    assert!(svg.contains("stroke=\"black\""));
+    assert!(!svg.contains("height=\"100%\""));
+    assert!(svg.contains("fill=\"none\" stroke=\"black\""));
+}
+
+#[test]
+fn test_unified() {
+    let hexpr = "(neg [x . x x])".parse().unwrap();
+    let graph = try_interpret_with_names(&signature(), &hexpr)
+        .unwrap()
+        .unify()
+        .unwrap();
+    let svg = to_svg_with(&graph.graph, &SvgOptions::default(), |obj| {
+        Some(format!("{}!", obj))
+    });
+    assert_eq!(svg.matches("<circle").count(), 1);
+    assert!(svg.contains(">R!</text>"));
+}
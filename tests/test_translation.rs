@@ -2,10 +2,13 @@ use hexpr::*;
 use open_hypergraphs::lax::OpenHypergraph;
 
 /// Operations in polynomial circuits
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 enum ArithOp {
     Add,
     Neg,
+    Const(f64),
+    /// Sum of `n` inputs
+    Sum(usize),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -24,6 +27,10 @@ impl Signature for PolyCirc {
         match op.as_str() {
             "add" => Ok(ArithOp::Add),
             "neg" => Ok(ArithOp::Neg),
+            "sum" => match op.positional().collect::<Vec<_>>()[..] {
+                [Value::Integer(n)] if *n >= 0 => Ok(ArithOp::Sum(*n as usize)),
+                _ => Err(ParseError(format!("expected sum<n>, got {}", op))),
+            },
             op => Err(ParseError(format!("invalid op: {}", op))),
         }
     }
@@ -33,6 +40,20 @@ impl Signature for PolyCirc {
         match op {
             ArithOp::Add => (vec![ob, ob], vec![ob]),
             ArithOp::Neg => (vec![ob], vec![ob]),
+            ArithOp::Const(_) => (vec![], vec![ob]),
+            ArithOp::Sum(n) => (vec![ob; *n], vec![ob]),
+        }
+    }
+
+    fn try_parse_obj(&self, obj: &Object) -> Option<Self::Obj> {
+        (obj.as_str() == "ℝ").then_some(())
+    }
+
+    fn try_parse_literal(&self, literal: &Literal) -> Result<Self::Arr, Self::Error> {
+        match literal.value() {
+            LiteralValue::Integer(n) => Ok(ArithOp::Const(*n as f64)),
+            LiteralValue::Float(x) => Ok(ArithOp::Const(*x)),
+            LiteralValue::String(_) => Err(ParseError(format!("not a number: {}", literal))),
         }
     }
 }
@@ -52,6 +73,41 @@ fn test_simple_operation() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_labels_need_not_be_clone() -> anyhow::Result<()> {
+    /// Objects and arrows which can't be copied
+    struct Wire;
+    struct Op(String);
+    struct Unique;
+
+    impl Signature for Unique {
+        type Arr = Op;
+        type Obj = Wire;
+        type Error = ParseError;
+
+        fn try_parse_op(&self, op: &Operation) -> Result<Self::Arr, Self::Error> {
+            Ok(Op(op.as_str().to_string()))
+        }
+
+        fn profile(&self, _: &Self::Arr) -> (Vec<Option<Self::Obj>>, Vec<Option<Self::Obj>>) {
+            (vec![Some(Wire)], vec![Some(Wire)])
+        }
+    }
+
+    let hexpr = "(f [x . x x] {g h})".parse()?;
+    let result = try_interpret_with_options(&Unique, &hexpr, &InterpretOptions::default())?;
+    let names: Vec<&str> = result
+        .graph
+        .hypergraph
+        .edges
+        .iter()
+        .map(|op| op.0.as_str())
+        .collect();
+    assert_eq!(names, vec!["f", "g", "h"]);
+    assert!(try_interpret(&Unique, &hexpr).is_ok());
+    Ok(())
+}
+
 #[test]
 fn test_composition() -> anyhow::Result<()> {
     let hexpr = "(add neg)".parse()?;
@@ -67,6 +123,24 @@ fn test_composition() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_anonymous_not_identity() -> anyhow::Result<()> {
+    // Written out in full, `_` is still fresh at every occurrence
+    let signature = PolyCirc;
+    for source in ["[_ . _]", "[_ x . _ x]"] {
+        let hexpr = source.parse()?;
+        let result = try_interpret(&signature, &hexpr)?;
+        assert_ne!(result.sources[0], result.targets[0], "{}", source);
+        assert!(result.hypergraph.quotient.0.is_empty(), "{}", source);
+    }
+
+    let hexpr = "[_ x . _ x]".parse()?;
+    let result = try_interpret(&signature, &hexpr)?;
+    assert_eq!(result.hypergraph.nodes.len(), 3);
+    assert_eq!(result.sources[1], result.targets[1]);
+    Ok(())
+}
+
 #[test]
 fn test_frobenius() -> anyhow::Result<()> {
     let hexpr = "[x y . x]".parse()?;
@@ -143,3 +217,673 @@ fn test_all() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_composition_error_location() -> anyhow::Result<()> {
+    let hexpr = "(add\n add)".parse()?;
+    let signature = PolyCirc;
+    let err = try_interpret(&signature, &hexpr).unwrap_err();
+
+    match err {
+        interpret::Error::Composition {
+            left,
+            left_arity,
+            right,
+            right_arity,
+        } => {
+            assert_eq!((left.line, left.column), (1, 2));
+            assert_eq!((right.line, right.column), (2, 2));
+            assert_eq!((left_arity, right_arity), (1, 2));
+        }
+        e => panic!("unexpected error {e}"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_unify_error_location() -> anyhow::Result<()> {
+    let hexpr = "({[x . x x] [y.]} add)".parse()?;
+    let signature = PolyCirc;
+    let err = try_interpret_with_names(&signature, &hexpr)?
+        .unify()
+        .unwrap_err();
+
+    // x is copied into add so gets a type, but y is discarded and never constrained
+    let span = err.span().expect("error should be located");
+    assert_eq!(&"({[x . x x] [y.]} add)"[span.start..span.end], "y");
+
+    Ok(())
+}
+
+#[test]
+fn test_interpret_recovered() {
+    let (hexprs, errors) = parse_hexprs_recovering("(add neg) (add ; neg)");
+    assert_eq!(errors.len(), 1);
+
+    let signature = PolyCirc;
+    assert!(try_interpret(&signature, &hexprs[0]).is_ok());
+    assert!(matches!(
+        try_interpret(&signature, &hexprs[1]),
+        Err(interpret::Error::Syntax(span)) if span.start == 15
+    ));
+}
+
+#[test]
+fn test_anonymous_variables() -> anyhow::Result<()> {
+    let hexpr = "[_ _ . _]".parse()?;
+    let signature = PolyCirc;
+    let result = try_interpret_with_names(&signature, &hexpr)?;
+
+    // Every `_` is a distinct wire, and none of them are named
+    assert_eq!(result.graph.hypergraph.nodes.len(), 3);
+    assert_ne!(result.graph.sources[0], result.graph.sources[1]);
+    assert!(result.names.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_anonymous_identity() -> anyhow::Result<()> {
+    let hexpr = "({[_] neg} add [_])".parse()?;
+    let signature = PolyCirc;
+    let result: OpenHypergraph<Option<()>, ArithOp> = try_interpret(&signature, &hexpr)?;
+    let result = unify(result)?;
+
+    assert_eq!(result.sources.len(), 2);
+    assert_eq!(result.targets.len(), 1);
+    assert_eq!(result.hypergraph.edges.len(), 2);
+    assert_eq!(result.hypergraph.nodes.len(), 4);
+
+    Ok(())
+}
+
+#[test]
+fn test_program_inlining() -> anyhow::Result<()> {
+    let program: Program = "
+        (def sub ({[x] neg} add))
+        (def sub3 ({sub [z]} sub))
+        (sub3 neg)
+    "
+    .parse()?;
+    let signature = PolyCirc;
+    let results = try_interpret_program(&signature, &program)?;
+    assert_eq!(results.len(), 1);
+
+    let result = results.into_iter().next().unwrap().unify()?;
+    assert_eq!(result.graph.sources.len(), 3);
+    assert_eq!(result.graph.targets.len(), 1);
+    assert_eq!(result.graph.hypergraph.edges.len(), 5);
+    assert_eq!(result.graph.hypergraph.nodes.len(), 8);
+
+    // Variables are local to each definition
+    assert!(result.names.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_program_redefinition() -> anyhow::Result<()> {
+    let program: Program = "(def f neg) (def f add)".parse()?;
+    let signature = PolyCirc;
+    assert!(matches!(
+        try_interpret_program(&signature, &program),
+        Err(interpret::Error::Redefinition { previous, .. }) if previous.start == 5
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_program_undefined() -> anyhow::Result<()> {
+    // definitions are only visible to later items
+    let program: Program = "(f) (def f neg)".parse()?;
+    let signature = PolyCirc;
+    assert!(matches!(
+        try_interpret_program(&signature, &program),
+        Err(interpret::Error::Signature(op, _)) if op.as_str() == "f"
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_scope_isolates_variables() -> anyhow::Result<()> {
+    let signature = PolyCirc;
+
+    // Without scopes, both brackets share the wire x
+    let hexpr = "{[x . x x] [x . x x]}".parse()?;
+    let result = try_interpret(&signature, &hexpr)?;
+    assert_eq!(result.hypergraph.nodes.len(), 1);
+
+    let hexpr = "{(let [x] [x . x x]) (let [x] [x . x x])}".parse()?;
+    let result = try_interpret_with_names(&signature, &hexpr)?;
+    assert_eq!(result.graph.hypergraph.nodes.len(), 2);
+    assert_ne!(result.graph.sources[0], result.graph.sources[1]);
+    assert_eq!(result.names.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_scope_shadowing() -> anyhow::Result<()> {
+    let signature = PolyCirc;
+
+    // The inner x shadows the outer one, while y is not declared so refers to the outer y
+    let hexpr = "([x y.] (let [x] [.y] neg [x.]) [.x x] add)".parse()?;
+    let result = try_interpret_with_names(&signature, &hexpr)?.unify()?;
+    assert_eq!(result.graph.sources.len(), 2);
+    assert_eq!(result.graph.targets.len(), 1);
+    assert_eq!(result.graph.hypergraph.edges.len(), 2);
+
+    // The outer x is fed to add, the inner x is discarded
+    let [x, y] = [0, 1].map(|i| result.graph.sources[i]);
+    assert_eq!(result.names[&x], vec!["x".parse()?]);
+    assert_eq!(result.names[&y], vec!["y".parse()?]);
+    let add = &result.graph.hypergraph.adjacency[1];
+    assert_eq!(add.sources, vec![x, x]);
+
+    Ok(())
+}
+
+#[test]
+fn test_annotated_frobenius() -> anyhow::Result<()> {
+    let hexpr = "[x:ℝ y:ℝ . x]".parse()?;
+    let signature = PolyCirc;
+    let result: OpenHypergraph<Option<()>, ArithOp> = try_interpret(&signature, &hexpr)?;
+    assert_eq!(result.hypergraph.nodes, vec![Some(()), Some(())]);
+
+    // Unlike the unannotated test_frobenius, all wires have types
+    let result = unify(result)?;
+    assert_eq!(result.sources.len(), 2);
+    assert_eq!(result.targets.len(), 1);
+    assert_eq!(result.hypergraph.nodes.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_annotation_one_occurrence() -> anyhow::Result<()> {
+    // One annotation types every occurrence of the variable
+    let hexpr = "([x:ℝ . x x] [a b . b])".parse()?;
+    let signature = PolyCirc;
+    let result = try_interpret_with_names(&signature, &hexpr)?.unify()?;
+    assert_eq!(result.graph.sources.len(), 1);
+    assert_eq!(result.graph.targets.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_target_annotation() -> anyhow::Result<()> {
+    // Annotations on the targets are checked even when they name the same wires as the sources
+    let hexpr = "[x:ℝ . x:ℕ]".parse()?;
+    let signature = PolyCirc;
+    assert!(matches!(
+        try_interpret(&signature, &hexpr),
+        Err(interpret::Error::Object(obj)) if obj.as_str() == "ℕ"
+    ));
+
+    let signature = JsonSignature::try_from(serde_json::json!({
+        "zero": { "inputs": [], "outputs": ["ℕ"] },
+        "neg": { "inputs": ["ℝ"], "outputs": ["ℝ"] },
+    }))?;
+    let hexpr = "[x:ℝ . x:ℕ]".parse()?;
+    let result = try_interpret_with_names(&signature, &hexpr)?;
+    assert!(matches!(
+        result.unify(),
+        Err(hexpr::unify::UnifyError::Mismatch { .. })
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_unknown_object() -> anyhow::Result<()> {
+    let hexpr = "[x:ℕ]".parse()?;
+    let signature = PolyCirc;
+    assert!(matches!(
+        try_interpret(&signature, &hexpr),
+        Err(interpret::Error::Object(obj)) if obj.as_str() == "ℕ"
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_ascription() -> anyhow::Result<()> {
+    let signature = PolyCirc;
+    for source in [
+        "(add neg : 2 -> 1)",
+        "({neg neg} add : ℝ ℝ -> ℝ)",
+        "{add neg : 3 -> 2}",
+    ] {
+        let hexpr = source.parse()?;
+        let result = try_interpret_with_names(&signature, &hexpr)?.unify()?;
+        assert!(result.graph.hypergraph.edges.len() >= 2, "{}", source);
+    }
+
+    // Ascribed types label wires, like annotations
+    let hexpr = "([x.] [.x] : ℝ -> ℝ)".parse()?;
+    let result = try_interpret(&signature, &hexpr)?;
+    assert_eq!(unify(result)?.hypergraph.nodes, vec![()]);
+    Ok(())
+}
+
+#[test]
+fn test_ascription_arity_mismatch() -> anyhow::Result<()> {
+    let hexpr = "(neg (add neg : 1 -> 1))".parse()?;
+    let signature = PolyCirc;
+    match try_interpret(&signature, &hexpr) {
+        Err(
+            e @ interpret::Error::Ascription {
+                boundary: interpret::Boundary::Sources,
+                expected: 1,
+                actual: 2,
+                ..
+            },
+        ) => assert_eq!(e.span().start, 5),
+        other => panic!("expected ascription error, got {:?}", other),
+    }
+
+    let hexpr = "(add : ℝ ℝ -> ℝ ℝ)".parse()?;
+    assert!(matches!(
+        try_interpret(&signature, &hexpr),
+        Err(interpret::Error::Ascription {
+            boundary: interpret::Boundary::Targets,
+            expected: 2,
+            actual: 1,
+            ..
+        })
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_literals() -> anyhow::Result<()> {
+    let hexpr = "({1.5 -2} add)".parse()?;
+    let signature = PolyCirc;
+    let result = unify(try_interpret(&signature, &hexpr)?)?;
+    assert_eq!(result.sources.len(), 0);
+    assert_eq!(result.targets.len(), 1);
+    let constants: Vec<f64> = result
+        .hypergraph
+        .edges
+        .iter()
+        .filter_map(|op| match op {
+            ArithOp::Const(x) => Some(*x),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(constants, vec![1.5, -2.0]);
+
+    let hexpr = r#"({1 "one"} add)"#.parse()?;
+    match try_interpret(&signature, &hexpr) {
+        Err(e @ interpret::Error::Literal(..)) => assert_eq!(e.span().start, 4),
+        other => panic!("expected literal error, got {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_parameterised_operation() -> anyhow::Result<()> {
+    let signature = PolyCirc;
+    let hexpr = "({neg neg neg} sum<3>)".parse()?;
+    let result = unify(try_interpret(&signature, &hexpr)?)?;
+    assert_eq!(result.sources.len(), 3);
+    assert_eq!(result.targets.len(), 1);
+
+    let hexpr = "sum<x>".parse()?;
+    assert!(matches!(
+        try_interpret(&signature, &hexpr),
+        Err(interpret::Error::Signature(op, _)) if op.to_string() == "sum<x>"
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_templates() -> anyhow::Result<()> {
+    let program: Program = "
+        (def (twice f) (f f))
+        (def (negs 1) neg)
+        (def (negs n) (neg negs<n-1>))
+        (def (par 1 f) f)
+        (def (par n f) {f par<n-1,f>})
+        twice<neg>
+        negs<4>
+        par<3,neg>
+        ({twice<neg> neg} add : 2 -> 1)
+    "
+    .parse()?;
+    let signature = PolyCirc;
+    let results = try_interpret_program(&signature, &program)?;
+    let shapes: Vec<(usize, usize, usize)> = results
+        .iter()
+        .map(|r| {
+            let g = &r.graph;
+            (g.sources.len(), g.targets.len(), g.hypergraph.edges.len())
+        })
+        .collect();
+    assert_eq!(shapes, vec![(1, 1, 2), (1, 1, 4), (3, 3, 3), (2, 1, 4)]);
+    Ok(())
+}
+
+#[test]
+fn test_template_expansion() -> anyhow::Result<()> {
+    let program: Program = "
+        (def (sums n) {sum<n> sum<n+1>})
+        (def (diag) [x . x x])
+        sums<2>
+        {diag diag}
+        (def (constant c) c)
+        constant<1.5>
+    "
+    .parse()?;
+    let expanded = template::expand(&program)?;
+    let hexprs: Vec<String> = expanded.hexprs().map(|h| h.to_string()).collect();
+    // Each expansion gets its own variables
+    assert_eq!(
+        hexprs,
+        vec![
+            "{sum<2> sum<3>}",
+            "{(let [x] [x . x x]) (let [x] [x . x x])}",
+            "1.5"
+        ]
+    );
+    assert_eq!(expanded.templates().count(), 0);
+
+    let signature = PolyCirc;
+    let results = try_interpret_program(&signature, &program)?;
+    assert_eq!(results[1].graph.sources.len(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_template_hexpr_arguments() -> anyhow::Result<()> {
+    let program: Program = "
+        (def (twice f) (f f))
+        (def (negs 1) neg)
+        (def (negs n) (neg negs<n-1>))
+        (def (wrap n f) twice<({f negs<n>})>)
+        twice<(neg neg)>
+        twice<([x . x x] add)>
+        wrap<2,{neg neg}>
+    "
+    .parse()?;
+    let expanded = template::expand(&program)?;
+    let hexprs: Vec<String> = expanded.hexprs().map(|h| h.to_string()).collect();
+    // Each copy of an argument has its own wires
+    assert_eq!(
+        hexprs[1],
+        "(let [x] ((let [x] ([x . x x] add)) (let [x] ([x . x x] add))))"
+    );
+
+    let results = try_interpret_program(&PolyCirc, &program)?;
+    let shapes: Vec<(usize, usize, usize)> = results
+        .iter()
+        .map(|r| {
+            let g = &r.graph;
+            (g.sources.len(), g.targets.len(), g.hypergraph.edges.len())
+        })
+        .collect();
+    assert_eq!(shapes, vec![(1, 1, 4), (1, 1, 2), (3, 3, 8)]);
+
+    // Hexpr arguments print back in brackets
+    let op: Operation = "twice<{f [x . x x]}>".parse()?;
+    assert!(matches!(op.positional().next(), Some(Value::Hexpr(_))));
+    assert_eq!(op.to_string(), "twice<{f [x . x x]}>");
+    Ok(())
+}
+
+#[test]
+fn test_template_errors() -> anyhow::Result<()> {
+    let signature = PolyCirc;
+    let program: Program = "(def (loop n) loop<n+1>) loop<0>".parse()?;
+    assert!(matches!(
+        try_interpret_program(&signature, &program),
+        Err(interpret::Error::Expand(
+            template::ExpandError::RecursionLimit { limit: 128, .. }
+        ))
+    ));
+    assert!(matches!(
+        template::expand_with_limit(&program, 3),
+        Err(template::ExpandError::RecursionLimit { op, .. }) if op.to_string() == "loop<3>"
+    ));
+
+    let program: Program = "(def (negs 1) neg) negs<2>".parse()?;
+    match try_interpret_program(&signature, &program) {
+        Err(e @ interpret::Error::Expand(template::ExpandError::NoMatchingDefinition(_))) => {
+            assert_eq!(e.span().start, 19)
+        }
+        other => panic!("expected expansion error, got {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_power() -> anyhow::Result<()> {
+    let signature = PolyCirc;
+    let shape = |source: &str| -> anyhow::Result<(usize, usize, usize)> {
+        let result = unify(try_interpret(&signature, &source.parse()?)?)?;
+        Ok((
+            result.sources.len(),
+            result.targets.len(),
+            result.hypergraph.edges.len(),
+        ))
+    };
+    assert_eq!(shape("{neg * 8}")?, (8, 8, 8));
+    assert_eq!(shape("(neg ^ 4)")?, (1, 1, 4));
+    assert_eq!(shape("({add * 2} add)")?, (4, 1, 3));
+
+    // Each copy has its own variables: without freshening, both copies would share `x`
+    let hexpr = "{([x . x x] add) * 3}".parse()?;
+    let result = try_interpret_with_names(&signature, &hexpr)?.unify()?;
+    assert_eq!(result.graph.sources.len(), 3);
+    assert_eq!(result.graph.targets.len(), 3);
+    let add_inputs: Vec<_> = result
+        .graph
+        .hypergraph
+        .adjacency
+        .iter()
+        .map(|edge| edge.sources[0])
+        .collect();
+    assert_eq!(add_inputs.len(), 3);
+    assert!(add_inputs
+        .iter()
+        .all(|node| add_inputs.iter().filter(|n| *n == node).count() == 1));
+
+    // but a composition power can still thread wires through
+    assert_eq!(shape("(([x . x x] add neg) ^ 2)")?, (1, 1, 4));
+    Ok(())
+}
+
+#[test]
+fn test_builtins() -> anyhow::Result<()> {
+    let signature = PolyCirc;
+    // Builtins have exactly the structure of the equivalent bracket
+    for (builtin, bracket) in [
+        ("swap", "[x y . y x]"),
+        ("id<2>", "[x y]"),
+        ("perm<2 0 1>", "[x y z . z x y]"),
+        ("copy<3>", "[x . x x x]"),
+        ("drop<2>", "[x y .]"),
+        ("copy<0>", "[x .]"),
+    ] {
+        assert_eq!(
+            try_interpret(&signature, &builtin.parse()?)?,
+            try_interpret(&signature, &bracket.parse()?)?,
+            "{}",
+            builtin
+        );
+    }
+
+    let hexpr = "({neg add drop<1>} swap {neg neg} add copy<2> {neg id<1>} add)".parse()?;
+    let result = try_interpret(&signature, &hexpr)?;
+    assert_eq!(result.sources.len(), 4);
+    assert_eq!(result.targets.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_builtin_errors() -> anyhow::Result<()> {
+    let signature = PolyCirc;
+    for (source, message) in [
+        ("perm<0 0>", "`0` appears twice"),
+        ("perm<1 2>", "expected a permutation of 0 to 1"),
+        ("copy<x>", "expected a number of wires, got `x`"),
+        ("id<1 2>", "expected `id<n>`"),
+    ] {
+        match try_interpret(&signature, &source.parse()?) {
+            Err(interpret::Error::Builtin(_, e)) => assert_eq!(e, message),
+            other => panic!("expected builtin error for {}, got {:?}", source, other),
+        }
+    }
+
+    // Without parameters, these are ordinary operations
+    assert!(matches!(
+        try_interpret(&signature, &"copy".parse()?),
+        Err(interpret::Error::Signature(..))
+    ));
+    // and definitions take precedence
+    let program: Program = "(def swap neg) swap".parse()?;
+    let results = try_interpret_program(&signature, &program)?;
+    assert_eq!(results[0].graph.hypergraph.edges, vec![ArithOp::Neg]);
+    Ok(())
+}
+
+#[test]
+fn test_deep_nesting() -> anyhow::Result<()> {
+    // Alternate compositions, tensors, scopes and powers, each wrapping a single `neg`
+    let depth = 100_000;
+    let nested = |inner: &str| {
+        let mut source = String::new();
+        for i in 0..depth {
+            source.push_str(["(neg ", "{", "(let [x] ", "("][i % 4]);
+        }
+        source.push_str(inner);
+        for i in (0..depth).rev() {
+            source.push_str([")", "}", ")", " ^ 1)"][i % 4]);
+        }
+        source
+    };
+    let source = nested("[x]");
+    let program: Program = source.parse()?;
+    let results = try_interpret_program(&PolyCirc, &program)?;
+    assert_eq!(results[0].graph.hypergraph.edges.len(), depth / 4);
+    assert_eq!(results[0].graph.sources.len(), 1);
+    assert_eq!(results[0].graph.targets.len(), 1);
+    program.drop_iteratively();
+
+    // Nor does expanding templates, in deep hexprs or deep template bodies
+    let program: Program = format!(
+        "(def (twice f) (f f)) (def (wrap f) {}) {} wrap<neg>",
+        nested("f"),
+        nested("twice<neg>"),
+    )
+    .parse()?;
+    let results = try_interpret_program(&PolyCirc, &program)?;
+    assert_eq!(results[0].graph.hypergraph.edges.len(), depth / 4 + 2);
+    assert_eq!(results[1].graph.hypergraph.edges.len(), depth / 4 + 1);
+    program.drop_iteratively();
+    Ok(())
+}
+
+#[test]
+fn test_interpret_limits() -> anyhow::Result<()> {
+    use hexpr::limit::{Limit, LimitExceeded};
+
+    let options = InterpretOptions {
+        max_operations: 10,
+        max_nodes: 20,
+        max_edges: 5,
+    };
+    let interpret = |source: &str| -> anyhow::Result<_> {
+        Ok(try_interpret_with_options(
+            &PolyCirc,
+            &source.parse()?,
+            &options,
+        ))
+    };
+    assert!(interpret("(neg ^ 5)")?.is_ok());
+
+    for (source, limit, start) in [
+        // A short hexpr can describe a huge graph
+        ("{neg * 1000000000}", Limit::Edges, 1),
+        ("{[x y z . x y z] * 1000000000}", Limit::Nodes, 1),
+        ("{[] * 1000000000}", Limit::Operations, 1),
+        ("(neg id<1000000000>)", Limit::Nodes, 5),
+    ] {
+        match interpret(source)? {
+            Err(interpret::Error::Limit {
+                exceeded: LimitExceeded { limit: l, .. },
+                span,
+            }) => assert_eq!((l, span.start), (limit, start), "{}", source),
+            other => panic!(
+                "expected {:?} to be exceeded by {}, got {:?}",
+                limit, source, other
+            ),
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_interpret_program_limits() -> anyhow::Result<()> {
+    use hexpr::limit::{Limit, LimitExceeded};
+
+    let options = InterpretOptions {
+        max_operations: 1000,
+        max_nodes: 1000,
+        max_edges: 100,
+    };
+    // Each definition doubles the last, so the program's size is exponential in its length
+    let mut source = "(def f0 neg)".to_string();
+    for i in 1..64 {
+        source.push_str(&format!(" (def f{} {{f{} f{}}})", i, i - 1, i - 1));
+    }
+    source.push_str(" (f63)");
+    let program: Program = source.parse()?;
+    match try_interpret_program_with_options(&PolyCirc, &program, &options) {
+        Err(interpret::Error::Limit {
+            exceeded:
+                LimitExceeded {
+                    limit: Limit::Edges,
+                    ..
+                },
+            span,
+        }) => assert_eq!(&source[span.start..span.end], "f6"),
+        other => panic!("expected the edge limit to be exceeded, got {:?}", other),
+    }
+
+    // The same limits apply to top-level hexprs
+    let program: Program = "(def f {neg neg}) {f f} {neg * 101}".parse()?;
+    assert!(matches!(
+        try_interpret_program_with_options(&PolyCirc, &program, &options),
+        Err(interpret::Error::Limit { .. })
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_template_expansion_limits() -> anyhow::Result<()> {
+    use hexpr::limit::{Limit, LimitExceeded};
+
+    let options = InterpretOptions {
+        max_operations: 1000,
+        max_nodes: 1000,
+        max_edges: 1000,
+    };
+    // Each use of the template doubles, so t<40> expands to 2^40 operations
+    let source = "(def (t 0) neg) (def (t n) {t<n-1> t<n-1>}) t<40>";
+    let program: Program = source.parse()?;
+    match try_interpret_program_with_options(&PolyCirc, &program, &options) {
+        Err(interpret::Error::Expand(template::ExpandError::Limit {
+            exceeded:
+                LimitExceeded {
+                    limit: Limit::Operations,
+                    ..
+                },
+            span,
+        })) => assert_eq!(&source[span.start..span.end], "t<40>"),
+        other => panic!(
+            "expected the operation limit to be exceeded, got {:?}",
+            other
+        ),
+    }
+    Ok(())
+}